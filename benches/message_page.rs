@@ -0,0 +1,203 @@
+//NOTE: compares the batched page loader against the loader it replaced,
+//run it with `BENCH_CHANNEL_ID=<id> BENCH_MEMBER_ID=<id> cargo bench --features ssr`
+use std::time::{Duration, Instant};
+
+use dotenvy::dotenv;
+use sqlx::mysql::MySqlPoolOptions;
+use sqlx::MySqlPool;
+use start_axum::entities::member::Member;
+use start_axum::entities::message::{
+    ChannelMessage, MessagePage, Reaction, SqlChannelMessage, MESSAGE_PAGE_SIZE,
+};
+use uuid::Uuid;
+
+const DEFAULT_ITERATIONS: u32 = 20;
+
+fn env_uuid(name: &str) -> Result<Uuid, String> {
+    let id = std::env::var(name).map_err(|_| format!("{name} isn't set"))?;
+    Uuid::parse_str(&id).map_err(|err| format!("{name} isn't a valid uuid: {err}"))
+}
+
+async fn batched(channel_id: Uuid, member_id: Uuid, pool: &MySqlPool) -> usize {
+    ChannelMessage::get_channel_messages(channel_id, member_id, MessagePage::latest(), pool)
+        .await
+        .expect("load the page")
+        .len()
+}
+
+//NOTE: the loader before the relations were batched, copied from the tree it replaced,
+//every relation of every message is a round trip
+async fn per_message(channel_id: Uuid, member_id: Uuid, pool: &MySqlPool) -> usize {
+    let mut messages: Vec<SqlChannelMessage> = sqlx::query_as(
+        r#"
+        SELECT
+            id,
+            channel_id,
+            thread_id,
+            sender_id,
+            message_reference,
+            content,
+            timestamp,
+            edited_timestamp,
+            pinned,
+            mention_everyone,
+            reference_deleted
+        FROM
+            channel_messages
+        WHERE channel_id = ?
+        ORDER BY timestamp DESC, id DESC LIMIT ?"#,
+    )
+    .bind(channel_id)
+    .bind(MESSAGE_PAGE_SIZE)
+    .fetch_all(pool)
+    .await
+    .expect("load the page");
+    messages.reverse();
+
+    let mut full_messages = vec![];
+    for message in messages {
+        let sender: Member =
+            sqlx::query_as("SELECT * FROM members_with_profile_fallback WHERE id = ?")
+                .bind(message.sender_id)
+                .fetch_one(pool)
+                .await
+                .expect("load the sender");
+        let message_reference = match message.message_reference {
+            Some(reference) => Some(Box::new(
+                ChannelMessage::get_message_reference(reference, pool)
+                    .await
+                    .expect("load the reference"),
+            )),
+            None => None,
+        };
+        let mentions = ChannelMessage::get_message_mentions(message.id, pool)
+            .await
+            .expect("load the mentions");
+        let mentions_roles = ChannelMessage::get_message_role_mentions(message.id, pool)
+            .await
+            .expect("load the role mentions");
+        let attachments = ChannelMessage::get_message_attachments(message.id, pool)
+            .await
+            .expect("load the attachments");
+        let embeds = ChannelMessage::get_message_embeds(message.id, pool)
+            .await
+            .expect("load the embeds");
+        let reactions = per_message_reactions(message.id, member_id, pool).await;
+        full_messages.push(ChannelMessage {
+            id: message.id,
+            channel_id: message.channel_id,
+            thread_id: message.thread_id,
+            sender,
+            message_reference,
+            content: message.content,
+            timestamp: message.timestamp,
+            edited_timestamp: message.edited_timestamp,
+            pinned: message.pinned,
+            mention_everyone: message.mention_everyone,
+            reference_deleted: message.reference_deleted,
+            mentions,
+            mentions_roles,
+            attachments,
+            embeds,
+            reactions,
+        });
+    }
+    full_messages.len()
+}
+
+async fn per_message_reactions(
+    message_id: Uuid,
+    member_id: Uuid,
+    pool: &MySqlPool,
+) -> Vec<Reaction> {
+    let reactions: Vec<(Uuid, Uuid, Vec<u8>, u32)> =
+        sqlx::query_as("SELECT id, message_id, name, counter FROM reactions WHERE message_id = ?")
+            .bind(message_id)
+            .fetch_all(pool)
+            .await
+            .expect("load the reactions");
+    let mut full_reactions = vec![];
+    for (id, message_id, name, counter) in reactions {
+        let me = ChannelMessage::check_member_in_reaction(member_id, id, pool)
+            .await
+            .expect("load the reaction member");
+        full_reactions.push(Reaction {
+            id,
+            message_id,
+            name: String::from_utf8(name).expect("valid reaction name"),
+            counter,
+            me,
+        });
+    }
+    full_reactions
+}
+
+fn report(name: &str, timings: &[Duration]) {
+    let total: Duration = timings.iter().sum();
+    let average = total / timings.len() as u32;
+    let min = timings.iter().min().copied().unwrap_or_default();
+    let max = timings.iter().max().copied().unwrap_or_default();
+    println!("{name:<12} avg {average:>10.2?}  min {min:>10.2?}  max {max:>10.2?}");
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    //NOTE: the bench needs a database with data, it's skipped when it isn't configured
+    let (database_url, channel_id, member_id) = match (
+        std::env::var("DATABASE_URL").map_err(|_| "DATABASE_URL isn't set".to_string()),
+        env_uuid("BENCH_CHANNEL_ID"),
+        env_uuid("BENCH_MEMBER_ID"),
+    ) {
+        (Ok(database_url), Ok(channel_id), Ok(member_id)) => (database_url, channel_id, member_id),
+        (database_url, channel_id, member_id) => {
+            for err in [database_url.err(), channel_id.err(), member_id.err()]
+                .into_iter()
+                .flatten()
+            {
+                println!("skipping the message page bench: {err}");
+            }
+            return;
+        }
+    };
+    let iterations = std::env::var("BENCH_ITERATIONS")
+        .ok()
+        .and_then(|iterations| iterations.parse::<u32>().ok())
+        .filter(|iterations| *iterations > 0)
+        .unwrap_or(DEFAULT_ITERATIONS);
+
+    let pool = MySqlPoolOptions::new()
+        .max_connections(5)
+        .connect(&database_url)
+        .await
+        .expect("connect to the database");
+
+    let batched_len = batched(channel_id, member_id, &pool).await;
+    let per_message_len = per_message(channel_id, member_id, &pool).await;
+    assert_eq!(
+        batched_len, per_message_len,
+        "both loaders should load the same page"
+    );
+    println!("loading a page of {batched_len} messages, {iterations} iterations");
+
+    let mut batched_timings = vec![];
+    let mut per_message_timings = vec![];
+    for _ in 0..iterations {
+        let start = Instant::now();
+        batched(channel_id, member_id, &pool).await;
+        batched_timings.push(start.elapsed());
+
+        let start = Instant::now();
+        per_message(channel_id, member_id, &pool).await;
+        per_message_timings.push(start.elapsed());
+    }
+
+    report("batched", &batched_timings);
+    report("per message", &per_message_timings);
+    let batched: Duration = batched_timings.iter().sum();
+    let per_message: Duration = per_message_timings.iter().sum();
+    println!(
+        "speedup      {:.1}x",
+        per_message.as_secs_f64() / batched.as_secs_f64()
+    );
+}
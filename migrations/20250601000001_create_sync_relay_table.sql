@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS sync_relay (
+  id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  node_id binary(16) NOT NULL,
+  request JSON NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX (created_at)
+);
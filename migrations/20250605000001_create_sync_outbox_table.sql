@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS sync_outbox (
+  id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  request JSON NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  sent_at TIMESTAMP NULL DEFAULT NULL,
+  INDEX (sent_at, id)
+);
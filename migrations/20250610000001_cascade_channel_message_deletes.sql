@@ -0,0 +1,25 @@
+ALTER TABLE channel_messages ADD reference_deleted BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE attachments ADD file_key VARCHAR(100) NULL;
+
+ALTER TABLE messages_mentions DROP FOREIGN KEY messages_mentions_ibfk_1;
+ALTER TABLE messages_mentions ADD FOREIGN KEY (message_id) REFERENCES channel_messages (id) ON DELETE CASCADE;
+
+ALTER TABLE messages_role_mentions DROP FOREIGN KEY messages_role_mentions_ibfk_1;
+ALTER TABLE messages_role_mentions ADD FOREIGN KEY (message_id) REFERENCES channel_messages (id) ON DELETE CASCADE;
+
+ALTER TABLE channel_messages_attachments DROP FOREIGN KEY channel_messages_attachments_ibfk_1;
+ALTER TABLE channel_messages_attachments ADD FOREIGN KEY (message_id) REFERENCES channel_messages (id) ON DELETE CASCADE;
+ALTER TABLE channel_messages_attachments DROP FOREIGN KEY channel_messages_attachments_ibfk_2;
+ALTER TABLE channel_messages_attachments ADD FOREIGN KEY (attachment_id) REFERENCES attachments (id) ON DELETE CASCADE;
+
+ALTER TABLE channel_messages_embeds DROP FOREIGN KEY channel_messages_embeds_ibfk_1;
+ALTER TABLE channel_messages_embeds ADD FOREIGN KEY (message_id) REFERENCES channel_messages (id) ON DELETE CASCADE;
+ALTER TABLE channel_messages_embeds DROP FOREIGN KEY channel_messages_embeds_ibfk_2;
+ALTER TABLE channel_messages_embeds ADD FOREIGN KEY (embeds_id) REFERENCES embeds (id) ON DELETE CASCADE;
+
+ALTER TABLE reactions DROP FOREIGN KEY reactions_ibfk_1;
+ALTER TABLE reactions ADD FOREIGN KEY (message_id) REFERENCES channel_messages (id) ON DELETE CASCADE;
+
+ALTER TABLE reaction_members DROP FOREIGN KEY reaction_members_ibfk_1;
+ALTER TABLE reaction_members ADD FOREIGN KEY (reaction_id) REFERENCES reactions (id) ON DELETE CASCADE;
@@ -0,0 +1,2 @@
+CREATE INDEX channel_messages_channel_history ON channel_messages (channel_id, timestamp, id);
+CREATE INDEX channel_messages_thread_history ON channel_messages (thread_id, timestamp, id);
@@ -0,0 +1 @@
+ALTER TABLE channel_messages ADD FULLTEXT INDEX channel_messages_content (content);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS sync_relay_nodes (
+  node_id binary(16) NOT NULL PRIMARY KEY,
+  position BIGINT UNSIGNED NOT NULL,
+  seen_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX (seen_at)
+);
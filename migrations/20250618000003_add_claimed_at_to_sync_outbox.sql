@@ -0,0 +1 @@
+ALTER TABLE sync_outbox ADD COLUMN claimed_at TIMESTAMP NULL DEFAULT NULL;
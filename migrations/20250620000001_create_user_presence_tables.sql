@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS presence_nodes (
+  node_id binary(16) NOT NULL PRIMARY KEY,
+  seen_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  INDEX (seen_at)
+);
+
+CREATE TABLE IF NOT EXISTS user_presence (
+  user_id binary(16) NOT NULL,
+  node_id binary(16) NOT NULL,
+  connections INT UNSIGNED NOT NULL,
+  PRIMARY KEY (user_id, node_id),
+  INDEX (node_id)
+);
@@ -1,14 +1,14 @@
 use crate::app::stores::CategoryStoreSync;
 use crate::entities::category::Category;
-use crate::sync::{SubscriptionMode, SyncRequest};
+use crate::sync::keys;
+use crate::sync::SyncRequest;
 use cfg_if::cfg_if;
 use leptos::prelude::*;
-use serde_json::json;
 use uuid::Uuid;
 
 cfg_if! {
     if #[cfg(feature = "ssr")] {
-        use super::sync;
+        use super::outbox;
         use crate::entities::server::Server;
         use crate::entities::channel::Channel;
         use super::user_can_edit;
@@ -41,22 +41,10 @@ pub fn use_category() -> CategoryContext {
 
 #[server(GetCategories)]
 pub async fn get_categories(server_id: Uuid) -> Result<Vec<Category>, ServerFnError> {
-    let user = auth_user()?;
+    auth_user()?;
     let pool = pool()?;
 
-    let categories = Server::get_server_categories(server_id, &pool).await?;
-
-    let sender = sync()?;
-
-    sender
-        .broadcast(SyncRequest::Subscription {
-            keys: vec![format!("categoriesStore:server:{server_id}")],
-            client: user.id,
-            action: SubscriptionMode::ReplacePrefix("categoriesStore:server:".into()),
-        })
-        .await;
-
-    Ok(categories)
+    Ok(Server::get_server_categories(server_id, &pool).await?)
 }
 
 #[server(CreateCategory)]
@@ -68,21 +56,21 @@ pub async fn create_category(server_id: Uuid, name: String) -> Result<Uuid, Serv
         if name.len() <= 1 {
             return Err(ServerFnError::new("min len is 1"));
         };
-        let category_id = Category::create(&name, server_id, &pool).await?;
-        let sync = sync()?;
+        let mut tx = outbox()?.begin().await?;
+        let category_id = Category::create(&name, server_id, &mut *tx).await?;
         let new_category = Category {
             id: category_id,
             name,
             server_id,
         };
-        let _ = sync
-            .broadcast(SyncRequest::Mutation {
-                key: format!("categoriesStore:server:{server_id}"),
-                data: json!(CategoryStoreSync::Created {
-                    category: new_category
-                }),
-            })
-            .await;
+        tx.stage(SyncRequest::mutation(
+            keys::server_categories(server_id),
+            &CategoryStoreSync::Created {
+                category: new_category,
+            },
+        ))
+        .await?;
+        tx.commit().await?;
         return Ok(category_id);
     }
     Err(ServerFnError::new("You can't create the category"))
@@ -101,13 +89,14 @@ pub async fn rename_category(
         if new_name.len() <= 1 {
             return Err(ServerFnError::new("min len is 1"));
         }
-        Category::rename(&new_name, category_id, server_id, &pool).await?;
-        sync()?
-            .broadcast(SyncRequest::Mutation {
-                key: format!("categoriesStore:server:{server_id}"),
-                data: json!(CategoryStoreSync::Updated { id: category_id }),
-            })
-            .await;
+        let mut tx = outbox()?.begin().await?;
+        Category::rename(&new_name, category_id, server_id, &mut *tx).await?;
+        tx.stage(SyncRequest::mutation(
+            keys::server_categories(server_id),
+            &CategoryStoreSync::Updated { id: category_id },
+        ))
+        .await?;
+        tx.commit().await?;
         return Ok(());
     };
 
@@ -120,15 +109,15 @@ pub async fn delete_category(server_id: Uuid, category_id: Uuid) -> Result<(), S
     let user = auth_user()?;
 
     if user_can_edit(server_id, user.id, &pool).await? {
-        Channel::remove_all_from_category(server_id, category_id, &pool).await?;
-        Category::delete(category_id, server_id, &pool).await?;
-        let sync = sync()?;
-        let _ = sync
-            .broadcast(SyncRequest::Mutation {
-                key: format!("categoriesStore:server:{server_id}"),
-                data: json!(CategoryStoreSync::Deleted { id: category_id }),
-            })
-            .await;
+        let mut tx = outbox()?.begin().await?;
+        Channel::remove_all_from_category(server_id, category_id, &mut *tx).await?;
+        Category::delete(category_id, server_id, &mut *tx).await?;
+        tx.stage(SyncRequest::mutation(
+            keys::server_categories(server_id),
+            &CategoryStoreSync::Deleted { id: category_id },
+        ))
+        .await?;
+        tx.commit().await?;
         return Ok(());
     };
     Err(ServerFnError::new("You can't delete the category"))
@@ -1,16 +1,15 @@
 use crate::app::stores::ChannelStoreSync;
 use crate::entities::channel::Channel;
 use crate::entities::channel::ChannelType;
-use crate::sync::SubscriptionMode;
+use crate::sync::keys::{self, SyncKey};
 use crate::sync::SyncRequest;
 use cfg_if::cfg_if;
 use leptos::prelude::*;
-use serde_json::json;
 use uuid::Uuid;
 
 cfg_if! {
     if #[cfg(feature = "ssr")] {
-        use super::sync;
+        use super::outbox;
         use crate::entities::server::Server;
         use super::user_can_edit;
         use super::auth_user;
@@ -58,20 +57,21 @@ pub async fn update_channel(
     let user = auth_user()?;
     let pool = pool()?;
     if user_can_edit(server_id, user.id, &pool).await? {
+        let mut tx = outbox()?.begin().await?;
         if let Some(ref name) = name {
-            Channel::rename(name, channel_id, &pool).await?;
+            Channel::rename(name, channel_id, &mut *tx).await?;
         };
 
         if let Some(ref topic) = topic {
-            Channel::update_topic(channel_id, topic, &pool).await?;
+            Channel::update_topic(channel_id, topic, &mut *tx).await?;
         }
 
-        let _ = sync()?
-            .broadcast(SyncRequest::Mutation {
-                key: format!("channelStore:channel:{channel_id}"),
-                data: json!(ChannelStoreSync::Updated { id: channel_id }),
-            })
-            .await;
+        tx.stage(SyncRequest::mutation(
+            keys::channel_store(channel_id),
+            &ChannelStoreSync::Updated { id: channel_id },
+        ))
+        .await?;
+        tx.commit().await?;
         Ok(())
     } else {
         Err(ServerFnError::new("You cant updatge this"))
@@ -80,32 +80,10 @@ pub async fn update_channel(
 
 #[server(GetAllChannels)]
 pub async fn get_channels(server_id: Uuid) -> Result<Vec<Channel>, ServerFnError> {
-    let user = auth_user()?;
+    auth_user()?;
     let pool = pool()?;
 
-    let channels = Server::get_channels(server_id, &pool).await?;
-
-    let sender = sync()?;
-
-    sender
-        .broadcast(SyncRequest::Subscription {
-            keys: vec![format!("channelStore:server:{server_id}")],
-            client: user.id,
-            action: SubscriptionMode::ReplacePrefix("channelsStore:server:".into()),
-        })
-        .await;
-    sender
-        .broadcast(SyncRequest::Subscription {
-            keys: channels
-                .iter()
-                .map(|channel| format!("channelStore:channel:{}", channel.id))
-                .collect(),
-            client: user.id,
-            action: SubscriptionMode::ReplacePrefix("channelsStore:channel:".into()),
-        })
-        .await;
-
-    Ok(channels)
+    Ok(Server::get_channels(server_id, &pool).await?)
 }
 
 #[server(CreateChannel)]
@@ -123,28 +101,29 @@ pub async fn create_channel(
             return Err(ServerFnError::new("the name have a min len of 1 char"));
         }
 
+        let mut tx = outbox()?.begin().await?;
         let channel_id = if let Some(category_id) = category_id {
-            Channel::create_with_category(&name, channel_type, server_id, category_id, &pool)
+            Channel::create_with_category(&name, channel_type, server_id, category_id, &mut *tx)
                 .await?
         } else {
-            Channel::create(&name, channel_type, server_id, &pool).await?
+            Channel::create(&name, channel_type, server_id, &mut *tx).await?
         };
 
-        sync()?
-            .broadcast(SyncRequest::Mutation {
-                key: format!("channelStore:server:{server_id}"),
-                data: json!(ChannelStoreSync::Created {
-                    channel: Channel {
-                        id: channel_id,
-                        name,
-                        channel_type,
-                        server_id,
-                        category_id,
-                        topic: None,
-                    }
-                }),
-            })
-            .await;
+        tx.stage(SyncRequest::mutation(
+            keys::server_channels(server_id),
+            &ChannelStoreSync::Created {
+                channel: Channel {
+                    id: channel_id,
+                    name,
+                    channel_type,
+                    server_id,
+                    category_id,
+                    topic: None,
+                },
+            },
+        ))
+        .await?;
+        tx.commit().await?;
 
         return Ok(channel_id);
     }
@@ -157,13 +136,21 @@ pub async fn delete_channel(server_id: Uuid, channel_id: Uuid) -> Result<(), Ser
     let user = auth_user()?;
 
     if user_can_edit(server_id, user.id, &pool).await? {
-        Channel::delete(channel_id, server_id, &pool).await?;
-        sync()?
-            .broadcast(SyncRequest::Mutation {
-                key: format!("channelStore:server:{server_id}"),
-                data: json!(ChannelStoreSync::Deleted { id: channel_id }),
-            })
-            .await;
+        let mut tx = outbox()?.begin().await?;
+        Channel::delete(channel_id, server_id, &mut *tx).await?;
+        tx.stage(SyncRequest::mutation(
+            keys::server_channels(server_id),
+            &ChannelStoreSync::Deleted { id: channel_id },
+        ))
+        .await?;
+        tx.stage(SyncRequest::ReauthorizeKeys {
+            keys: vec![
+                SyncKey::Channel(channel_id),
+                SyncKey::ChannelStore(channel_id),
+            ],
+        })
+        .await?;
+        tx.commit().await?;
         return Ok(());
     }
 
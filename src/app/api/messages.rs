@@ -4,7 +4,6 @@ use cfg_if::cfg_if;
 use leptos::prelude::*;
 use log::debug;
 use regex::Regex;
-use serde_json::json;
 use server_fn::codec::{MultipartData, MultipartFormData};
 use server_fn::ServerFnError;
 use uuid::Uuid;
@@ -12,33 +11,77 @@ use uuid::Uuid;
 use crate::app::components::uploadthings::{FileType, UploadthingFile};
 use crate::app::stores::{MessageStoreSync, MessageSync};
 use crate::entities::member::Member;
-use crate::entities::message::ChannelMessage;
+use crate::entities::message::{ChannelMessage, MessageWindow};
 use crate::entities::role::Role;
+use crate::entities::search::SearchPage;
+use crate::sync::keys;
 use crate::sync::SyncRequest;
 
 cfg_if! {
     if #[cfg(feature = "ssr")] {
         use reqwest::Url;
-        use crate::uploadthing::{FileData, UploadThing};
+        use crate::uploadthing::{FileData, FileDeletions, UploadThing};
         use crate::open_graph::fetch_op_data;
         use multer::bytes::Bytes as MulterBytes;
         use futures::TryStreamExt;
         use super::{auth_user, user_can_edit};
         use super::auth;
-        use super::sync;
+        use super::outbox;
         use super::pool;
+        use chrono::Utc;
+        use sqlx::{MySqlConnection, MySqlPool};
+        use crate::entities::message::Embed;
+        use crate::sync::outbox::SyncOutbox;
+        use crate::entities::channel::Channel;
+        use crate::entities::thread::Thread;
+        use crate::entities::message::MessagePage;
+        use crate::entities::search::MessageSearch;
+        use crate::sync::keys::{kind, Payload};
     }
 }
 
+//NOTE: the history is read as the member of the user, it's looked up once the access is checked
+#[cfg(feature = "ssr")]
+async fn channel_member(
+    channel_id: Uuid,
+    user_id: Uuid,
+    pool: &MySqlPool,
+) -> Result<Member, ServerFnError> {
+    if !Channel::user_has_access(channel_id, user_id, pool).await? {
+        return Err(ServerFnError::new("You can't read this channel"));
+    }
+    Ok(Member::get_user_member_on_channel(user_id, channel_id, pool).await?)
+}
+
+#[cfg(feature = "ssr")]
+async fn thread_member(
+    thread_id: Uuid,
+    user_id: Uuid,
+    pool: &MySqlPool,
+) -> Result<Member, ServerFnError> {
+    if !Thread::user_has_access(thread_id, user_id, pool).await? {
+        return Err(ServerFnError::new("You can't read this thread"));
+    }
+    Ok(Member::get_user_member_on_thread(user_id, thread_id, pool).await?)
+}
+
 #[server(GetMessages)]
 pub async fn get_messages(
     channel_id: Uuid,
-    member_id: Uuid,
+    before: Option<Uuid>,
+    after: Option<Uuid>,
+    limit: Option<u32>,
 ) -> Result<Vec<ChannelMessage>, ServerFnError> {
     let pool = pool()?;
-    auth()?;
+    let user = auth_user()?;
 
-    Ok(ChannelMessage::get_channel_messages(channel_id, member_id, &pool).await?)
+    let member = channel_member(channel_id, user.id, &pool).await?;
+    let page = MessagePage {
+        before,
+        after,
+        limit,
+    };
+    Ok(ChannelMessage::get_channel_messages(channel_id, member.id, page, &pool).await?)
 }
 
 #[server(GetPinnedMessages)]
@@ -52,12 +95,63 @@ pub async fn get_pinned_messages(channel_id: Uuid) -> Result<Vec<ChannelMessage>
 #[server(GetThreadMessages)]
 pub async fn get_thread_messages(
     thread_id: Uuid,
-    member_id: Uuid,
+    before: Option<Uuid>,
+    after: Option<Uuid>,
+    limit: Option<u32>,
 ) -> Result<Vec<ChannelMessage>, ServerFnError> {
     let pool = pool()?;
-    auth()?;
+    let user = auth_user()?;
+
+    let member = thread_member(thread_id, user.id, &pool).await?;
+    let page = MessagePage {
+        before,
+        after,
+        limit,
+    };
+    Ok(ChannelMessage::get_thread_messages(thread_id, member.id, page, &pool).await?)
+}
+
+#[server(GetMessageWindow)]
+pub async fn get_message_window(
+    channel_id: Uuid,
+    thread_id: Option<Uuid>,
+    message_id: Uuid,
+    limit: Option<u32>,
+) -> Result<MessageWindow, ServerFnError> {
+    let pool = pool()?;
+    let user = auth_user()?;
+
+    Ok(match thread_id {
+        Some(thread_id) => {
+            let member = thread_member(thread_id, user.id, &pool).await?;
+            ChannelMessage::get_thread_window(thread_id, member.id, message_id, limit, &pool)
+                .await?
+        }
+        None => {
+            let member = channel_member(channel_id, user.id, &pool).await?;
+            ChannelMessage::get_channel_window(channel_id, member.id, message_id, limit, &pool)
+                .await?
+        }
+    })
+}
+
+#[server(SearchMessages)]
+pub async fn search_messages(
+    server_id: Uuid,
+    query: String,
+    offset: Option<u32>,
+) -> Result<SearchPage, ServerFnError> {
+    let pool = pool()?;
+    let user = auth_user()?;
 
-    Ok(ChannelMessage::get_thread_messages(thread_id, member_id, &pool).await?)
+    let member = Member::get_from_user_on_server(user.id, server_id, &pool).await?;
+    let search = MessageSearch::parse(&query);
+    if search.is_empty() {
+        return Ok(SearchPage::default());
+    }
+    Ok(search
+        .search(server_id, member.id, offset.unwrap_or_default(), &pool)
+        .await?)
 }
 
 #[server(UpdatePinned)]
@@ -70,18 +164,21 @@ pub async fn update_pinned(
     let pool = pool()?;
     let user = auth_user()?;
     if user_can_edit(server_id, user.id, &pool).await? {
-        ChannelMessage::pin(message_id, pinned, &pool).await?;
-        // let _ = sync()?
-        //     .broadcast(SyncRequest::Mutation(MutationRequest {
-        //         key: format!("channel:{channel_id}"),
-        //         module: "ChannelStore".into(),
-        //         data: if pinned {
-        //             json!(MessageSync::Pin { id: message_id })
-        //         } else {
-        //             json!(MessageSync::Unpin { id: message_id })
-        //         },
-        //     }))
-        //     .await;
+        let mut tx = outbox()?.begin().await?;
+        ChannelMessage::pin(message_id, pinned, &mut *tx).await?;
+        let thread_id = ChannelMessage::get_message_thread(message_id, &mut *tx).await?;
+        tx.stage(message_mutation(
+            channel_id,
+            thread_id,
+            &if pinned {
+                MessageSync::Pin { id: message_id }
+            } else {
+                MessageSync::Unpin { id: message_id }
+            },
+            None,
+        ))
+        .await?;
+        tx.commit().await?;
     }
     Ok(())
 }
@@ -140,34 +237,58 @@ pub async fn send_message_attachments(data: MultipartData) -> Result<(), ServerF
         message_id.ok_or_else(|| ServerFnError::new("Something go wrong in our servers"))?;
     let channel_id =
         channel_id.ok_or_else(|| ServerFnError::new("Something go wrong in our servers"))?;
-    let pool = pool()?;
-
     let uploadthing = use_context::<UploadThing>().expect("acces to upload thing");
-    let mut attachments = vec![];
+    let mut uploaded = vec![];
 
     for file in files {
         if file.data.size != 0 {
             if let Ok(res) = uploadthing.upload_file(file.chunks, file.data, true).await {
-                attachments.push(
-                    ChannelMessage::add_attachment(message_id, &res.name, &res.url, &pool).await?,
-                );
+                uploaded.push(res);
             }
         }
     }
-    // let _ = sync()?
-    //     .broadcast(SyncRequest::Mutation(MutationRequest {
-    //         key: format!("channel:{channel_id}"),
-    //         module: "ChannelStore".into(),
-    //         data: json!(MessageSync::Attachments {
-    //             id: message_id,
-    //             attachments,
-    //         }),
-    //     }))
-    //     .await;
+    if !uploaded.is_empty() {
+        //NOTE: the uploads stay out of the transaction, it only holds the inserts
+        let mut tx = outbox()?.begin().await?;
+        let mut attachments = vec![];
+        for res in uploaded {
+            attachments.push(
+                ChannelMessage::add_attachment(message_id, &res.name, &res.url, &res.key, &mut *tx)
+                    .await?,
+            );
+        }
+        let thread_id = ChannelMessage::get_message_thread(message_id, &mut *tx).await?;
+        tx.stage(message_mutation(
+            channel_id,
+            thread_id,
+            &MessageSync::Attachments {
+                id: message_id,
+                attachments,
+            },
+            None,
+        ))
+        .await?;
+        tx.commit().await?;
+    }
 
     Ok(())
 }
 
+//NOTE: the mutations of a thread message go out on the thread key,
+//the thread page only subscribes to it
+#[cfg(feature = "ssr")]
+fn message_mutation<P: Payload<kind::Channel> + Payload<kind::Thread>>(
+    channel_id: Uuid,
+    thread_id: Option<Uuid>,
+    payload: &P,
+    mutation_id: Option<Uuid>,
+) -> SyncRequest {
+    match thread_id {
+        Some(thread_id) => SyncRequest::echo(keys::thread(thread_id), payload, mutation_id),
+        None => SyncRequest::echo(keys::channel(channel_id), payload, mutation_id),
+    }
+}
+
 #[cfg(feature = "ssr")]
 #[derive(Debug, PartialEq)]
 enum MessageElement {
@@ -228,89 +349,256 @@ fn process_urls(text: &str, data: &mut Vec<MessageElement>) {
     }
 }
 
-#[server(SendMessage)]
-pub async fn send_message(
-    server_id: Uuid,
-    channel_id: Uuid,
-    message: String,
-    member_id: Uuid,
-    msg_reference: Option<Uuid>,
-) -> Result<Uuid, ServerFnError> {
-    let pool = pool()?;
-    auth()?;
-
-    if message.is_empty() {
-        return Err(ServerFnError::new("The message is empty"));
-    }
-
-    let mut message =
-        ChannelMessage::add_channel_message(channel_id, member_id, &message, msg_reference, &pool)
-            .await?;
+#[cfg(feature = "ssr")]
+#[derive(Debug, Default)]
+struct MessageMentions {
+    mentions: Vec<Member>,
+    mentions_roles: Vec<Role>,
+    mention_everyone: bool,
+    urls: Vec<Url>,
+}
 
-    let elements = extract_message_elements(&message.content);
-    let mut urls = vec![];
+#[cfg(feature = "ssr")]
+async fn add_message_mentions(
+    message_id: Uuid,
+    server_id: Uuid,
+    content: &str,
+    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
+) -> Result<MessageMentions, ServerFnError> {
+    let mut mentions = MessageMentions::default();
 
-    for element in elements {
+    for element in extract_message_elements(content) {
         match element {
             MessageElement::Member(id) => {
-                if let Ok(member) = Member::check_member_on_server(id, server_id, &pool).await {
-                    ChannelMessage::add_mention(message.id, member.id, &pool).await?;
-                    message.mentions.push(member);
+                if let Ok(member) = Member::check_member_on_server(id, server_id, pool).await {
+                    ChannelMessage::add_mention(message_id, member.id, &mut *conn).await?;
+                    mentions.mentions.push(member);
                 }
             }
             MessageElement::Role(id) => {
-                if let Ok(role) = Role::check_role_on_server(id, server_id, &pool).await {
-                    ChannelMessage::add_role_mention(message.id, role.id, &pool).await?;
-                    message.mentions_roles.push(role);
+                if let Ok(role) = Role::check_role_on_server(id, server_id, pool).await {
+                    ChannelMessage::add_role_mention(message_id, role.id, &mut *conn).await?;
+                    mentions.mentions_roles.push(role);
                 }
             }
             MessageElement::Everyone => {
-                ChannelMessage::mention_everyone(message.id, &pool).await?;
-                message.mention_everyone = true;
+                ChannelMessage::mention_everyone(message_id, &mut *conn).await?;
+                mentions.mention_everyone = true;
             }
             MessageElement::Url(url) => {
-                urls.push(url);
+                mentions.urls.push(url);
             }
         }
     }
 
-    let id = message.id;
-    // let _ = sync()?
-    //     .broadcast(crate::sync::SyncRequest::Mutation(MutationRequest {
-    //         key: format!("channe:{channel_id}"),
-    //         module: "MessageStore".into(),
-    //         data: json!(MessageStoreSync::Created {
-    //             message: Box::new(message)
-    //         }),
-    //     }))
-    //     .await;
+    Ok(mentions)
+}
 
-    let mut embeds = vec![];
+//NOTE: the previews are fetched before the transaction, it only holds the inserts,
+//the mutation carries every embed of the message
+#[cfg(feature = "ssr")]
+async fn add_embeds(
+    channel_id: Uuid,
+    thread_id: Option<Uuid>,
+    message_id: Uuid,
+    urls: Vec<Url>,
+    mut embeds: Vec<Embed>,
+    outbox: &SyncOutbox,
+) -> Result<(), ServerFnError> {
+    let mut previews = vec![];
 
     for url in urls {
         if let Ok(op) = fetch_op_data(url.clone()).await {
-            if let Ok(embed) = ChannelMessage::add_embed(id, op, url.to_string(), &pool).await {
-                embeds.push(embed);
-            }
+            previews.push((op, url));
         }
     }
-    // let _ = sync()?
-    //     .broadcast(SyncRequest::Mutation(MutationRequest {
-    //         key: format!("channel:{channel_id}"),
-    //         module: "ChannelStore".into(),
-    //         data: json!(MessageSync::Embeds { id, embeds }),
-    //     }))
-    //     .await;
+    if previews.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = outbox.begin().await?;
+    let mut added = false;
+    for (op, url) in previews {
+        if let Ok(embed) =
+            ChannelMessage::add_embed(message_id, op, url.to_string(), &mut *tx).await
+        {
+            embeds.push(embed);
+            added = true;
+        }
+    }
+    if added {
+        tx.stage(message_mutation(
+            channel_id,
+            thread_id,
+            &MessageSync::Embeds {
+                id: message_id,
+                embeds,
+            },
+            None,
+        ))
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+#[server(SendMessage)]
+pub async fn send_message(
+    server_id: Uuid,
+    channel_id: Uuid,
+    message: String,
+    member_id: Uuid,
+    msg_reference: Option<Uuid>,
+    mutation_id: Option<Uuid>,
+) -> Result<Uuid, ServerFnError> {
+    let pool = pool()?;
+    auth()?;
+
+    if message.is_empty() {
+        return Err(ServerFnError::new("The message is empty"));
+    }
+
+    let outbox = outbox()?;
+    let mut tx = outbox.begin().await?;
+    let mut message = ChannelMessage::add_channel_message(
+        channel_id,
+        member_id,
+        &message,
+        msg_reference,
+        &mut *tx,
+    )
+    .await?;
+
+    let MessageMentions {
+        mentions,
+        mentions_roles,
+        mention_everyone,
+        urls,
+    } = add_message_mentions(message.id, server_id, &message.content, &pool, &mut *tx).await?;
+    message.mentions = mentions;
+    message.mentions_roles = mentions_roles;
+    message.mention_everyone = mention_everyone;
+
+    let id = message.id;
+    let thread_id = message.thread_id;
+    tx.stage(message_mutation(
+        channel_id,
+        thread_id,
+        &MessageStoreSync::Created {
+            message: Box::new(message),
+        },
+        mutation_id,
+    ))
+    .await?;
+    tx.commit().await?;
+
+    add_embeds(channel_id, thread_id, id, urls, vec![], &outbox).await?;
 
     Ok(id)
 }
 
+#[server(EditMessage)]
+pub async fn edit_message(
+    server_id: Uuid,
+    message_id: Uuid,
+    content: String,
+) -> Result<(), ServerFnError> {
+    let pool = pool()?;
+    let user = auth_user()?;
+
+    if content.is_empty() {
+        return Err(ServerFnError::new("The message is empty"));
+    }
+
+    let member = Member::get_user_member(user.id, server_id, &pool).await?;
+    let (sender_id, channel_id) = ChannelMessage::get_sender_and_channel(message_id, &pool).await?;
+    if sender_id != member.id {
+        return Err(ServerFnError::new("You can't edit this message"));
+    }
+
+    let outbox = outbox()?;
+    let mut tx = outbox.begin().await?;
+    let edited_timestamp = Utc::now();
+    let thread_id = ChannelMessage::get_message_thread(message_id, &mut *tx).await?;
+    ChannelMessage::edit(message_id, &content, edited_timestamp, &mut *tx).await?;
+    ChannelMessage::clear_mentions(message_id, &mut *tx).await?;
+    let MessageMentions {
+        mentions,
+        mentions_roles,
+        mention_everyone,
+        mut urls,
+    } = add_message_mentions(message_id, server_id, &content, &pool, &mut *tx).await?;
+
+    //NOTE: the embeds of the urls that are still in the message are kept, only the new urls are fetched
+    let mut embeds = vec![];
+    for embed in ChannelMessage::get_message_embeds(message_id, &mut *tx).await? {
+        if urls.iter().any(|url| url.to_string() == embed.url) {
+            embeds.push(embed);
+        } else {
+            ChannelMessage::remove_embed(message_id, embed.id, &mut *tx).await?;
+        }
+    }
+    urls.retain(|url| !embeds.iter().any(|embed| embed.url == url.to_string()));
+
+    tx.stage(message_mutation(
+        channel_id,
+        thread_id,
+        &MessageSync::Edited {
+            id: message_id,
+            content,
+            edited_timestamp,
+            mentions,
+            mentions_roles,
+            mention_everyone,
+            embeds: embeds.clone(),
+        },
+        None,
+    ))
+    .await?;
+    tx.commit().await?;
+
+    add_embeds(channel_id, thread_id, message_id, urls, embeds, &outbox).await?;
+
+    Ok(())
+}
+
+#[server(DeleteMessage)]
+pub async fn delete_message(server_id: Uuid, message_id: Uuid) -> Result<(), ServerFnError> {
+    let pool = pool()?;
+    let user = auth_user()?;
+
+    let member = Member::get_user_member(user.id, server_id, &pool).await?;
+    let (sender_id, channel_id) = ChannelMessage::get_sender_and_channel(message_id, &pool).await?;
+    Channel::get_channel(channel_id, server_id, &pool).await?;
+    if sender_id != member.id && !user_can_edit(server_id, user.id, &pool).await? {
+        return Err(ServerFnError::new("You can't delete this message"));
+    }
+
+    //NOTE: the files are deleted by the FileDeletions worker once the message is gone
+    let mut tx = outbox()?.begin().await?;
+    let thread_id = ChannelMessage::get_message_thread(message_id, &mut *tx).await?;
+    let file_keys = ChannelMessage::delete(message_id, &mut *tx).await?;
+    FileDeletions::stage(&file_keys, &mut *tx).await?;
+    tx.stage(message_mutation(
+        channel_id,
+        thread_id,
+        &MessageStoreSync::Deleted { id: message_id },
+        None,
+    ))
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
 #[server(React)]
 pub async fn react(
     name: String,
     message_id: Uuid,
     member_id: Uuid,
     channel_id: Uuid,
+    mutation_id: Option<Uuid>,
 ) -> Result<(), ServerFnError> {
     let pool = pool()?;
     auth()?;
@@ -318,47 +606,56 @@ pub async fn react(
     {
         debug!("{reaction:?}");
         if !reaction.me {
-            ChannelMessage::inc_reaction_counter(reaction.id, &pool).await?;
-            ChannelMessage::add_member_to_reaction(reaction.id, member_id, &pool).await?;
-            // let _ = sync()?
-            //     .broadcast(SyncRequest::Mutation(MutationRequest {
-            //         key: format!("channel:{channel_id}"),
-            //         module: "ChannelStore".into(),
-            //         data: json!(MessageSync::MemberReact {
-            //             member: member_id,
-            //             id: message_id,
-            //             reaction: reaction.id
-            //         }),
-            //     }))
-            //     .await;
+            let mut tx = outbox()?.begin().await?;
+            let thread_id = ChannelMessage::get_message_thread(message_id, &mut *tx).await?;
+            let counter = ChannelMessage::inc_reaction_counter(reaction.id, &mut *tx).await?;
+            ChannelMessage::add_member_to_reaction(reaction.id, member_id, &mut *tx).await?;
+            tx.stage(message_mutation(
+                channel_id,
+                thread_id,
+                &MessageSync::MemberReact {
+                    member: member_id,
+                    id: message_id,
+                    reaction: reaction.id,
+                    counter,
+                },
+                mutation_id,
+            ))
+            .await?;
+            tx.commit().await?;
         }
     } else {
-        let mut reaction = ChannelMessage::create_reaction(message_id, &name, &pool).await?;
-        ChannelMessage::inc_reaction_counter(reaction.id, &pool).await?;
-        ChannelMessage::add_member_to_reaction(reaction.id, member_id, &pool).await?;
-        reaction.me = true;
+        let mut tx = outbox()?.begin().await?;
+        let thread_id = ChannelMessage::get_message_thread(message_id, &mut *tx).await?;
+        let reaction = ChannelMessage::create_reaction(message_id, &name, &mut *tx).await?;
+        let counter = ChannelMessage::inc_reaction_counter(reaction.id, &mut *tx).await?;
+        ChannelMessage::add_member_to_reaction(reaction.id, member_id, &mut *tx).await?;
         let reaction_id = reaction.id;
-        // let _ = sync()?
-        //     .broadcast(SyncRequest::Mutation(MutationRequest {
-        //         key: format!("channel:{channel_id}"),
-        //         module: "ChannelStore".into(),
-        //         data: json!(MessageSync::NewReaction {
-        //             id: message_id,
-        //             reaction
-        //         }),
-        //     }))
-        //     .await;
-        // let _ = sync()?
-        //     .broadcast(SyncRequest::Mutation(MutationRequest {
-        //         key: format!("channel:{channel_id}"),
-        //         module: "ChannelStore".into(),
-        //         data: json!(MessageSync::MemberReact {
-        //             member: member_id,
-        //             id: message_id,
-        //             reaction: reaction_id
-        //         }),
-        //     }))
-        //     .await;
+        //NOTE: the reaction goes out with counter 0 and me false,
+        //the MemberReact that follows is the one that sets both for every client
+        tx.stage(message_mutation(
+            channel_id,
+            thread_id,
+            &MessageSync::NewReaction {
+                id: message_id,
+                reaction,
+            },
+            None,
+        ))
+        .await?;
+        tx.stage(message_mutation(
+            channel_id,
+            thread_id,
+            &MessageSync::MemberReact {
+                member: member_id,
+                id: message_id,
+                reaction: reaction_id,
+                counter,
+            },
+            mutation_id,
+        ))
+        .await?;
+        tx.commit().await?;
     }
 
     Ok(())
@@ -370,6 +667,7 @@ pub async fn unreact(
     message_id: Uuid,
     member_id: Uuid,
     channel_id: Uuid,
+    mutation_id: Option<Uuid>,
 ) -> Result<(), ServerFnError> {
     let pool = pool()?;
     auth()?;
@@ -377,31 +675,36 @@ pub async fn unreact(
     if let Ok(reaction) = ChannelMessage::select_reaction(message_id, member_id, &name, &pool).await
     {
         if reaction.me {
-            ChannelMessage::remove_member_to_reaction(reaction.id, member_id, &pool).await?;
-            // let _ = sync()?
-            //     .broadcast(SyncRequest::Mutation(MutationRequest {
-            //         key: format!("channel:{channel_id}"),
-            //         module: "ChannelStore".into(),
-            //         data: json!(MessageSync::MemberUnreact {
-            //             member: member_id,
-            //             id: message_id,
-            //             reaction: reaction.id
-            //         }),
-            //     }))
-            //     .await;
-            if ChannelMessage::dec_reaction_counter(reaction.id, &pool).await? == 0 {
-                ChannelMessage::delete_reaction(reaction.id, &pool).await?;
-                // let _ = sync()?
-                //     .broadcast(SyncRequest::Mutation(MutationRequest {
-                //         key: format!("channel:{channel_id}"),
-                //         module: "ChannelStore".into(),
-                //         data: json!(MessageSync::DeletedReaction {
-                //             id: message_id,
-                //             reaction: reaction.id
-                //         }),
-                //     }))
-                //     .await;
+            let mut tx = outbox()?.begin().await?;
+            let thread_id = ChannelMessage::get_message_thread(message_id, &mut *tx).await?;
+            ChannelMessage::remove_member_to_reaction(reaction.id, member_id, &mut *tx).await?;
+            let counter = ChannelMessage::dec_reaction_counter(reaction.id, &mut *tx).await?;
+            tx.stage(message_mutation(
+                channel_id,
+                thread_id,
+                &MessageSync::MemberUnreact {
+                    member: member_id,
+                    id: message_id,
+                    reaction: reaction.id,
+                    counter,
+                },
+                mutation_id,
+            ))
+            .await?;
+            if counter == 0 {
+                ChannelMessage::delete_reaction(reaction.id, &mut *tx).await?;
+                tx.stage(message_mutation(
+                    channel_id,
+                    thread_id,
+                    &MessageSync::DeletedReaction {
+                        id: message_id,
+                        reaction: reaction.id,
+                    },
+                    None,
+                ))
+                .await?;
             }
+            tx.commit().await?;
         }
     }
     Ok(())
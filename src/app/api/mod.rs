@@ -4,6 +4,7 @@ pub mod channel;
 pub mod member;
 pub mod messages;
 pub mod server;
+pub mod sync;
 pub mod theme;
 pub mod thread;
 pub mod user;
@@ -17,6 +18,7 @@ pub const SERVER_ERROR: &str = "Something go wrong in our servers";
 cfg_if! {
     if #[cfg(feature = "ssr")] {
         use crate::sync::SyncRequest;
+        use crate::sync::outbox::SyncOutbox;
         use crate::sync::connections::ConnectionMessage;
         use crate::entities::user::AuthSession;
         use crate::entities::user::User;
@@ -44,6 +46,11 @@ cfg_if! {
                 .ok_or_else(|| ServerFnError::new(SERVER_ERROR.to_string()))
         }
 
+        pub fn outbox() -> Result<SyncOutbox, ServerFnError> {
+            use_context()
+                .ok_or_else(|| ServerFnError::new(SERVER_ERROR.to_string()))
+        }
+
         pub fn connection() -> Result<Sender<ConnectionMessage>, ServerFnError> {
             use_context()
                 .ok_or_else(|| ServerFnError::new(SERVER_ERROR.to_string()))
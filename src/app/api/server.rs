@@ -14,9 +14,7 @@ use web_sys::FormData;
 cfg_if! {
     if #[cfg(feature = "ssr")] {
         use crate::sync::SyncRequest;
-        use crate::sync::connections::ConnectionMessage;
-        use super::connection;
-        use super::sync;
+        use super::outbox;
         use std::str::FromStr;
         use crate::entities::member::Member;
         use multer::bytes::Bytes as MulterBytes;
@@ -156,11 +154,10 @@ pub async fn edit_server_image(data: MultipartData) -> Result<(), ServerFnError>
             }
             Server::set_image_url(&res.url, &res.key, server_id, &pool).await?;
             // let _ = sync()?
-            //     .broadcast(SyncRequest::Mutation(MutationRequest {
-            //         key: format!("server:{server_id}"),
-            //         module: "ServersStore".into(),
-            //         data: json!(ServersStoreSync::Updated { id: server_id }),
-            //     }))
+            //     .broadcast(SyncRequest::mutation(
+            //         keys::server(server_id),
+            //         &ServersStoreSync::Updated { id: server_id },
+            //     ))
             //     .await;
             return Ok(());
         }
@@ -176,11 +173,10 @@ pub async fn edit_server_name(new_name: String, server_id: Uuid) -> Result<(), S
     Server::set_server_name(&new_name, server_id, &pool).await?;
 
     // let _ = sync()?
-    //     .broadcast(SyncRequest::Mutation(MutationRequest {
-    //         key: format!("server:{server_id}"),
-    //         module: "ServersStore".into(),
-    //         data: json!(ServersStoreSync::Updated { id: server_id }),
-    //     }))
+    //     .broadcast(SyncRequest::mutation(
+    //         keys::server(server_id),
+    //         &ServersStoreSync::Updated { id: server_id },
+    //     ))
     //     .await;
 
     Ok(())
@@ -198,11 +194,6 @@ pub async fn get_server_roles(server_id: Uuid) -> Result<Vec<Role>, ServerFnErro
 pub async fn get_user_servers() -> Result<Vec<Server>, ServerFnError> {
     let pool = pool()?;
     let user = auth_user()?;
-    let sync = sync()?;
-    let connection = connection()?;
-    let _ = connection
-        .broadcast(ConnectionMessage::InitConnection { client: user.id })
-        .await;
     let servers = Server::get_user_servers(user.id, &pool).await?;
     // let _ = sync
     //     .broadcast(SyncRequest::Subscription(SubscriptionRequest {
@@ -211,16 +202,6 @@ pub async fn get_user_servers() -> Result<Vec<Server>, ServerFnError> {
     //         action: crate::sync::SubscriptionMode::Add,
     //     }))
     //     .await;
-    // let _ = sync
-    //     .broadcast(SyncRequest::Subscription(SubscriptionRequest {
-    //         keys: servers
-    //             .iter()
-    //             .map(|server| format!("server:{}", server.id))
-    //             .collect(),
-    //         client: user.id,
-    //         action: crate::sync::SubscriptionMode::Add,
-    //     }))
-    //     .await;
     Ok(servers)
 }
 
@@ -253,20 +234,11 @@ pub async fn join_server_with_invitation(invitation: String) -> Result<(), Serve
                 Ok(server_id) => {
                     let server = Server::get_server(server_id, &pool).await?;
                     let member = Member::get_from_user_on_server(user.id, server_id, &pool).await?;
-                    let sync = sync()?;
-                    // let _ = sync
-                    //     .broadcast(SyncRequest::Subscription(SubscriptionRequest {
-                    //         keys: HashSet::from([format!("server:{}", server.id)]),
-                    //         client: user.id,
-                    //         action: crate::sync::SubscriptionMode::Add,
-                    //     }))
-                    //     .await;
                     // let _ = sync
-                    //     .broadcast(SyncRequest::Mutation(MutationRequest {
-                    //         key: format!("user:{}", user.id),
-                    //         module: "ServersStore".into(),
-                    //         data: json!(ServersStoreSync::Join { server }),
-                    //     }))
+                    //     .broadcast(SyncRequest::mutation(
+                    //         keys::user(user.id),
+                    //         &ServersStoreSync::Join { server },
+                    //     ))
                     //     .await;
                     redirect(&format!("/servers/{server_id}"))
                 }
@@ -378,22 +350,13 @@ pub async fn create_server(data: MultipartData) -> Result<Server, ServerFnError>
     )
     .await?;
     redirect(&format!("/servers/{}", server.id.simple()));
-    let sync = sync()?;
-    // let _ = sync
-    //     .broadcast(SyncRequest::Subscription(SubscriptionRequest {
-    //         keys: HashSet::from([format!("server:{}", server.id)]),
-    //         client: user.id,
-    //         action: crate::sync::SubscriptionMode::Add,
-    //     }))
-    //     .await;
     // let _ = sync
-    //     .broadcast(SyncRequest::Mutation(MutationRequest {
-    //         key: format!("user:{}", user.id),
-    //         module: "ServersStore".into(),
-    //         data: json!(ServersStoreSync::Join {
+    //     .broadcast(SyncRequest::mutation(
+    //         keys::user(user.id),
+    //         &ServersStoreSync::Join {
     //             server: server.clone()
-    //         }),
-    //     }))
+    //         },
+    //     ))
     // .await;
     Ok(server)
 }
@@ -411,23 +374,16 @@ pub async fn leave_server(server_id: Uuid) -> Result<(), ServerFnError> {
     let pool = pool()?;
     let auth = auth_user()?;
     let member = Member::get_from_user_on_server(auth.id, server_id, &pool).await?;
-    Member::delete_from_server(auth.id, server_id, &pool).await?;
+    let mut tx = outbox()?.begin().await?;
+    Member::delete_from_server(auth.id, server_id, &mut *tx).await?;
 
-    let sync = sync()?;
-    // let _ = sync
-    //     .broadcast(SyncRequest::Mutation(MutationRequest {
-    //         key: format!("user:{}", auth.id),
-    //         module: "ServersStore".into(),
-    //         data: json!(ServersStoreSync::Leave { id: server_id }),
-    //     }))
-    //     .await;
-    // let _ = sync
-    //     .broadcast(SyncRequest::Unsubscription(UnsubscriptionRequest {
-    //         keys: HashSet::from([format!("server:{server_id}")]),
-    //         client: auth.id,
-    //         prefix: None,
-    //     }))
-    //     .await;
+    // tx.stage(SyncRequest::mutation(
+    //     keys::user(auth.id),
+    //     &ServersStoreSync::Leave { id: server_id },
+    // ))
+    // .await?;
+    tx.stage(SyncRequest::Reauthorize { user: auth.id }).await?;
+    tx.commit().await?;
     // msg_sender.send(ServerMessage {
     //     server_id,
     //     msg: Message::MemberLeftServer {
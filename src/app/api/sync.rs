@@ -0,0 +1,33 @@
+use cfg_if::cfg_if;
+use leptos::prelude::*;
+use server_fn::codec::Json;
+use uuid::Uuid;
+
+use crate::sync::protocol::ClientFrame;
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use crate::sync::connections::UserConnections;
+        use crate::sync::typing::TypingIndicators;
+        use crate::ws::handle_client_message;
+        use super::{auth_user, sync, SERVER_ERROR};
+    }
+}
+
+//NOTE: used by the clients on the event stream fallback, the replies go out through the stream
+#[server(name = SendSyncFrame, prefix = "/api", input = Json)]
+pub async fn send_sync_frame(connection: Uuid, frame: ClientFrame) -> Result<(), ServerFnError> {
+    let user = auth_user()?;
+    let connections =
+        use_context::<UserConnections>().ok_or_else(|| ServerFnError::new(SERVER_ERROR))?;
+    let typing =
+        use_context::<TypingIndicators>().ok_or_else(|| ServerFnError::new(SERVER_ERROR))?;
+    let reply = connections
+        .get(&connection)
+        .filter(|entry| entry.value().user_id == user.id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| ServerFnError::new("The sync connection doesn't exist"))?;
+
+    handle_client_message(frame, user.id, connection, &sync()?, &typing, &reply).await;
+    Ok(())
+}
@@ -13,6 +13,9 @@ cfg_if! {
         use super::user_can_edit;
         use super::auth_user;
         use super::pool;
+        use super::outbox;
+        use crate::sync::SyncRequest;
+        use crate::sync::keys::SyncKey;
     }
 }
 
@@ -154,8 +157,14 @@ pub async fn delete_thread(thread_id: Uuid, server_id: Uuid) -> Result<(), Serve
     let user = auth_user()?;
 
     if user_can_edit(server_id, user.id, &pool).await? {
-        Thread::delete_members(thread_id, &pool).await?;
-        Thread::delete(thread_id, &pool).await?;
+        let mut tx = outbox()?.begin().await?;
+        Thread::delete_members(thread_id, &mut *tx).await?;
+        Thread::delete(thread_id, &mut *tx).await?;
+        tx.stage(SyncRequest::ReauthorizeKeys {
+            keys: vec![SyncKey::Thread(thread_id)],
+        })
+        .await?;
+        tx.commit().await?;
         // msg_sender()?.send(ServerMessage {
         //     server_id,
         //     msg: Message::ThreadDeleted { thread_id },
@@ -164,8 +173,14 @@ pub async fn delete_thread(thread_id: Uuid, server_id: Uuid) -> Result<(), Serve
     }
     if let Ok(member) = Member::get_user_member(user.id, server_id, &pool).await {
         if Thread::get_created_by(thread_id, &pool).await? == member.id {
-            Thread::delete_members(thread_id, &pool).await?;
-            Thread::delete(thread_id, &pool).await?;
+            let mut tx = outbox()?.begin().await?;
+            Thread::delete_members(thread_id, &mut *tx).await?;
+            Thread::delete(thread_id, &mut *tx).await?;
+            tx.stage(SyncRequest::ReauthorizeKeys {
+                keys: vec![SyncKey::Thread(thread_id)],
+            })
+            .await?;
+            tx.commit().await?;
             // msg_sender()?.send(ServerMessage {
             //     server_id,
             //     msg: Message::ThreadDeleted { thread_id },
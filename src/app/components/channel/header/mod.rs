@@ -1,4 +1,5 @@
 mod pinned;
+mod search;
 pub mod thread_menu;
 pub mod title;
 
@@ -13,6 +14,7 @@ use leptos::prelude::*;
 use reactive_stores::Field;
 
 use self::pinned::Pinned;
+use self::search::Search;
 use self::thread_menu::ThreadMenu;
 
 #[component]
@@ -65,10 +67,7 @@ pub fn ChannelHeader(
                     />
                 </TooltipProvider>
                 <MemberSideBarTrigger />
-                <div class="relative flex rounded-md p-1 text-center cursor-pointer h-7 border border-base-100 bg-base-200 w-42">
-                    <span class="text-sm">"Search"</span>
-                    <Icon icon=IconData::Search class="w-4 h-4 absolute right-1" />
-                </div>
+                <Search server_id=channel.server_id() />
             </div>
         </div>
     }
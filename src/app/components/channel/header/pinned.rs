@@ -1,17 +1,21 @@
 use leptos::either::Either;
 use leptos::prelude::*;
+use leptos_router::hooks::use_navigate;
 use pulldown_cmark::BlockQuoteKind;
 use reactive_stores::{Field, Store};
 use uuid::Uuid;
 
 use crate::app::api::messages::get_pinned_messages;
 use crate::app::components::channel::member::banner::MemberBanner;
+use crate::app::components::chat::message_url;
 use crate::app::components::ui::dropdown_menu::*;
 use crate::app::components::ui::icons::{Icon, IconData};
 use crate::app::components::ui::markdown::styled::Markdown;
 use crate::app::components::ui::markdown::MarkdownParser;
+use crate::app::routes::servers::server::use_current_server_context;
 use crate::entities::member::MemberStoreFields;
 use crate::entities::message::{ChannelMessage, ChannelMessageStoreFields};
+use crate::entities::server::ServerStoreFields;
 
 #[derive(Debug, Store)]
 struct MessageStore {
@@ -23,6 +27,7 @@ struct MessageStore {
 pub fn Pinned(#[prop(into)] channel_id: Field<Uuid>) -> impl IntoView {
     let pinned = Resource::new(move || (channel_id.get()), get_pinned_messages);
     let open = RwSignal::new(false);
+    let server_id = use_current_server_context().server.id();
     view! {
         <DropdownProvider open=open modal=false>
             <DropdownTrigger class="hover:bg-base-100 rounded-md flex items-center justify-center w-7 h-7 cursor-pointer select-none">
@@ -56,7 +61,19 @@ pub fn Pinned(#[prop(into)] channel_id: Field<Uuid>) -> impl IntoView {
                                             key=|message| message.id().get()
                                             let:message
                                         >
-                                            <div class="relative py-1 w-full flex items-start isolate rounded-lg border-base-content">
+                                            <div class="relative py-1 w-full flex items-start isolate rounded-lg border-base-content group">
+                                                <button
+                                                    on:click={
+                                                        let navigate = use_navigate();
+                                                        move |_| {
+                                                            open.set(false);
+                                                            navigate(&message_url(server_id.get_untracked(), &message.get_untracked()), Default::default());
+                                                        }
+                                                    }
+                                                    class="absolute right-2 top-2 z-10 rounded-md bg-base-100 px-2 py-0.5 text-xs cursor-pointer select-none opacity-0 group-hover:opacity-100"
+                                                >
+                                                    "Jump"
+                                                </button>
                                                 <MemberBanner side=MenuSide::Right align=MenuAlign::Start member=message.sender() class="w-auto h-auto absolute left-2 top-2 z-10" >
                                                     {if let Some(url) = message.sender().image_url().get() {
                                                         Either::Left(
@@ -0,0 +1,145 @@
+use leptos::html;
+use leptos::prelude::*;
+use leptos_router::hooks::use_navigate;
+use reactive_stores::Field;
+use uuid::Uuid;
+
+use crate::app::api::messages::search_messages;
+use crate::app::components::chat::message_url;
+use crate::app::components::ui::dropdown_menu::*;
+use crate::app::components::ui::icons::{Icon, IconData};
+use crate::entities::search::{SearchResult, SEARCH_PAGE_SIZE};
+
+#[component]
+pub fn Search(#[prop(into)] server_id: Field<Uuid>) -> impl IntoView {
+    let open = RwSignal::new(false);
+    let input = RwSignal::new(String::new());
+    let query = RwSignal::new(String::new());
+    let offset = RwSignal::new(0_u32);
+    let trigger_ref: NodeRef<html::Div> = NodeRef::new();
+    let results = Resource::new(
+        move || (server_id.get(), query.get(), offset.get()),
+        |(server_id, query, offset)| search_messages(server_id, query, Some(offset)),
+    );
+    view! {
+        <DropdownProvider open=open modal=false trigger_ref=trigger_ref>
+            <div node_ref=trigger_ref class="relative flex items-center rounded-md h-7 border border-base-100 bg-base-200 w-42">
+                <input
+                    class="w-full h-full bg-transparent text-sm pl-1 pr-6 outline-0"
+                    placeholder="Search"
+                    prop:value=move || input.get()
+                    on:input=move |evt| input.set(event_target_value(&evt))
+                    on:keydown=move |evt| {
+                        match evt.key().as_str() {
+                            "Enter" => {
+                                evt.prevent_default();
+                                query.set(input.get_untracked());
+                                offset.set(0);
+                                open.set(!input.get_untracked().trim().is_empty());
+                            }
+                            "Escape" => open.set(false),
+                            _ => {}
+                        }
+                    }
+                />
+                <Icon icon=IconData::Search class="w-4 h-4 absolute right-1" />
+            </div>
+            <DropdownContent
+                side=MenuSide::Bottom
+                align=MenuAlign::End
+                class="w-auto h-auto z-40"
+            >
+                <div class="relative w-[510px] min-h-[342px] max-h-[600px] h-auto bg-base-300 flex flex-col overflow-x-hidden overflow-y-scroll rounded-md border border-base-100 p-2 origin-top-right starting:opacity-0 starting:translate-x-2 starting:-translate-y-2 starting:scale-95 transition-all">
+                    <Transition>
+                        {
+                            move || {
+                                results.and_then(|page| {
+                                    let total = page.total;
+                                    let has_more = page.has_more;
+                                    let page_offset = page.offset;
+                                    let results = page.results.clone();
+                                    view!{
+                                        <Show when=move || total == 0>
+                                            <div class="absolute inset-0 flex items-center justify-center text-sm">
+                                                "We couldn't find any messages.."
+                                            </div>
+                                        </Show>
+                                        <Show when=move || total != 0>
+                                            <div class="text-xs text-base-content/50 px-2 pb-2 select-none">
+                                                {format!("{total} results")}
+                                            </div>
+                                        </Show>
+                                        {
+                                            results.into_iter().map(|result| view!{ <SearchHit result=result server_id=server_id.get_untracked() open=open/> }).collect_view()
+                                        }
+                                        <Show when=move || page_offset != 0 || has_more>
+                                            <div class="flex items-center justify-end space-x-1 pt-2">
+                                                <button
+                                                    class="hover:bg-base-100 disabled:opacity-50 rounded-md flex items-center justify-center w-7 h-7 cursor-pointer"
+                                                    disabled={page_offset == 0}
+                                                    on:click=move |_| offset.set(page_offset.saturating_sub(SEARCH_PAGE_SIZE))
+                                                >
+                                                    <Icon icon=IconData::ChevronLeft class="h-4 w-4"/>
+                                                </button>
+                                                <button
+                                                    class="hover:bg-base-100 disabled:opacity-50 rounded-md flex items-center justify-center w-7 h-7 cursor-pointer"
+                                                    disabled={!has_more}
+                                                    on:click=move |_| offset.set(page_offset + SEARCH_PAGE_SIZE)
+                                                >
+                                                    <Icon icon=IconData::ChevronRight class="h-4 w-4"/>
+                                                </button>
+                                            </div>
+                                        </Show>
+                                    }
+                                })
+                            }
+                        }
+                    </Transition>
+                </div>
+            </DropdownContent>
+        </DropdownProvider>
+    }
+}
+
+#[component]
+pub fn SearchHit(result: SearchResult, server_id: Uuid, open: RwSignal<bool>) -> impl IntoView {
+    let SearchResult {
+        message,
+        channel_name,
+        thread_name,
+        snippet,
+    } = result;
+    let location = match thread_name {
+        Some(thread_name) => format!("#{channel_name} › {thread_name}"),
+        None => format!("#{channel_name}"),
+    };
+    let url = message_url(server_id, &message);
+    let navigate = use_navigate();
+    let jump = move |_| {
+        open.set(false);
+        navigate(&url, Default::default());
+    };
+    view! {
+        <div on:click=jump class="relative w-full flex flex-col rounded-md px-2 py-1.5 hover:bg-base-100/50 cursor-pointer">
+            <div class="flex items-center mb-1 text-xs">
+                <div class="font-medium text-sm mr-2">{message.sender.name}</div>
+                <div class="text-base-content/50 mr-2">{location}</div>
+                <div class="text-[11px] text-base-content/50">
+                    {message.timestamp.format("%d/%m/%y, %H:%M").to_string()}
+                </div>
+            </div>
+            <div class="text-sm text-wrap whitespace-break-spaces break-words">
+                {snippet
+                    .into_iter()
+                    .map(|part| {
+                        if part.highlight {
+                            view! { <mark class="bg-primary/30 text-base-content rounded-sm">{part.text}</mark> }.into_any()
+                        } else {
+                            view! { <span>{part.text}</span> }.into_any()
+                        }
+                    })
+                    .collect_view()}
+            </div>
+        </div>
+    }
+}
@@ -1,3 +1,4 @@
+use crate::app::api::messages::delete_message;
 use crate::app::components::chat::messages::pin::Pin;
 use crate::app::components::chat::messages::reaction::Reaction;
 use crate::app::components::chat::ChatContext;
@@ -46,9 +47,16 @@ pub fn MessageContextMenu(
             .unwrap_or_default()
     });
     let open = RwSignal::new(false);
+    let server = current_server.server;
+    let delete = Action::new(move |_: &()| {
+        delete_message(server.id().get_untracked(), message.get_untracked().id)
+    });
     let reaction_ref = NodeRef::new();
-    let ChatContext { msg_reference, .. } =
-        use_context::<ChatContext>().expect("should acces to the chat context");
+    let ChatContext {
+        msg_reference,
+        editing,
+        ..
+    } = use_context::<ChatContext>().expect("should acces to the chat context");
     view! {
         <ContextMenuProvider content_ref=content_ref open=open>
             <ContextMenuTrigger>
@@ -71,7 +79,11 @@ pub fn MessageContextMenu(
                             (current_member.get().id == member_id.get()).then(|| {
                                 view!{
                                     <div
-                                        class="flex justify-between hover:bg-base-100 items-center w-full text-sm py-1.5 px-2 group rounded-md"
+                                        on:click=move |_| {
+                                            editing.set(Some(message.get().id));
+                                            open.set(false);
+                                        }
+                                        class="flex cursor-pointer justify-between hover:bg-base-100 items-center w-full text-sm py-1.5 px-2 group rounded-md"
                                     >
                                         "Edit Message"
                                     </div>
@@ -95,11 +107,16 @@ pub fn MessageContextMenu(
                         move || {
                             (current_member.get().id == member_id.get() || current_server.member_can_edit).then(|| {
                                 view!{
-                                    <div
-                                        class="flex justify-between hover:bg-base-100 items-center w-full text-sm py-1.5 px-2 group rounded-md"
+                                    <button
+                                        on:click=move |_| {
+                                            delete.dispatch(());
+                                            open.set(false);
+                                        }
+                                        disabled=move || delete.pending().get()
+                                        class="flex cursor-pointer justify-between hover:bg-base-100 items-center w-full text-sm py-1.5 px-2 group rounded-md text-error"
                                     >
                                         "Delete Message"
-                                    </div>
+                                    </button>
                                 }
                             })
                         }
@@ -0,0 +1,54 @@
+use leptos::html::Textarea;
+use leptos::prelude::*;
+
+use crate::app::components::chat::ChatContext;
+use crate::entities::message::ChannelMessage;
+
+#[component]
+pub fn EditMessage(message: RwSignal<ChannelMessage>, on_save: Callback<String>) -> impl IntoView {
+    let ChatContext { editing, .. } =
+        use_context::<ChatContext>().expect("should acces to the chat context");
+    let content = RwSignal::new(message.get_untracked().content);
+    let textarea_ref: NodeRef<Textarea> = NodeRef::new();
+
+    Effect::new(move |_| {
+        if let Some(textarea) = textarea_ref.get() {
+            let _ = textarea.focus();
+        }
+    });
+
+    let save = move || {
+        let content = content.get_untracked();
+        if !content.trim().is_empty() && content != message.get_untracked().content {
+            on_save.run(content);
+        }
+        editing.set(None);
+    };
+
+    view! {
+        <div class="relative w-full flex flex-col my-1">
+            <textarea
+                node_ref=textarea_ref
+                prop:value=move || content.get()
+                on:input=move |evt| content.set(event_target_value(&evt))
+                on:keydown=move |evt| {
+                    match evt.key().as_str() {
+                        "Enter" if !evt.shift_key() => {
+                            evt.prevent_default();
+                            save();
+                        }
+                        "Escape" => {
+                            evt.prevent_default();
+                            editing.set(None);
+                        }
+                        _ => {}
+                    }
+                }
+                class="w-full resize-none text-sm bg-base-300 rounded-lg border border-base-100 px-4 py-2 outline-0"
+            />
+            <div class="text-xs text-base-content/50 mt-1 select-none">
+                "escape to cancel • enter to save"
+            </div>
+        </div>
+    }
+}
@@ -1,10 +1,12 @@
 mod attachments;
+mod edit;
 mod embeds;
 mod reactions;
 mod reference;
 
-use crate::app::api::messages::{React, Unreact};
+use crate::app::api::messages::{edit_message, react, unreact};
 use crate::app::components::chat::messages::menu::MessageContextMenu;
+use crate::app::components::chat::ChatContext;
 use crate::app::components::ui::icons::{Icon, IconData};
 use crate::app::components::ui::markdown::styled::Markdown;
 use crate::app::components::ui::markdown::MarkdownParser;
@@ -12,23 +14,54 @@ use crate::app::routes::servers::server::use_current_server_context;
 use crate::app::stores::MessageSync;
 use crate::app::sync::use_sync;
 use crate::entities::server::ServerStoreFields;
+use crate::sync::keys::kind;
+use std::collections::HashSet;
 use std::ops::Not;
 
+use chrono::Utc;
 use leptos::either::Either;
+use leptos::html::Div;
 use leptos::prelude::*;
 use pulldown_cmark::BlockQuoteKind;
+use uuid::Uuid;
+use web_sys::{ScrollIntoViewOptions, ScrollLogicalPosition};
 
 use crate::app::components::channel::member::banner::MemberBanner;
 use crate::app::components::ui::dropdown_menu::{MenuAlign, MenuSide};
 use crate::entities::member::{Member, MemberStoreFields};
-use crate::entities::message::ChannelMessage;
+use crate::entities::message::{ChannelMessage, Reaction};
 
 use self::attachments::Attachments;
+use self::edit::EditMessage;
 use self::embeds::Embeds;
-use self::reference::Reference;
+use self::reference::{DeletedReference, Reference};
 
 use super::Group;
 
+fn apply_reaction(message: &mut ChannelMessage, reaction: Uuid, add: bool, me: bool) {
+    if let Some(reaction) = message.reactions.iter_mut().find(|rec| rec.id == reaction) {
+        if add {
+            reaction.counter += 1;
+        } else {
+            reaction.counter = reaction.counter.saturating_sub(1);
+        }
+        if me {
+            reaction.me = add
+        }
+    }
+}
+
+//NOTE: the mutations carry the counter after the write, so a mutation that the snapshot
+//already reflects, or that arrives twice, leaves the reaction as it is
+fn set_reaction(message: &mut ChannelMessage, reaction: Uuid, counter: u32, me: Option<bool>) {
+    if let Some(reaction) = message.reactions.iter_mut().find(|rec| rec.id == reaction) {
+        reaction.counter = counter;
+        if let Some(me) = me {
+            reaction.me = me
+        }
+    }
+}
+
 #[component]
 pub fn ChatGroup(group: Group) -> impl IntoView {
     let sender = RwSignal::new(group.sender);
@@ -39,6 +72,9 @@ pub fn ChatGroup(group: Group) -> impl IntoView {
             <Show when=move || fist_message.get().message_reference.is_some()>
                 <Reference message=Signal::derive(move || *fist_message.get().message_reference.unwrap())/>
             </Show>
+            <Show when=move || fist_message.get().reference_deleted>
+                <DeletedReference/>
+            </Show>
             <div class="relative w-full flex flex-col">
                 <ChatMessage message=fist_message sender=sender is_first=true/>
                 <For
@@ -63,81 +99,206 @@ pub fn ChatMessage(
     let block_kind: RwSignal<Option<BlockQuoteKind>> = RwSignal::new(None);
     let current_server = use_current_server_context().server;
     let current_member = use_current_server_context().member;
+    let ChatContext {
+        editing,
+        highlighted,
+        ..
+    } = use_context::<ChatContext>().expect("should acces to the chat context");
+    let is_editing = move || editing.get() == Some(message.get().id);
+    let is_highlighted = move || highlighted.get() == Some(message.get().id);
+    let node: NodeRef<Div> = NodeRef::new();
+    Effect::new(move |_| {
+        if let Some(node) = node.get() {
+            if is_highlighted() {
+                let options = ScrollIntoViewOptions::new();
+                options.set_block(ScrollLogicalPosition::Center);
+                node.scroll_into_view_with_scroll_into_view_options(&options);
+            }
+        }
+    });
+    //NOTE: the edit is applied right away and rolled back if the write fails,
+    //the broadcast brings the mentions and embeds of the new content
+    let edit = Action::new(move |content: &String| {
+        let previous = message.get_untracked();
+        let content = content.clone();
+        message.update(|message| {
+            message.content = content.clone();
+            message.edited_timestamp = Some(Utc::now());
+        });
+        let server_id = current_server.id().get_untracked();
+        async move {
+            (
+                previous.clone(),
+                edit_message(server_id, previous.id, content).await,
+            )
+        }
+    });
+    Effect::watch(
+        move || edit.value().get(),
+        move |result, _, _| {
+            if let Some((previous, Err(_))) = result {
+                message.update(|message| {
+                    message.content = previous.content.clone();
+                    message.edited_timestamp = previous.edited_timestamp;
+                });
+            }
+        },
+        false,
+    );
+    //NOTE: reactions toggled from this client are applied right away,
+    //their echo is skipped and a failed write is rolled back
+    let pending_reactions: StoredValue<HashSet<Uuid>> = StoredValue::new(HashSet::new());
+    let toggle_reaction = Action::new(move |(reaction, add): &(Reaction, bool)| {
+        let (reaction, add) = (reaction.clone(), *add);
+        let mutation_id = Uuid::new_v4();
+        pending_reactions.update_value(|pending| {
+            pending.insert(mutation_id);
+        });
+        message.update(|message| apply_reaction(message, reaction.id, add, true));
+        let member_id = current_member.id().get_untracked();
+        let channel_id = message.get_untracked().channel_id;
+        async move {
+            let result = if add {
+                react(
+                    reaction.name,
+                    reaction.message_id,
+                    member_id,
+                    channel_id,
+                    Some(mutation_id),
+                )
+                .await
+            } else {
+                unreact(
+                    reaction.name,
+                    reaction.message_id,
+                    member_id,
+                    channel_id,
+                    Some(mutation_id),
+                )
+                .await
+            };
+            (mutation_id, reaction.id, add, result)
+        }
+    });
+    Effect::watch(
+        move || toggle_reaction.value().get(),
+        move |result, _, _| {
+            if let Some((mutation_id, reaction, add, Err(_))) = result {
+                if pending_reactions
+                    .try_update_value(|pending| pending.remove(mutation_id))
+                    .unwrap_or_default()
+                {
+                    message.update(|message| apply_reaction(message, *reaction, !add, true));
+                }
+            }
+        },
+        false,
+    );
+    let is_echo = move |mutation_id: Option<Uuid>| {
+        mutation_id.is_some_and(|mutation_id| {
+            pending_reactions
+                .try_update_value(|pending| pending.remove(&mutation_id))
+                .unwrap_or_default()
+        })
+    };
     if let Some(sync) = use_sync() {
-        sync.message_router
-            .on_module_msg("Message", move |msg: MessageSync| match msg {
-                MessageSync::Pin { id } => {
-                    if message.get().id == id {
-                        message.update(|message| message.pinned = true);
-                    }
+        let on_message = move |msg: MessageSync, mutation_id: Option<Uuid>| match msg {
+            MessageSync::Pin { id } => {
+                if message.get().id == id {
+                    message.update(|message| message.pinned = true);
                 }
-                MessageSync::Unpin { id } => {
-                    if message.get().id == id {
-                        message.update(|message| message.pinned = false);
-                    }
+            }
+            MessageSync::Unpin { id } => {
+                if message.get().id == id {
+                    message.update(|message| message.pinned = false);
                 }
-                MessageSync::NewReaction { id, reaction } => {
-                    if message.get().id == id {
-                        message.update(|message| message.reactions.push(reaction));
-                    }
+            }
+            MessageSync::NewReaction { id, reaction } => {
+                if message.get().id == id
+                    && message
+                        .get()
+                        .reactions
+                        .iter()
+                        .all(|rec| rec.id != reaction.id)
+                {
+                    message.update(|message| message.reactions.push(reaction));
                 }
-                MessageSync::DeletedReaction { id, reaction } => {
-                    if message.get().id == id {
-                        message
-                            .update(|message| message.reactions.retain(|rec| rec.id != reaction));
-                    }
+            }
+            MessageSync::DeletedReaction { id, reaction } => {
+                if message.get().id == id {
+                    message.update(|message| message.reactions.retain(|rec| rec.id != reaction));
                 }
-                MessageSync::MemberReact {
-                    member,
-                    id,
-                    reaction,
-                } => {
-                    if message.get().id == id {
-                        message.update(|message| {
-                            if let Some(reaction) =
-                                message.reactions.iter_mut().find(|rec| rec.id == reaction)
-                            {
-                                reaction.counter += 1;
-                                if member == current_member.id().get() {
-                                    reaction.me = true
-                                }
-                            }
-                        });
-                    }
+            }
+            MessageSync::MemberReact {
+                member,
+                id,
+                reaction,
+                counter,
+            } => {
+                if message.get().id == id && !is_echo(mutation_id) {
+                    let me = (member == current_member.id().get()).then_some(true);
+                    message.update(|message| set_reaction(message, reaction, counter, me));
                 }
-                MessageSync::MemberUnreact {
-                    member,
-                    id,
-                    reaction,
-                } => {
-                    if message.get().id == id {
-                        message.update(|message| {
-                            if let Some(reaction) =
-                                message.reactions.iter_mut().find(|rec| rec.id == reaction)
-                            {
-                                reaction.counter -= 1;
-                                if member == current_member.id().get() {
-                                    reaction.me = false
-                                }
-                            }
-                        });
-                    }
+            }
+            MessageSync::MemberUnreact {
+                member,
+                id,
+                reaction,
+                counter,
+            } => {
+                if message.get().id == id && !is_echo(mutation_id) {
+                    let me = (member == current_member.id().get()).then_some(false);
+                    message.update(|message| set_reaction(message, reaction, counter, me));
                 }
-                MessageSync::Attachments { id, attachments } => {
-                    if message.get().id == id {
-                        message.update(|message| message.attachments = attachments);
-                    }
+            }
+            MessageSync::Attachments { id, attachments } => {
+                if message.get().id == id {
+                    message.update(|message| message.attachments = attachments);
                 }
-                MessageSync::Embeds { id, embeds } => {
-                    if message.get().id == id {
-                        message.update(|message| message.embeds = embeds);
-                    }
+            }
+            MessageSync::Embeds { id, embeds } => {
+                if message.get().id == id {
+                    message.update(|message| message.embeds = embeds);
+                }
+            }
+            MessageSync::Edited {
+                id,
+                content,
+                edited_timestamp,
+                mentions,
+                mentions_roles,
+                mention_everyone,
+                embeds,
+            } => {
+                if message.get().id == id {
+                    message.update(|message| {
+                        message.content = content;
+                        message.edited_timestamp = Some(edited_timestamp);
+                        message.mentions = mentions;
+                        message.mentions_roles = mentions_roles;
+                        message.mention_everyone = mention_everyone;
+                        message.embeds = embeds;
+                    });
                 }
-            });
+            }
+        };
+        //NOTE: the mutations of a thread message are published on the thread key
+        if message.get_untracked().thread_id.is_some() {
+            sync.message_router.on_mutation(kind::Thread, on_message);
+        } else {
+            sync.message_router.on_mutation(kind::Channel, on_message);
+        }
     };
     view! {
         <MessageContextMenu message=message member_id=Signal::derive(move || sender.get().id)>
-            <div class="relative py-0.5 w-full pl-14 pr-4 group hover:bg-neutral/10 flex flex-col items-start text-wrap whitespace-break-spaces">
+            <div
+                node_ref=node
+                class=move || format!("relative py-0.5 w-full pl-14 pr-4 group hover:bg-neutral/10 flex flex-col items-start text-wrap whitespace-break-spaces transition-colors {}", if is_highlighted() {
+                    "bg-indigo-500/10"
+                } else {
+                    ""
+                })
+            >
                 {
                     is_first.then(|| view! {
                         <MemberBanner side=MenuSide::Right align=MenuAlign::Start member=sender class="w-auto h-auto absolute left-2 top-1 z-10" >
@@ -198,8 +359,27 @@ pub fn ChatMessage(
                         </div>
                     })
                 }
-                <div class="flex flex-col items-start">
-                    <Markdown role_mentions=Signal::derive(move || message.get().mentions_roles) mentions=Signal::derive(move || message.get().mentions) markdown=markdown block_kind=block_kind/>
+                <div class="flex flex-col items-start w-full">
+                    <Show
+                        when=is_editing
+                        fallback=move || view! {
+                            <Markdown role_mentions=Signal::derive(move || message.get().mentions_roles) mentions=Signal::derive(move || message.get().mentions) markdown=markdown block_kind=block_kind/>
+                            {
+                                move || message.get().edited_timestamp.map(|edited| view!{
+                                    <div
+                                        title=edited.format("%d/%m/%y, %H:%M").to_string()
+                                        class="text-[10px] text-base-content/50 select-none"
+                                    >
+                                        "(edited)"
+                                    </div>
+                                })
+                            }
+                        }
+                    >
+                        <EditMessage message=message on_save=Callback::new(move |content: String| {
+                            edit.dispatch(content);
+                        })/>
+                    </Show>
                     <Embeds message=message />
                     <Attachments message=message/>
                     <Show when=move || {
@@ -208,16 +388,13 @@ pub fn ChatMessage(
                         <div class="relative flex justify-start space-x-1 mt-1">
                             {
                                 move || {
-                                    let react = ServerAction::<React>::new();
-                                    let unreact = ServerAction::<Unreact>::new();
                                     message.get().reactions.into_iter().map(|reaction| view!{
                                         <button
-                                            disabled=move || react.pending().get() || unreact.pending().get()
-                                            on:click=move |_| {
-                                                if reaction.me {
-                                                    unreact.dispatch(Unreact { name: reaction.name.clone(), message_id: reaction.message_id, member_id: current_member.id().get(), channel_id: message.get().channel_id });
-                                                } else {
-                                                    react.dispatch(React { name: reaction.name.clone(), message_id: reaction.message_id, member_id: current_member.id().get(), channel_id: message.get().channel_id });
+                                            disabled=move || toggle_reaction.pending().get()
+                                            on:click={
+                                                let reaction = reaction.clone();
+                                                move |_| {
+                                                    toggle_reaction.dispatch((reaction.clone(), !reaction.me));
                                                 }
                                             }
                                             class=format!("flex items-center cursor-pointer pl-1 pr-1.5 h-6 w-auto text-center select-none rounded bg-neutral/10 hover:bg-neutral/20 {}", if reaction.me {
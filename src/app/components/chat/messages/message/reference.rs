@@ -1,11 +1,15 @@
 use leptos::either::Either;
 use leptos::prelude::*;
+use leptos_router::hooks::{use_location, use_navigate};
 use pulldown_cmark::HeadingLevel;
 
 use crate::app::components::channel::member::banner::MemberBanner;
+use crate::app::components::chat::JUMP_QUERY;
 use crate::app::components::ui::context_menu::{MenuAlign, MenuSide};
 use crate::app::components::ui::icons::{Icon, IconData};
-use crate::app::components::ui::markdown::{MarkdownElement, MarkdownNode, MarkdownParser, MarkdownTree};
+use crate::app::components::ui::markdown::{
+    MarkdownElement, MarkdownNode, MarkdownParser, MarkdownTree,
+};
 use crate::entities::member::Member;
 use crate::entities::message::ChannelMessage;
 use crate::entities::role::Role;
@@ -13,6 +17,19 @@ use crate::entities::role::Role;
 #[component]
 pub fn Reference(message: Signal<ChannelMessage>) -> impl IntoView {
     let markdown = Signal::derive(move || MarkdownParser::new(&message.get().content).parse_tree());
+    let location = use_location();
+    let navigate = use_navigate();
+    //NOTE: the reference is in the same chat, the url keeps the current view
+    let jump = move |_| {
+        navigate(
+            &format!(
+                "{}?{JUMP_QUERY}={}",
+                location.pathname.get_untracked(),
+                message.get_untracked().id
+            ),
+            Default::default(),
+        )
+    };
     view! {
         <div class="w-full h-7 pl-14 pr-8 overflow-hidden flex items-center justify-start">
             <div class="absolute h-4 w-7 bg-transparent border-l-2 border-l-neutral/30 border-t-2 border-t-neutral/30 rounded-tl-md left-6.5 top-4"/>
@@ -46,7 +63,7 @@ pub fn Reference(message: Signal<ChannelMessage>) -> impl IntoView {
                     }
                 </div>
             </MemberBanner>
-            <div class="w-auto h-full flex items-center min-w-0 mr-1">
+            <div on:click=jump class="w-auto h-full flex items-center min-w-0 mr-1 cursor-pointer hover:text-base-content">
                 <Markdown role_mentions=Signal::derive(move || message.get().mentions_roles) mentions=Signal::derive(move || message.get().mentions) markdown=markdown/>
             </div>
             <Show when=move || !message.get().attachments.is_empty()>
@@ -56,6 +73,18 @@ pub fn Reference(message: Signal<ChannelMessage>) -> impl IntoView {
     }
 }
 
+#[component]
+pub fn DeletedReference() -> impl IntoView {
+    view! {
+        <div class="w-full h-7 pl-14 pr-8 overflow-hidden flex items-center justify-start">
+            <div class="absolute h-4 w-7 bg-transparent border-l-2 border-l-neutral/30 border-t-2 border-t-neutral/30 rounded-tl-md left-6.5 top-4"/>
+            <div class="text-sm italic text-base-content/50 select-none">
+                "Original message deleted"
+            </div>
+        </div>
+    }
+}
+
 #[component]
 fn Markdown(
     markdown: Signal<MarkdownTree>,
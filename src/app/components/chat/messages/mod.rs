@@ -4,25 +4,32 @@ mod pin;
 mod reaction;
 
 use std::collections::BTreeMap;
+use std::time::Duration;
 
 use chrono::{DateTime, Datelike, Month, Utc};
 use leptos::html::Div;
 use leptos::prelude::*;
+use leptos_router::hooks::{use_location, use_navigate, use_query_map};
 use reactive_stores::Field;
 use uuid::Uuid;
 
-use crate::app::api::messages::{get_messages, get_thread_messages};
+use crate::app::api::messages::{get_message_window, get_messages, get_thread_messages};
+use crate::app::components::chat::{ChatContext, JUMP_QUERY};
 use crate::app::routes::servers::server::use_current_server_context;
 use crate::app::stores::MessageStoreSync;
 use crate::app::sync::use_sync;
 use crate::entities::member::Member;
-use crate::entities::message::ChannelMessage;
+use crate::entities::message::{ChannelMessage, MessageWindow, MESSAGE_PAGE_SIZE};
 use crate::entities::server::ServerStoreFields;
 use crate::messages::Message;
+use crate::sync::keys::{kind, SyncKey, SyncModule};
 // use crate::ws::client::use_ws;
 
 use self::message::ChatGroup;
 
+const LOAD_PAGE_THRESHOLD: i32 = 200;
+const HIGHLIGHT_DURATION: Duration = Duration::from_secs(2);
+
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 struct Date {
     month: u32,
@@ -74,7 +81,16 @@ impl MessageGroup {
         let date = Date::from(message.timestamp);
         let entry = self.groups.entry(date).or_default();
 
-        if message.message_reference.is_some() {
+        if entry.iter().any(|group| {
+            group
+                .messages
+                .iter()
+                .any(|current| current.id == message.id)
+        }) {
+            return;
+        }
+
+        if message.message_reference.is_some() || message.reference_deleted {
             entry.push(Group {
                 sender: message.sender.clone(),
                 messages: vec![message],
@@ -94,6 +110,83 @@ impl MessageGroup {
             messages: vec![message],
         });
     }
+
+    //NOTE: the replies to the message keep their group, they only lose the reference
+    pub fn remove(&mut self, id: Uuid) {
+        for groups in self.groups.values_mut() {
+            for group in groups.iter_mut() {
+                group.messages.retain(|message| message.id != id);
+                for message in group.messages.iter_mut() {
+                    if message
+                        .message_reference
+                        .as_ref()
+                        .is_some_and(|reference| reference.id == id)
+                    {
+                        message.message_reference = None;
+                        message.reference_deleted = true;
+                    }
+                }
+            }
+            groups.retain(|group| !group.messages.is_empty());
+        }
+        self.groups.retain(|_, groups| !groups.is_empty());
+    }
+
+    pub fn messages(&self) -> Vec<ChannelMessage> {
+        self.groups
+            .values()
+            .flat_map(|groups| groups.iter())
+            .flat_map(|group| group.messages.iter().cloned())
+            .collect()
+    }
+
+    pub fn newest(&self) -> Option<Uuid> {
+        self.groups
+            .values()
+            .rev()
+            .flat_map(|groups| groups.iter().rev())
+            .flat_map(|group| group.messages.iter().rev())
+            .next()
+            .map(|message| message.id)
+    }
+
+    pub fn oldest(&self) -> Option<Uuid> {
+        self.groups
+            .values()
+            .flat_map(|groups| groups.iter())
+            .flat_map(|group| group.messages.iter())
+            .next()
+            .map(|message| message.id)
+    }
+
+    //NOTE: the groups are built in order, an older page rebuilds them
+    pub fn prepend(&mut self, older: Vec<ChannelMessage>) {
+        let messages = older.into_iter().chain(self.messages()).collect::<Vec<_>>();
+        *self = MessageGroup::from(messages);
+    }
+
+    //NOTE: a snapshot is the latest page, the older pages that were loaded are kept
+    pub fn replace_latest(&mut self, latest: Vec<ChannelMessage>) {
+        let older = match latest.first() {
+            Some(first) => self
+                .messages()
+                .into_iter()
+                .filter(|message| (message.timestamp, message.id) < (first.timestamp, first.id))
+                .collect(),
+            None => vec![],
+        };
+        *self = MessageGroup::from(older.into_iter().chain(latest).collect::<Vec<_>>());
+    }
+
+    pub fn last_from(&self, member_id: Uuid) -> Option<Uuid> {
+        self.groups
+            .values()
+            .rev()
+            .flat_map(|groups| groups.iter().rev())
+            .flat_map(|group| group.messages.iter().rev())
+            .find(|message| message.sender.id == member_id)
+            .map(|message| message.id)
+    }
 }
 
 #[component]
@@ -102,29 +195,250 @@ pub fn ChatMessages(
     thread_id: Option<Signal<Uuid>>,
     #[prop(into)] member_id: Field<Uuid>,
 ) -> impl IntoView {
+    let query = use_query_map();
+    let jump = Memo::new(move |_| {
+        query.with(|query| {
+            query
+                .get(JUMP_QUERY)
+                .and_then(|id| Uuid::parse_str(&id).ok())
+        })
+    });
     let messages = Resource::new(
-        move || (channel_id.get(), member_id.get(), thread_id.get()),
-        move |(channel_id, member_id, thread_id)| async move {
-            if let Some(thread_id) = thread_id {
-                return get_thread_messages(thread_id, member_id).await;
+        move || (channel_id.get(), thread_id.get(), jump.get()),
+        move |(channel_id, thread_id, jump)| async move {
+            if let Some(message_id) = jump {
+                //NOTE: in a split view the message can be in the other chat, then this one
+                //loads its latest page
+                if let Ok(window) =
+                    get_message_window(channel_id, thread_id, message_id, Some(MESSAGE_PAGE_SIZE))
+                        .await
+                {
+                    return Ok::<_, ServerFnError>(window);
+                }
+            }
+            let messages = match thread_id {
+                Some(thread_id) => {
+                    get_thread_messages(thread_id, None, None, Some(MESSAGE_PAGE_SIZE)).await?
+                }
+                None => get_messages(channel_id, None, None, Some(MESSAGE_PAGE_SIZE)).await?,
+            };
+            Ok(MessageWindow {
+                has_older: messages.len() as u32 == MESSAGE_PAGE_SIZE,
+                has_newer: false,
+                messages,
+            })
+        },
+    );
+    let has_older = RwSignal::new(false);
+    let oldest: RwSignal<Option<Uuid>> = RwSignal::new(None);
+    let load_older = Action::new(move |before: &Uuid| {
+        let before = Some(*before);
+        let channel_id = channel_id.get_untracked();
+        let thread_id = thread_id.map(|thread_id| thread_id.get_untracked());
+        async move {
+            match thread_id {
+                Some(thread_id) => {
+                    get_thread_messages(thread_id, before, None, Some(MESSAGE_PAGE_SIZE)).await
+                }
+                None => get_messages(channel_id, before, None, Some(MESSAGE_PAGE_SIZE)).await,
+            }
+        }
+    });
+    //NOTE: after a jump the chat is detached from the present until the newer pages are loaded,
+    //the live messages are skipped meanwhile
+    let has_newer = RwSignal::new(false);
+    let newest: RwSignal<Option<Uuid>> = RwSignal::new(None);
+    let load_newer = Action::new(move |after: &Uuid| {
+        let after = Some(*after);
+        let channel_id = channel_id.get_untracked();
+        let thread_id = thread_id.map(|thread_id| thread_id.get_untracked());
+        async move {
+            match thread_id {
+                Some(thread_id) => {
+                    get_thread_messages(thread_id, None, after, Some(MESSAGE_PAGE_SIZE)).await
+                }
+                None => get_messages(channel_id, None, after, Some(MESSAGE_PAGE_SIZE)).await,
+            }
+        }
+    });
+    let ChatContext {
+        pending,
+        confirmed,
+        last_sent,
+        highlighted,
+        ..
+    } = use_context::<ChatContext>().expect("should acces to the chat context");
+    Effect::watch(
+        move || highlighted.get(),
+        move |current, _, _| {
+            if let Some(current) = *current {
+                set_timeout(
+                    move || {
+                        if highlighted.get_untracked() == Some(current) {
+                            highlighted.set(None);
+                        }
+                    },
+                    HIGHLIGHT_DURATION,
+                );
             }
-            get_messages(channel_id, member_id).await
         },
+        false,
     );
+    let location = use_location();
+    let navigate = use_navigate();
+    let jump_to_present = move |_| navigate(&location.pathname.get_untracked(), Default::default());
+    let subscription_key = move || match thread_id {
+        Some(thread_id) => SyncKey::Thread(thread_id.get()),
+        None => SyncKey::Channel(channel_id.get()),
+    };
+    if let Some(sync) = use_sync() {
+        let module = if thread_id.is_some() {
+            SyncModule::Thread
+        } else {
+            SyncModule::Channel
+        };
+        //NOTE: the snapshot covers whatever changed between the fetch and the subscription,
+        //and it is sent again instead of a resync
+        Effect::new(move |_| sync.subscribe_with_snapshot(vec![subscription_key()], Some(module)));
+    }
     let server = use_current_server_context().server;
     let node: NodeRef<Div> = NodeRef::new();
+    //NOTE: the list is reversed, scroll_top goes from 0 at the bottom to negative values
+    let on_scroll = move |_| {
+        if let Some(node) = node.get() {
+            let to_bottom = node.scroll_top().abs();
+            let to_top = node.scroll_height() - node.client_height() - to_bottom;
+            if to_top < LOAD_PAGE_THRESHOLD
+                && has_older.get_untracked()
+                && !load_older.pending().get_untracked()
+            {
+                if let Some(oldest) = oldest.get_untracked() {
+                    load_older.dispatch(oldest);
+                }
+            }
+            if to_bottom < LOAD_PAGE_THRESHOLD
+                && has_newer.get_untracked()
+                && !load_newer.pending().get_untracked()
+            {
+                if let Some(newest) = newest.get_untracked() {
+                    load_newer.dispatch(newest);
+                }
+            }
+        }
+    };
     view! {
-        <div class="relative min-h-0 h-full scrollbar-none flex flex-col-reverse overflow-y-scroll min-w-0 overflow-x-hidden py-1" node_ref=node >
+        <div on:scroll=on_scroll class="relative min-h-0 h-full scrollbar-none flex flex-col-reverse overflow-y-scroll min-w-0 overflow-x-hidden py-1" node_ref=node >
+            <Show when=move || has_newer.get()>
+                <button
+                    on:click=jump_to_present.clone()
+                    class="sticky z-10 bottom-2 self-center shrink-0 rounded-md bg-base-300 border border-base-100 px-3 py-1 text-xs cursor-pointer select-none hover:bg-base-100"
+                >
+                    "You're viewing older messages • Jump to present"
+                </button>
+            </Show>
+            <div class="relative w-full flex flex-col-reverse opacity-50 pointer-events-none">
+                <For
+                    each=move || pending.get().into_iter().rev()
+                    key=|message| message.id
+                    let:message
+                >
+                    <ChatGroup group=Group { sender: message.sender.clone(), messages: vec![message] }/>
+                </For>
+            </div>
             <Transition>
                 {move || Suspend::new(async move {
-                    messages.await.map(|messages| {
-                        let groups = RwSignal::new(MessageGroup::from(messages));
+                    messages.await.map(|window| {
+                        has_older.set(window.has_older);
+                        has_newer.set(window.has_newer);
+                        if let Some(jump) = jump.get_untracked() {
+                            if window.messages.iter().any(|message| message.id == jump) {
+                                highlighted.set(Some(jump));
+                            }
+                        }
+                        let groups = RwSignal::new(MessageGroup::from(window.messages));
+                        Effect::new(move |_| oldest.set(groups.with(|groups| groups.oldest())));
+                        Effect::new(move |_| newest.set(groups.with(|groups| groups.newest())));
+                        Effect::watch(
+                            move || load_older.value().get(),
+                            move |result, _, _| {
+                                if let Some(Ok(older)) = result {
+                                    has_older.set(older.len() as u32 == MESSAGE_PAGE_SIZE);
+                                    let older = older
+                                        .iter()
+                                        .filter(|message| {
+                                            message.channel_id == channel_id.get_untracked()
+                                                && message.thread_id == thread_id.map(|thread_id| thread_id.get_untracked())
+                                        })
+                                        .cloned()
+                                        .collect();
+                                    groups.update(|groups| groups.prepend(older));
+                                }
+                            },
+                            false,
+                        );
+                        Effect::watch(
+                            move || load_newer.value().get(),
+                            move |result, _, _| {
+                                if let Some(Ok(newer)) = result {
+                                    has_newer.set(newer.len() as u32 == MESSAGE_PAGE_SIZE);
+                                    let newer = newer
+                                        .iter()
+                                        .filter(|message| {
+                                            message.channel_id == channel_id.get_untracked()
+                                                && message.thread_id == thread_id.map(|thread_id| thread_id.get_untracked())
+                                        })
+                                        .cloned()
+                                        .collect();
+                                    groups.update(|groups| groups.add_messages(newer));
+                                }
+                            },
+                            false,
+                        );
+                        Effect::new(move |_| last_sent.set(groups.with(|groups| groups.last_from(member_id.get()))));
                         if let Some(sync) = use_sync() {
-                            sync.message_router.on_module_msg("MessagStore", move |msg: MessageStoreSync| {
-                                match msg {
-                                    MessageStoreSync::Created{message}=>{groups.write().add(*message);},
+                            sync.message_router.on_snapshot(move |key, messages: Vec<ChannelMessage>| {
+                                if key == untrack(subscription_key) && !has_newer.get_untracked() {
+                                    //NOTE: the echo of a pending message may be older than the snapshot,
+                                    //only the ones the snapshot already has are dropped
+                                    let in_snapshot: Vec<Uuid> = confirmed.with_untracked(|confirmed| {
+                                        confirmed
+                                            .iter()
+                                            .filter(|(_, id)| messages.iter().any(|message| message.id == **id))
+                                            .map(|(mutation_id, _)| *mutation_id)
+                                            .collect()
+                                    });
+                                    pending.update(|pending| pending.retain(|message| !in_snapshot.contains(&message.id)));
+                                    confirmed.update(|confirmed| confirmed.retain(|mutation_id, _| !in_snapshot.contains(mutation_id)));
+                                    groups.update(|groups| groups.replace_latest(messages));
                                 }
                             });
+                            let on_message = move |msg: MessageStoreSync, mutation_id: Option<Uuid>| {
+                                match msg {
+                                    MessageStoreSync::Created{message}=>{
+                                        if let Some(mutation_id) = mutation_id {
+                                            pending.update(|pending| pending.retain(|message| message.id != mutation_id));
+                                            confirmed.update(|confirmed| {
+                                                confirmed.remove(&mutation_id);
+                                            });
+                                        }
+                                        if message.channel_id == channel_id.get_untracked()
+                                            && message.thread_id == thread_id.map(|thread_id| thread_id.get_untracked())
+                                            && !has_newer.get_untracked()
+                                        {
+                                            groups.write().add(*message);
+                                        }
+                                    },
+                                    MessageStoreSync::Deleted{id}=>{
+                                        groups.write().remove(id);
+                                    },
+                                }
+                            };
+                            //NOTE: the messages of a thread are published on its own key
+                            if thread_id.is_some() {
+                                sync.message_router.on_mutation(kind::Thread, on_message);
+                            } else {
+                                sync.message_router.on_mutation(kind::Channel, on_message);
+                            }
                         }
                         view!{
                             {
@@ -48,6 +48,7 @@ pub fn Reaction(
             message.get().id,
             member.id().get(),
             message.get().channel_id,
+            None,
         )
     });
     view! {
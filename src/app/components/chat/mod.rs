@@ -2,6 +2,8 @@ mod drop;
 mod messages;
 mod sender;
 
+use std::collections::HashMap;
+
 use leptos::prelude::*;
 use reactive_stores::Field;
 use uuid::Uuid;
@@ -19,6 +21,34 @@ use self::sender::Sender;
 pub struct ChatContext {
     pub msg_reference: RwSignal<Option<ChannelMessage>>,
     pub attachments: RwSignal<Vec<UploadthingFile>>,
+    //NOTE: messages sent from this client that the server has not echoed yet,
+    //their id is the mutation id of the write
+    pub pending: RwSignal<Vec<ChannelMessage>>,
+    //NOTE: the id the server gave to the pending messages whose write already returned,
+    //keyed by their mutation id
+    pub confirmed: RwSignal<HashMap<Uuid, Uuid>>,
+    //NOTE: the message shown with the inline editor, and the last one sent by the current member,
+    //the one that Up-arrow edits
+    pub editing: RwSignal<Option<Uuid>>,
+    pub last_sent: RwSignal<Option<Uuid>>,
+    //NOTE: the message the chat jumped to, it's scrolled into view and highlighted for a moment
+    pub highlighted: RwSignal<Option<Uuid>>,
+}
+
+//NOTE: a chat with `?message=<id>` in the url loads the messages around it instead of the latest
+pub const JUMP_QUERY: &str = "message";
+
+pub fn message_url(server_id: Uuid, message: &ChannelMessage) -> String {
+    match message.thread_id {
+        Some(thread_id) => format!(
+            "/servers/{server_id}/thread/{}/{thread_id}?{JUMP_QUERY}={}",
+            message.channel_id, message.id
+        ),
+        None => format!(
+            "/servers/{server_id}/{}?{JUMP_QUERY}={}",
+            message.channel_id, message.id
+        ),
+    }
 }
 
 #[component]
@@ -1,3 +1,4 @@
+use leptos::ev::KeyboardEvent;
 use leptos::html::Div;
 use leptos::prelude::*;
 use reactive_stores::Field;
@@ -22,8 +23,21 @@ pub fn Input(
         }
     };
 
-    let ChatContext { attachments, .. } =
-        use_context::<ChatContext>().expect("should acces to the chat context");
+    let ChatContext {
+        attachments,
+        editing,
+        last_sent,
+        ..
+    } = use_context::<ChatContext>().expect("should acces to the chat context");
+
+    let on_keydown = move |evt: KeyboardEvent| {
+        if evt.key() == "ArrowUp" && message.get_untracked().is_empty() {
+            if let Some(last_sent) = last_sent.get_untracked() {
+                evt.prevent_default();
+                editing.set(Some(last_sent));
+            }
+        }
+    };
 
     view! {
         <div class="relative w-full h-auto bg-base-300 rounded-b-lg px-4 only:rounded-lg border border-base-100 flex items-center">
@@ -41,6 +55,7 @@ pub fn Input(
                         </div>
                         <div
                             on:input=on_input
+                            on:keydown=on_keydown
                             node_ref=content_ref
                             class="relative outline-0 wrap-break-word text-left whitespace-break-spaces"
                             contenteditable="true"
@@ -1,12 +1,18 @@
 mod attachments;
 mod input;
 mod reference;
+mod typing;
 
-use crate::app::api::messages::{send_message_attachments, SendMessage};
+use crate::app::api::messages::{send_message, send_message_attachments, SendMessage};
 use crate::app::components::chat::ChatContext;
 use crate::app::routes::servers::server::use_current_server_context;
+use crate::app::sync::use_sync;
 use crate::entities::member::MemberStoreFields;
+use crate::entities::message::ChannelMessage;
 use crate::entities::server::ServerStoreFields;
+use crate::sync::keys::SyncKey;
+use crate::sync::protocol::ClientMessage;
+use chrono::{DateTime, TimeDelta, Utc};
 use gloo_file::Blob;
 use leptos::prelude::*;
 use reactive_stores::Field;
@@ -16,6 +22,9 @@ use web_sys::FormData;
 use self::attachments::Attachments;
 use self::input::Input;
 use self::reference::Reference;
+use self::typing::Typing;
+
+const TYPING_THROTTLE: TimeDelta = TimeDelta::seconds(3);
 
 // fn get_caret_position(editable: &HtmlDivElement) -> usize {
 //     if let Some(selection) = window().and_then(|w| w.get_selection().ok()).flatten() {
@@ -172,21 +181,109 @@ pub fn Sender(
     let server = use_current_server_context().server;
     let member = use_current_server_context().member;
 
-    let send_msg = ServerAction::<SendMessage>::new();
+    let send_msg = Action::new(|input: &SendMessage| {
+        let SendMessage {
+            server_id,
+            channel_id,
+            message,
+            member_id,
+            msg_reference,
+            mutation_id,
+        } = input.clone();
+        async move {
+            (
+                mutation_id,
+                send_message(
+                    server_id,
+                    channel_id,
+                    message,
+                    member_id,
+                    msg_reference,
+                    mutation_id,
+                )
+                .await,
+            )
+        }
+    });
 
     let ChatContext {
         msg_reference,
         attachments,
+        pending,
+        confirmed,
+        ..
     } = use_context::<ChatContext>().expect("should acces to the chat context");
 
+    let sync = StoredValue::new(use_sync());
+    let typing_since: StoredValue<Option<DateTime<Utc>>> = StoredValue::new(None);
+    let typing_key = move || match thread_id {
+        Some(thread_id) => SyncKey::Thread(thread_id.get_untracked()),
+        None => SyncKey::Channel(channel_id.get_untracked()),
+    };
+    let send_typing = move |is_typing: bool| {
+        sync.with_value(|sync| {
+            if let Some(sync) = sync {
+                sync.send(ClientMessage::Typing {
+                    key: typing_key().to_string(),
+                    is_typing,
+                });
+            }
+        });
+        typing_since.set_value(is_typing.then(Utc::now));
+    };
+
+    Effect::watch(
+        move || message.get(),
+        move |message, _, _| {
+            let last = typing_since.get_value();
+            if message.trim().is_empty() {
+                if last.is_some() {
+                    send_typing(false);
+                }
+            } else if last.is_none_or(|last| Utc::now() - last > TYPING_THROTTLE) {
+                send_typing(true);
+            }
+        },
+        false,
+    );
+
     let on_click = Signal::derive(move || {
+        if typing_since.get_value().is_some() {
+            send_typing(false);
+        }
         let channel_id = channel_id.get();
+        let content = message.get();
+        let reference = msg_reference.get();
+        let mutation_id = Uuid::new_v4();
+        if !content.is_empty() {
+            pending.update(|pending| {
+                pending.push(ChannelMessage {
+                    id: mutation_id,
+                    channel_id,
+                    thread_id: thread_id.map(|thread_id| thread_id.get()),
+                    sender: member.get(),
+                    message_reference: reference.clone().map(Box::new),
+                    content: content.clone(),
+                    timestamp: Utc::now(),
+                    edited_timestamp: None,
+                    pinned: false,
+                    mention_everyone: false,
+                    reference_deleted: false,
+                    mentions: vec![],
+                    mentions_roles: vec![],
+                    attachments: vec![],
+                    embeds: vec![],
+                    reactions: vec![],
+                })
+            });
+        }
         send_msg.dispatch(SendMessage {
             server_id: server.id().get(),
             channel_id,
-            message: message.get(),
+            message: content,
             member_id: member.id().get(),
-            msg_reference: msg_reference.get().map(|reference| reference.id),
+            msg_reference: reference.map(|reference| reference.id),
+            mutation_id: Some(mutation_id),
         });
     });
 
@@ -197,8 +294,21 @@ pub fn Sender(
 
     Effect::watch(
         move || send_msg.value().get(),
-        move |message_id, _, _| {
-            if let Some(Ok(message_id)) = message_id {
+        move |result, _, _| {
+            if let Some((Some(mutation_id), Err(_))) = result {
+                //NOTE: the write failed, roll back the optimistic message
+                pending.update(|pending| pending.retain(|message| message.id != *mutation_id));
+            }
+            if let Some((Some(mutation_id), Ok(message_id))) = result {
+                if pending.with_untracked(|pending| {
+                    pending.iter().any(|message| message.id == *mutation_id)
+                }) {
+                    confirmed.update(|confirmed| {
+                        confirmed.insert(*mutation_id, *message_id);
+                    });
+                }
+            }
+            if let Some((_, Ok(message_id))) = result {
                 if !attachments.get().is_empty() {
                     let multipart = FormData::new().expect("should create the form data");
                     multipart
@@ -231,6 +341,7 @@ pub fn Sender(
 
     view! {
         <div class="shrink-0 relative mb-4 px-4 w-full h-auto">
+            <Typing channel_id=channel_id thread_id=thread_id/>
             <div class="relative w-full flex flex-col">
                 <Reference/>
                 <Attachments/>
@@ -0,0 +1,82 @@
+use leptos::prelude::*;
+use uuid::Uuid;
+
+use crate::app::routes::servers::server::use_current_server_context;
+use crate::app::routes::servers::MemberStoreStoreFields;
+use crate::app::sync::use_sync;
+use crate::entities::member::MemberStoreFields;
+use crate::messages::Message;
+use crate::sync::keys::kind;
+
+fn typing_text(names: &[String]) -> Option<String> {
+    match names {
+        [] => None,
+        [name] => Some(format!("{name} is typing…")),
+        [first, second] => Some(format!("{first} and {second} are typing…")),
+        [first, second, third] => Some(format!("{first}, {second} and {third} are typing…")),
+        _ => Some("Several people are typing…".into()),
+    }
+}
+
+#[component]
+pub fn Typing(channel_id: Signal<Uuid>, thread_id: Option<Signal<Uuid>>) -> impl IntoView {
+    let typing: RwSignal<Vec<Uuid>> = RwSignal::new(vec![]);
+    let current_server = use_current_server_context();
+    let current_user = current_server.member.user_id();
+    let members = current_server.members;
+
+    Effect::watch(
+        move || (channel_id.get(), thread_id.map(|thread_id| thread_id.get())),
+        move |_, _, _| typing.set(vec![]),
+        false,
+    );
+
+    if let Some(sync) = use_sync() {
+        let on_typing = move |msg: Message| {
+            if let Message::Typing {
+                user_id,
+                chat_id,
+                is_typing,
+            } = msg
+            {
+                let current_chat = thread_id
+                    .map(|thread_id| thread_id.get_untracked())
+                    .unwrap_or_else(|| channel_id.get_untracked());
+                if chat_id != current_chat || user_id == current_user.get_untracked() {
+                    return;
+                }
+                typing.update(|typing| {
+                    typing.retain(|id| *id != user_id);
+                    if is_typing {
+                        typing.push(user_id);
+                    }
+                });
+            }
+        };
+        if thread_id.is_some() {
+            sync.message_router.on_msg(kind::Thread, on_typing);
+        } else {
+            sync.message_router.on_msg(kind::Channel, on_typing);
+        }
+    }
+
+    let names = move || {
+        let members = members.members().read();
+        typing
+            .get()
+            .iter()
+            .filter_map(|user_id| {
+                members
+                    .iter()
+                    .find(|member| member.user_id == *user_id)
+                    .map(|member| member.name.clone())
+            })
+            .collect::<Vec<_>>()
+    };
+
+    view! {
+        <div class="absolute left-4 -top-5 h-5 text-xs text-base-content/60 truncate select-none">
+            {move || typing_text(&names())}
+        </div>
+    }
+}
@@ -0,0 +1,23 @@
+use leptos::prelude::*;
+
+use crate::app::sync::{use_sync, WsState};
+
+#[component]
+pub fn ConnectionBanner() -> impl IntoView {
+    let state = use_sync()
+        .map(|sync| sync.state())
+        .unwrap_or_else(|| Signal::stored(WsState::Open));
+    let message = move || match state.get() {
+        WsState::Open | WsState::Connecting => None,
+        WsState::Closed => Some("Connection lost, reconnecting…"),
+        WsState::Paused => Some("You are offline, waiting to reconnect…"),
+        WsState::Stopped => Some("Disconnected, reload the page to reconnect"),
+    };
+    view! {
+        <Show when=move || message().is_some()>
+            <div class="fixed top-0 inset-x-0 z-50 flex items-center justify-center bg-warning text-warning-content text-xs font-semibold py-1">
+                {message}
+            </div>
+        </Show>
+    }
+}
@@ -1,5 +1,6 @@
 pub mod channel;
 pub mod chat;
+pub mod connection;
 pub mod menu;
 pub mod modal;
 pub mod navigation;
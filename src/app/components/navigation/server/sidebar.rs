@@ -18,6 +18,7 @@ use crate::entities::category::CategoryStoreFields;
 use crate::entities::channel::Channel as ChannelStruct;
 use crate::entities::channel::ChannelStoreFields;
 use crate::entities::server::ServerStoreFields;
+use crate::sync::keys::{kind, SyncKey, SyncModule};
 use leptos::prelude::*;
 use log::debug;
 use reactive_stores::Store;
@@ -63,6 +64,13 @@ pub fn ServerSideBar() -> impl IntoView {
                             {move || Suspend::new(async move {
                                     match (channels.await, categories.await)  {
                                         (Ok(channels), Ok(categories)) => {
+                                            if let Some(sync) = use_sync() {
+                                                let server_id = server.id().get_untracked();
+                                                let mut keys = vec![SyncKey::ServerChannels(server_id)];
+                                                keys.extend(channels.iter().map(|channel| SyncKey::ChannelStore(channel.id)));
+                                                sync.subscribe(keys, Some(SyncModule::ChannelStore));
+                                                sync.subscribe(vec![SyncKey::ServerCategories(server_id)], Some(SyncModule::CategoryStore));
+                                            }
                                             let channels_with_category: Store<HashMap<Uuid, Store<ChannelStore>>> = Store::new(HashMap::new());
                                             let general_channels = Store::new(ChannelStore { channels: vec![] });
                                             for category in &categories {
@@ -88,7 +96,7 @@ pub fn ServerSideBar() -> impl IntoView {
                                             }
                                             let sync =use_sync();
                                             if let Some(sync) = sync {
-                                                sync.message_router.on_module_msg("ChannelStore", move |msg: ChannelStoreSync| {
+                                                sync.message_router.on_msg(kind::ServerChannels, move |msg: ChannelStoreSync| {
                                                     match msg {
                                                         ChannelStoreSync::Deleted { id } => {
                                                             general_channels.channels().update(|channels| {
@@ -114,7 +122,7 @@ pub fn ServerSideBar() -> impl IntoView {
                                                         },
                                                     }
                                                 });
-                                                sync.message_router.on_module_msg("CategoryStore", move |msg: CategoryStoreSync| {
+                                                sync.message_router.on_msg(kind::ServerCategories, move |msg: CategoryStoreSync| {
                                                     match msg {
                                                         CategoryStoreSync::Deleted { id } => {
                                                                             categories.update(|store| {
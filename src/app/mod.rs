@@ -37,7 +37,6 @@ pub fn App() -> impl IntoView {
     //fix the icons
     //fix the ui of the modals and user overview
     //add flallbacks for the transitions
-    //add optimistic update where needed
     //work in the server overview
     //add notifications
     //add inbox
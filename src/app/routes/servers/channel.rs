@@ -9,6 +9,7 @@ use crate::app::stores::ChannelStoreSync;
 use crate::app::sync::use_sync;
 use crate::entities::channel::ChannelStoreFields;
 use crate::entities::server::ServerStoreFields;
+use crate::sync::keys::kind;
 use leptos::prelude::*;
 use leptos_router::components::Outlet;
 use leptos_router::hooks::{use_navigate, use_params_map};
@@ -47,7 +48,7 @@ pub fn ChannelView() -> impl IntoView {
                             let navigate = use_navigate();
                             let sync =use_sync();
                             if let Some(sync) = sync {
-                                sync.message_router.on_module_msg("ChannelStore", move |msg: ChannelStoreSync| {
+                                sync.message_router.on_msg(kind::ServerChannels, move |msg: ChannelStoreSync| {
                                     match msg {
                                         ChannelStoreSync::Deleted { id } => {
                                             if channel.id().get() == id {
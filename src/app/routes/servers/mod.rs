@@ -10,6 +10,7 @@ use crate::app::api::server::provide_server_context;
 use crate::app::api::server::use_server;
 use crate::app::api::thread::provide_thread_context;
 use crate::app::api::user::provide_user_context;
+use crate::app::components::connection::ConnectionBanner;
 use crate::app::components::navigation::sidebar::SideBar;
 use crate::app::components::overview::server::provide_server_overview_context;
 use crate::app::components::overview::server::ServerOverview;
@@ -20,6 +21,7 @@ use crate::app::sync::provide_sync_context;
 use crate::app::sync::use_sync;
 use crate::entities::member::Member;
 use crate::entities::server::Server;
+use crate::sync::keys::{kind, SyncKey, SyncModule};
 use leptos::prelude::*;
 use leptos_router::components::Outlet;
 use log::debug;
@@ -59,10 +61,19 @@ pub fn Servers() -> impl IntoView {
         let servers = servers.await;
         let sync = use_sync();
         servers.map(|servers| {
+            if let Some(sync) = &sync {
+                sync.subscribe(
+                    servers
+                        .iter()
+                        .map(|server| SyncKey::Server(server.id))
+                        .collect(),
+                    Some(SyncModule::Server),
+                );
+            }
             let server_store = Store::new(ServersStore { servers });
             if let Some(sync) = sync {
                 sync.message_router
-                    .on_module_msg("ServersStore", move |sync: ServersStoreSync| match sync {
+                    .on_msg(kind::User, move |sync: ServersStoreSync| match sync {
                         ServersStoreSync::Updated { id } => {
                             debug!("server {id} updated");
                         }
@@ -82,6 +93,7 @@ pub fn Servers() -> impl IntoView {
             });
 
             view! {
+                <ConnectionBanner />
                 <UserOverview />
                 <ServerOverview />
                 <div class="h-full w-full relative z-40 flex">
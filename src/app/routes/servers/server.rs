@@ -7,12 +7,16 @@ use crate::app::api::server::get_server_roles;
 use crate::app::components::navigation::server::sidebar::ServerSideBar;
 use crate::app::components::navigation::server::sidebar::ServerSideBarContext;
 use crate::app::routes::servers::MemberStore;
+use crate::app::routes::servers::MemberStoreStoreFields;
 use crate::app::routes::servers::ServersStore;
 use crate::app::routes::servers::ServersStoreStoreFields;
-use crate::entities::member::Member;
+use crate::app::sync::use_sync;
+use crate::entities::member::{Member, Status};
 use crate::entities::role::Role;
 use crate::entities::server::Server as ServerEnt;
 use crate::entities::server::ServerStoreFields;
+use crate::messages::{Message, ServerMessage};
+use crate::sync::keys::{kind, SyncKey};
 use futures::try_join;
 use leptos::prelude::*;
 use leptos_router::components::Outlet;
@@ -69,6 +73,14 @@ pub fn Server() -> impl IntoView {
                         });
 
 
+                        if let Some(sync) = use_sync() {
+                            sync.message_router.on_resync(move |key| {
+                                if key == SyncKey::Server(server.id().get_untracked()) {
+                                    server_data.refetch();
+                                }
+                            });
+                        }
+
                         let inner_view = Suspend::new(async move {
                             server_data
                                 .await
@@ -76,6 +88,23 @@ pub fn Server() -> impl IntoView {
                                     let member = Store::new(member);
                                     let members = Store::new(MemberStore { members });
                                     let roles = Store::new(RoleStore { roles });
+                                    if let Some(sync) = use_sync() {
+                                        sync.message_router.on_msg(kind::Server, move |msg: ServerMessage| {
+                                            if msg.server_id != server.id().get_untracked() {
+                                                return;
+                                            }
+                                            let (member_id, status) = match msg.msg {
+                                                Message::MemberConnected { member_id } => (member_id, Status::ONLINE),
+                                                Message::MemberDisconnected { member_id } => (member_id, Status::OFFLINE),
+                                                _ => return,
+                                            };
+                                            members.members().update(|members| {
+                                                if let Some(member) = members.iter_mut().find(|member| member.id == member_id) {
+                                                    member.status = status;
+                                                }
+                                            });
+                                        });
+                                    }
 
                                     outer_owner.with(|| {
                                         provide_context(ServerSideBarContext { open });
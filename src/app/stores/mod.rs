@@ -1,10 +1,14 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::entities::category::Category;
 use crate::entities::channel::Channel;
+use crate::entities::member::Member;
 use crate::entities::message::{Attachment, ChannelMessage, Embed, Reaction};
+use crate::entities::role::Role;
 use crate::entities::server::Server;
+use crate::sync::keys::{kind, Coalesce, Payload};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ServersStoreSync {
@@ -30,6 +34,7 @@ pub enum CategoryStoreSync {
 #[derive(Debug, Serialize, Deserialize)]
 pub enum MessageStoreSync {
     Created { message: Box<ChannelMessage> },
+    Deleted { id: Uuid },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,11 +57,13 @@ pub enum MessageSync {
         member: Uuid,
         id: Uuid,
         reaction: Uuid,
+        counter: u32,
     },
     MemberUnreact {
         member: Uuid,
         id: Uuid,
         reaction: Uuid,
+        counter: u32,
     },
     Attachments {
         id: Uuid,
@@ -66,4 +73,64 @@ pub enum MessageSync {
         id: Uuid,
         embeds: Vec<Embed>,
     },
+    Edited {
+        id: Uuid,
+        content: String,
+        edited_timestamp: DateTime<Utc>,
+        mentions: Vec<Member>,
+        mentions_roles: Vec<Role>,
+        mention_everyone: bool,
+        embeds: Vec<Embed>,
+    },
+}
+
+impl Payload<kind::User> for ServersStoreSync {}
+impl Payload<kind::Server> for ServersStoreSync {}
+impl Payload<kind::ServerChannels> for ChannelStoreSync {}
+impl Payload<kind::ChannelStore> for ChannelStoreSync {}
+impl Payload<kind::ServerCategories> for CategoryStoreSync {}
+impl Payload<kind::Channel> for MessageStoreSync {}
+impl Payload<kind::Thread> for MessageStoreSync {}
+impl Payload<kind::Channel> for MessageSync {}
+impl Payload<kind::Thread> for MessageSync {}
+
+impl Coalesce for ServersStoreSync {
+    fn entity(&self) -> Option<String> {
+        match self {
+            ServersStoreSync::Updated { id } => Some(format!("updated:{id}")),
+            _ => None,
+        }
+    }
+}
+
+impl Coalesce for ChannelStoreSync {
+    fn entity(&self) -> Option<String> {
+        match self {
+            ChannelStoreSync::Updated { id } => Some(format!("updated:{id}")),
+            _ => None,
+        }
+    }
+}
+
+impl Coalesce for CategoryStoreSync {
+    fn entity(&self) -> Option<String> {
+        match self {
+            CategoryStoreSync::Updated { id } => Some(format!("updated:{id}")),
+            _ => None,
+        }
+    }
+}
+
+impl Coalesce for MessageStoreSync {}
+
+impl Coalesce for MessageSync {
+    fn entity(&self) -> Option<String> {
+        match self {
+            MessageSync::Pin { id } | MessageSync::Unpin { id } => Some(format!("pinned:{id}")),
+            MessageSync::Attachments { id, .. } => Some(format!("attachments:{id}")),
+            MessageSync::Embeds { id, .. } => Some(format!("embeds:{id}")),
+            MessageSync::Edited { id, .. } => Some(format!("edited:{id}")),
+            _ => None,
+        }
+    }
 }
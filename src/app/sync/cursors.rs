@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+
+use log::debug;
+use uuid::Uuid;
+
+use crate::sync::protocol::{ClientMessage, KeyCursor, SeqRange, ServerFrame};
+
+pub enum FrameAction {
+    Route(ServerFrame),
+    Send(ClientMessage),
+}
+
+enum Advance {
+    //NOTE: carries the last seq applied before, older mutations of the frame are skipped
+    Apply(u64),
+    Skip,
+    Resume(FrameAction),
+}
+
+#[derive(Debug, Default)]
+pub struct SyncCursors {
+    epoch: Option<Uuid>,
+    keys: HashMap<String, u64>,
+    resuming: HashSet<String>,
+    subscriptions: HashSet<String>,
+    snapshots: HashSet<String>,
+}
+
+impl SyncCursors {
+    pub fn track(&mut self, message: &ClientMessage) {
+        match message {
+            ClientMessage::Subscribe {
+                keys,
+                replace_prefix,
+                snapshot,
+            } => {
+                if let Some(prefix) = replace_prefix {
+                    self.subscriptions.retain(|key| !key.starts_with(prefix));
+                    self.snapshots.retain(|key| !key.starts_with(prefix));
+                }
+                self.subscriptions.extend(keys.iter().cloned());
+                if *snapshot {
+                    self.snapshots.extend(keys.iter().cloned());
+                }
+            }
+            ClientMessage::Unsubscribe { keys, prefix } => {
+                if let Some(prefix) = prefix {
+                    self.subscriptions.retain(|key| !key.starts_with(prefix));
+                    self.snapshots.retain(|key| !key.starts_with(prefix));
+                }
+                for key in keys {
+                    self.subscriptions.remove(key);
+                    self.snapshots.remove(key);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn reconnect_messages(&self) -> Vec<ClientMessage> {
+        let (resumable, fresh): (Vec<&String>, Vec<&String>) = self
+            .subscriptions
+            .iter()
+            .partition(|key| self.epoch.is_some() && self.keys.contains_key(*key));
+        let mut messages = vec![];
+        if let (Some(epoch), false) = (self.epoch, resumable.is_empty()) {
+            messages.push(ClientMessage::Resume {
+                epoch,
+                cursors: resumable
+                    .into_iter()
+                    .map(|key| KeyCursor {
+                        key: key.clone(),
+                        seq: self.keys[key],
+                    })
+                    .collect(),
+            });
+        }
+        let (snapshot, fresh): (Vec<&String>, Vec<&String>) = fresh
+            .into_iter()
+            .partition(|key| self.snapshots.contains(*key));
+        for (keys, snapshot) in [(fresh, false), (snapshot, true)] {
+            if !keys.is_empty() {
+                messages.push(ClientMessage::Subscribe {
+                    keys: keys.into_iter().cloned().collect(),
+                    replace_prefix: None,
+                    snapshot,
+                });
+            }
+        }
+        messages
+    }
+
+    //NOTE: a key subscribed with a snapshot asks for a new one instead of making the view refetch
+    fn resync(&self, key: String) -> FrameAction {
+        if self.snapshots.contains(&key) {
+            FrameAction::Send(ClientMessage::Subscribe {
+                keys: vec![key],
+                replace_prefix: None,
+                snapshot: true,
+            })
+        } else {
+            FrameAction::Route(ServerFrame::ResyncRequired { key })
+        }
+    }
+
+    //NOTE: from..=to are the seqs the frame covers, a single mutation covers only its own
+    fn advance(&mut self, key: &str, from: u64, to: u64) -> Advance {
+        match (self.keys.get(key).copied(), self.epoch) {
+            (Some(last), _) if to <= last => {
+                debug!("SyncCursors: Skipping duplicated mutation for '{key}'");
+                Advance::Skip
+            }
+            (Some(last), Some(epoch)) if from > last + 1 => {
+                debug!("SyncCursors: Missed mutations for '{key}' ({last} -> {from})");
+                if self.resuming.insert(key.to_string()) {
+                    Advance::Resume(FrameAction::Send(ClientMessage::Resume {
+                        epoch,
+                        cursors: vec![KeyCursor {
+                            key: key.to_string(),
+                            seq: last,
+                        }],
+                    }))
+                } else {
+                    Advance::Skip
+                }
+            }
+            (last, _) => {
+                self.resuming.remove(key);
+                self.keys.insert(key.to_string(), to);
+                Advance::Apply(last.unwrap_or_default())
+            }
+        }
+    }
+
+    pub fn handle(&mut self, frame: ServerFrame) -> Vec<FrameAction> {
+        match frame {
+            ServerFrame::Mutation(mutation) => {
+                match self.advance(&mutation.key, mutation.seq, mutation.seq) {
+                    Advance::Apply(_) => vec![FrameAction::Route(ServerFrame::Mutation(mutation))],
+                    Advance::Skip => vec![],
+                    Advance::Resume(action) => vec![action],
+                }
+            }
+            ServerFrame::Batch { mutations, ranges } => {
+                let mut actions = vec![];
+                let mut applied: HashMap<String, u64> = HashMap::new();
+                for SeqRange { key, from, to } in ranges {
+                    match self.advance(&key, from, to) {
+                        Advance::Apply(last) => {
+                            applied.insert(key, last);
+                        }
+                        Advance::Skip => {}
+                        Advance::Resume(action) => actions.push(action),
+                    }
+                }
+                //NOTE: unpacked in the order they were sent, as if they came in their own frames
+                actions.extend(
+                    mutations
+                        .into_iter()
+                        .filter(|mutation| {
+                            applied
+                                .get(&mutation.key)
+                                .is_some_and(|last| mutation.seq > *last)
+                        })
+                        .map(|mutation| FrameAction::Route(ServerFrame::Mutation(mutation))),
+                );
+                actions
+            }
+            ServerFrame::Subscribed { epoch, cursors } => {
+                let mut actions = vec![];
+                let subscribed: HashSet<&String> =
+                    cursors.iter().map(|cursor| &cursor.key).collect();
+                if self.epoch.is_some_and(|current| current != epoch) {
+                    let keys: Vec<String> = self.keys.drain().map(|(key, _)| key).collect();
+                    actions = keys
+                        .into_iter()
+                        //NOTE: the keys that come with this frame were just loaded from the new epoch
+                        .filter(|key| !subscribed.contains(key) || !self.snapshots.contains(key))
+                        .map(|key| self.resync(key))
+                        .collect();
+                    self.resuming.clear();
+                }
+                self.epoch = Some(epoch);
+                for KeyCursor { key, seq } in cursors {
+                    self.resuming.remove(&key);
+                    self.keys.insert(key, seq);
+                }
+                actions
+            }
+            ServerFrame::Unsubscribed { keys } => {
+                for key in keys {
+                    self.resuming.remove(&key);
+                    self.keys.remove(&key);
+                }
+                vec![]
+            }
+            ServerFrame::Snapshot { key, seq, data } => {
+                self.resuming.remove(&key);
+                self.keys.insert(key.clone(), seq);
+                vec![FrameAction::Route(ServerFrame::Snapshot { key, seq, data })]
+            }
+            ServerFrame::ResyncRequired { key } => {
+                self.resuming.remove(&key);
+                self.keys.remove(&key);
+                vec![self.resync(key)]
+            }
+            ServerFrame::Error { code, message } => {
+                debug!("SyncCursors: Server rejected a frame ({code:?}): {message}");
+                vec![]
+            }
+            ServerFrame::Ping => vec![FrameAction::Send(ClientMessage::Pong)],
+            ServerFrame::Pong => vec![],
+        }
+    }
+}
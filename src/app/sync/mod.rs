@@ -1,14 +1,21 @@
+use std::sync::{Arc, Mutex};
+
 use async_broadcast::Sender;
 use futures::channel::mpsc;
-use leptos::prelude::{on_cleanup, provide_context, use_context};
+use leptos::prelude::{on_cleanup, provide_context, use_context, ArcRwSignal, Set, Signal};
 use leptos::task::spawn_local;
 use log::debug;
 
-use crate::sync::Mutation;
+use crate::sync::keys::{SyncKey, SyncModule};
+use crate::sync::protocol::{ClientMessage, ServerFrame};
 
+use self::cursors::SyncCursors;
 use self::subscriptions::SyncChannels;
-use self::ws::{Ws, WsMessage};
+use self::ws::{endpoint, Ws, WsMessage};
+
+pub use self::ws::WsState;
 
+mod cursors;
 mod subscriptions;
 mod ws;
 
@@ -16,6 +23,48 @@ mod ws;
 pub struct SyncContext {
     pub ws_sender: Sender<WsMessage>,
     pub message_router: SyncChannels,
+    ws_state: ArcRwSignal<WsState>,
+    cursors: Arc<Mutex<SyncCursors>>,
+}
+
+impl SyncContext {
+    pub fn send(&self, message: ClientMessage) {
+        // Dropped while disconnected, the tracked subscriptions are replayed on connect
+        self.cursors.lock().unwrap().track(&message);
+        let ws_sender = self.ws_sender.clone();
+        spawn_local(async move {
+            let _ = ws_sender.broadcast(WsMessage::Message(message)).await;
+        });
+    }
+
+    pub fn subscribe(&self, keys: Vec<SyncKey>, replace_module: Option<SyncModule>) {
+        self.send(ClientMessage::Subscribe {
+            keys: keys.iter().map(SyncKey::to_string).collect(),
+            replace_prefix: replace_module.map(|module| module.prefix()),
+            snapshot: false,
+        });
+    }
+
+    // The server answers with the current data of every key, see SyncChannels::on_snapshot
+    pub fn subscribe_with_snapshot(&self, keys: Vec<SyncKey>, replace_module: Option<SyncModule>) {
+        self.send(ClientMessage::Subscribe {
+            keys: keys.iter().map(SyncKey::to_string).collect(),
+            replace_prefix: replace_module.map(|module| module.prefix()),
+            snapshot: true,
+        });
+    }
+
+    pub fn state(&self) -> Signal<WsState> {
+        self.ws_state.read_only().into()
+    }
+
+    pub fn stop(&self) {
+        self.ws_state.set(WsState::Stopped);
+        let ws_sender = self.ws_sender.clone();
+        spawn_local(async move {
+            let _ = ws_sender.broadcast(WsMessage::Stop).await;
+        });
+    }
 }
 
 pub fn use_sync() -> Option<SyncContext> {
@@ -26,8 +75,15 @@ pub fn provide_sync_context() {
     #[cfg(feature = "hydrate")]
     {
         debug!("Providing sync context...");
-        let (ws_to_router_tx, ws_to_router_rx) = mpsc::channel::<Mutation>(32);
-        let ws_client = Ws::new(ws_to_router_tx);
+        let (ws_to_router_tx, ws_to_router_rx) = mpsc::channel::<ServerFrame>(32);
+        let ws_state = ArcRwSignal::new(WsState::Connecting);
+        let cursors = Arc::new(Mutex::new(SyncCursors::default()));
+        let ws_client = Ws::new(
+            endpoint(option_env!("SYNC_WS_URL")),
+            ws_to_router_tx,
+            ws_state.clone(),
+            cursors.clone(),
+        );
         let ws_sender_for_app = ws_client.get_ws_sender();
 
         let message_router = SyncChannels::new();
@@ -43,16 +99,15 @@ pub fn provide_sync_context() {
             debug!("MessageRouter processing loop exited.");
         });
 
-        let ws_sender_clone = ws_sender_for_app.clone();
-        on_cleanup(move || {
-            spawn_local(async move {
-                let _ = ws_sender_clone.broadcast(WsMessage::Close).await;
-            });
-        });
-        provide_context(SyncContext {
+        let sync = SyncContext {
             ws_sender: ws_sender_for_app,
             message_router,
-        });
+            ws_state,
+            cursors,
+        };
+        let sync_for_cleanup = sync.clone();
+        on_cleanup(move || sync_for_cleanup.stop());
+        provide_context(sync);
 
         debug!("Sync context provided.");
     }
@@ -1,4 +1,4 @@
-use async_broadcast::{broadcast, Receiver, Sender};
+use async_broadcast::{broadcast, InactiveReceiver, Receiver, Sender};
 use dashmap::DashMap;
 use futures::channel::mpsc;
 use futures::StreamExt as _;
@@ -6,47 +6,81 @@ use leptos::task::spawn_local_scoped_with_cancellation;
 use log::{debug, error};
 use serde::Deserialize;
 use serde_json::Value;
+use std::str::FromStr;
 use std::sync::Arc;
 
+use uuid::Uuid;
+
+use crate::sync::keys::{KeyKind, Payload, SyncKey, SyncModule};
+use crate::sync::protocol::ServerFrame;
 use crate::sync::Mutation;
 
-type Channels = Arc<DashMap<String, (Sender<Value>, Receiver<Value>)>>;
+type Channels = Arc<DashMap<String, (Sender<Mutation>, InactiveReceiver<Mutation>)>>;
 
 const BROADCAST_CHANNEL_CAPACITY: usize = 1024;
 
 #[derive(Debug, Clone)]
 pub struct SyncChannels {
     channels: Channels,
+    resync: (Sender<String>, Receiver<String>),
+    snapshots: (Sender<(String, Value)>, Receiver<(String, Value)>),
 }
 
 impl SyncChannels {
     pub fn new() -> Self {
+        let (mut resync_sender, resync_receiver) = broadcast(BROADCAST_CHANNEL_CAPACITY);
+        resync_sender.set_overflow(true);
+        let (mut snapshot_sender, snapshot_receiver) = broadcast(BROADCAST_CHANNEL_CAPACITY);
+        snapshot_sender.set_overflow(true);
         SyncChannels {
             channels: Default::default(),
+            resync: (resync_sender, resync_receiver),
+            snapshots: (snapshot_sender, snapshot_receiver),
         }
     }
 
-    pub async fn start(&self, receiver: mpsc::Receiver<Mutation>) {
+    //NOTE: the channel keeps an inactive receiver so it stays open without buffering for anyone,
+    //and a listener that can't keep up loses the oldest mutations instead of stalling the routing
+    fn channel(module: &str) -> (Sender<Mutation>, InactiveReceiver<Mutation>) {
+        debug!("SyncChannels: Creating new broadcast channel for module '{module}'");
+        let (mut sender, receiver) = broadcast(BROADCAST_CHANNEL_CAPACITY);
+        sender.set_overflow(true);
+        sender.set_await_active(false);
+        (sender, receiver.deactivate())
+    }
+
+    pub async fn start(&self, receiver: mpsc::Receiver<ServerFrame>) {
         debug!("SyncChannels: Starting message routing loop...");
 
         receiver
-            .for_each_concurrent(None, |mutation| async {
-                let module = mutation.module;
+            .for_each(|frame| async {
+                let mutation = match frame {
+                    ServerFrame::Mutation(mutation) => mutation,
+                    ServerFrame::ResyncRequired { key } => {
+                        debug!("SyncChannels: Resync required for key '{key}'");
+                        let _ = self.resync.0.broadcast(key).await;
+                        return;
+                    }
+                    ServerFrame::Snapshot { key, data, .. } => {
+                        debug!("SyncChannels: Snapshot received for key '{key}'");
+                        let _ = self.snapshots.0.broadcast((key, data)).await;
+                        return;
+                    }
+                    _ => return,
+                };
+                let module = mutation.module.clone();
 
-                let (sender, _) = self
+                let sender = self
                     .channels
                     .entry(module.clone())
-                    .or_insert_with(|| {
-                        debug!(
-                            "SyncChannels: Creating new broadcast channel for module '{module}'"
-                        );
-                        let (s, r) = broadcast(BROADCAST_CHANNEL_CAPACITY);
-                        (s, r)
-                    })
+                    .or_insert_with(|| Self::channel(&module))
                     .value()
+                    .0
                     .clone();
 
-                if let Err(e) = sender.broadcast(mutation.data).await {
+                if sender.receiver_count() == 0 {
+                    debug!("SyncChannels: No listeners for module '{module}'");
+                } else if let Err(e) = sender.broadcast(mutation).await {
                     error!("SyncChannels: Failed to broadcast message for module '{module}': {e}");
                 } else {
                     debug!("SyncChannels: Message broadcasted for module '{module}'");
@@ -57,37 +91,91 @@ impl SyncChannels {
         debug!("SyncChannels: Message routing loop stopped.");
     }
 
-    pub fn subscribe(&self, module: &str) -> Receiver<Value> {
+    pub fn subscribe(&self, module: SyncModule) -> Receiver<Mutation> {
         self.channels
             .entry(module.to_string())
-            .or_insert_with(|| {
-                debug!("SyncChannels: Creating new broadcast channel for module '{module}'");
-                let (s, r) = broadcast(BROADCAST_CHANNEL_CAPACITY);
-                (s, r)
-            })
+            .or_insert_with(|| Self::channel(&module.to_string()))
             .value()
-            .1
-            .clone()
+            .0
+            .new_receiver()
+    }
+
+    pub fn on_resync(&self, on_resync: impl Fn(SyncKey) + Send + Sync + 'static) {
+        #[cfg(feature = "hydrate")]
+        {
+            let mut rx = self.resync.1.clone();
+            spawn_local_scoped_with_cancellation(async move {
+                while let Ok(key) = rx.recv().await {
+                    if let Ok(key) = SyncKey::from_str(&key) {
+                        on_resync(key);
+                    }
+                }
+            });
+        }
     }
 
-    pub fn on_module_msg<T>(&self, module: &str, on_msg: impl Fn(T) + Send + Sync + 'static)
+    pub fn on_snapshot<T>(&self, on_snapshot: impl Fn(SyncKey, T) + Send + Sync + 'static)
     where
         T: for<'de> Deserialize<'de> + Send + 'static,
     {
         #[cfg(feature = "hydrate")]
         {
+            let mut rx = self.snapshots.1.clone();
+            spawn_local_scoped_with_cancellation(async move {
+                while let Ok((key, data)) = rx.recv().await {
+                    let Ok(key) = SyncKey::from_str(&key) else {
+                        continue;
+                    };
+                    match serde_json::from_value(data) {
+                        Ok(snapshot) => on_snapshot(key, snapshot),
+                        Err(err) => debug!("SyncChannels: Invalid snapshot for '{key}': {err}"),
+                    }
+                }
+            });
+        }
+    }
+
+    //NOTE: the kind picks the module to listen to, and only the payloads bound to it compile
+    pub fn on_msg<K, T>(&self, kind: K, on_msg: impl Fn(T) + Send + Sync + 'static)
+    where
+        K: KeyKind,
+        T: Payload<K> + Send + 'static,
+    {
+        self.on_mutation(kind, move |msg: T, _| on_msg(msg));
+    }
+
+    //NOTE: the mutation id is only set on the echo of a write that came from this client,
+    //it lets the caller reconcile the optimistic update it already applied
+    pub fn on_mutation<K, T>(
+        &self,
+        _kind: K,
+        on_msg: impl Fn(T, Option<Uuid>) + Send + Sync + 'static,
+    ) where
+        K: KeyKind,
+        T: Payload<K> + Send + 'static,
+    {
+        #[cfg(feature = "hydrate")]
+        {
+            let module = K::MODULE;
             let mut rx = self.subscribe(module);
             let on_msg = Arc::new(on_msg);
-            let key_clone = module.to_string();
 
             spawn_local_scoped_with_cancellation(async move {
-                debug!("Started listener for key '{key_clone}'");
-                while let Ok(msg_value) = rx.recv().await {
-                    if let Ok(msg) = serde_json::from_value(msg_value) {
-                        on_msg(msg);
+                debug!("Started listener for module '{module}'");
+                loop {
+                    let mutation = match rx.recv().await {
+                        Ok(mutation) => mutation,
+                        Err(async_broadcast::RecvError::Overflowed(skipped)) => {
+                            error!("Listener for module '{module}' skipped {skipped} mutations");
+                            continue;
+                        }
+                        Err(async_broadcast::RecvError::Closed) => break,
+                    };
+                    if let Ok(msg) = serde_json::from_value(mutation.data) {
+                        on_msg(msg, mutation.mutation_id);
                     }
                 }
-                debug!("Listener for key '{key_clone}' stopped.");
+                debug!("Listener for module '{module}' stopped.");
             });
         }
     }
@@ -1,223 +1,406 @@
-use async_broadcast::{broadcast, Receiver, Sender};
+use std::cell::Cell;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use async_broadcast::{broadcast, InactiveReceiver, Sender};
+use chrono::{DateTime, TimeDelta, Utc};
 use futures::channel::mpsc;
-use futures::{SinkExt, StreamExt};
+use futures::stream::{self, SplitStream};
+use futures::{FutureExt as _, SinkExt, StreamExt};
+use gloo_net::eventsource::futures::EventSource;
+use gloo_net::eventsource::EventSourceError;
 use gloo_net::websocket::futures::WebSocket;
 use gloo_net::websocket::Message;
-use gloo_timers::future::TimeoutFuture;
-use leptos::task::spawn_local;
+use gloo_timers::future::{IntervalStream, TimeoutFuture};
+use leptos::prelude::{document, window, ArcRwSignal, GetUntracked, Set};
 use log::debug;
-use serde_json::Value;
-use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+use web_sys::MessageEvent;
 
-use crate::sync::Mutation;
+use crate::app::api::sync::send_sync_frame;
+use crate::sync::codec::{decode, Codec, Frame, PROTOCOLS};
+use crate::sync::protocol::{
+    ClientFrame, ClientMessage, ServerFrame, HEARTBEAT_INTERVAL_MS, HEARTBEAT_TIMEOUT_MS,
+};
 
-#[derive(PartialEq, Debug, Clone)]
+use super::cursors::{FrameAction, SyncCursors};
+
+const INITIAL_RECONNECT_DELAY_MS: u32 = 500;
+const MAX_RECONNECT_DELAY_MS: u32 = 30_000;
+const PAUSED_CHECK_MS: u32 = 1_000;
+const SSE_ENDPOINT: &str = "/sync/events";
+// WebSocket attempts that never opened before we move to the event stream
+const WS_FAILURES_BEFORE_SSE: u32 = 3;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum WsState {
+    Connecting,
     Open,
     Closed,
+    Paused,
     Stopped,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum WsMessage {
-    Message(Value),
+    Message(ClientMessage),
     Close,
     Stop,
 }
 
 pub struct Ws {
+    url: String,
     ws_sender: Sender<WsMessage>,
-    ws_receiver: Receiver<WsMessage>, // Associated receiver for the above sender
-    channel_sender: futures::channel::mpsc::Sender<Mutation>,
-    ws_state: Arc<Mutex<WsState>>,
+    ws_receiver: InactiveReceiver<WsMessage>,
+    channel_sender: mpsc::Sender<ServerFrame>,
+    ws_state: ArcRwSignal<WsState>,
+    cursors: Arc<Mutex<SyncCursors>>,
+}
+
+pub fn endpoint(url: Option<&str>) -> String {
+    if let Some(url) = url {
+        return url.to_string();
+    }
+    let location = window().location();
+    let scheme = match location.protocol().as_deref() {
+        Ok("https:") => "wss",
+        _ => "ws",
+    };
+    let host = location
+        .host()
+        .unwrap_or_else(|_| "localhost:3000".to_string());
+    format!("{scheme}://{host}/ws")
+}
+
+fn can_connect() -> bool {
+    !document().hidden() && window().navigator().on_line()
+}
+
+fn reconnect_delay(attempt: u32) -> u32 {
+    let ceiling = INITIAL_RECONNECT_DELAY_MS
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_RECONNECT_DELAY_MS);
+    // Half of the delay is random so clients don't reconnect all at once after a restart
+    let jitter = (Uuid::new_v4().as_u128() % (ceiling as u128 / 2 + 1)) as u32;
+    ceiling / 2 + jitter
+}
+
+fn encode(codec: Codec, message: ClientMessage) -> Message {
+    match codec
+        .encode(&ClientFrame::new(message))
+        .expect("should serialize the frame")
+    {
+        Frame::Text(text) => Message::Text(text),
+        Frame::Binary(bytes) => Message::Bytes(bytes),
+    }
 }
 
 impl Ws {
-    pub fn new(channel_sender: mpsc::Sender<Mutation>) -> Self {
-        let (ws_sender, ws_receiver) = broadcast::<WsMessage>(1000); // For messages FROM app TO WebSocket
+    pub fn new(
+        url: String,
+        channel_sender: mpsc::Sender<ServerFrame>,
+        ws_state: ArcRwSignal<WsState>,
+        cursors: Arc<Mutex<SyncCursors>>,
+    ) -> Self {
+        let (mut ws_sender, ws_receiver) = broadcast::<WsMessage>(1000); // For messages FROM app TO WebSocket
+
+        // Nothing is queued while we are disconnected, the subscriptions are replayed on connect
+        ws_sender.set_await_active(false);
 
         Ws {
+            url,
             ws_sender,
-            ws_receiver,
+            ws_receiver: ws_receiver.deactivate(),
             channel_sender,
-            ws_state: Arc::new(Mutex::new(WsState::Closed)),
+            ws_state,
+            cursors,
         }
     }
 
-    // You might want to expose the sender for the app to send messages
     pub fn get_ws_sender(&self) -> Sender<WsMessage> {
         self.ws_sender.clone()
     }
 
+    fn stopped(&self) -> bool {
+        self.ws_state.get_untracked() == WsState::Stopped
+    }
+
     pub async fn run(self) {
-        const MAX_RECONNECT_TRIES: u32 = 5;
-        const INITIAL_RECONNECT_DELAY_MS: u32 = 1000; // 1 second
-        const MAX_RECONNECT_DELAY_MS: u32 = 8000; // Cap delay at 8 seconds
+        let mut attempt: u32 = 0;
+        let mut ws_failures: u32 = 0;
 
-        let mut reconnect_tries: u32 = 0;
-        let mut reconnect_delay_ms: u32 = INITIAL_RECONNECT_DELAY_MS;
+        loop {
+            if self.stopped() {
+                break;
+            }
 
-        // Clone the sender to allow the main loop to send a `Stop` message if needed
-        let ws_internal_sender = self.ws_sender.clone();
+            if !can_connect() {
+                self.ws_state.set(WsState::Paused);
+                TimeoutFuture::new(PAUSED_CHECK_MS).await;
+                continue;
+            }
 
-        loop {
-            // Read the current state using the mutex
-            let current_ws_state = {
-                let state_lock = self.ws_state.lock().unwrap();
-                state_lock.clone()
+            if attempt > 0 {
+                let delay = reconnect_delay(attempt);
+                self.ws_state.set(WsState::Closed);
+                debug!("WS: Reconnecting (try {attempt}) after {delay}ms delay...");
+                TimeoutFuture::new(delay).await;
+                if self.stopped() || !can_connect() {
+                    continue;
+                }
+            }
+
+            self.ws_state.set(WsState::Connecting);
+            //NOTE: some proxies strip the upgrade, once the socket keeps failing
+            //we stay on the event stream for the rest of the session
+            let sse = ws_failures >= WS_FAILURES_BEFORE_SSE;
+            let end = if sse {
+                self.event_source().await
+            } else {
+                match WebSocket::open_with_protocols(&self.url, &PROTOCOLS) {
+                    Ok(ws) => self.connection(ws).await,
+                    Err(err) => {
+                        debug!("WS: Connection error: {err:?}");
+                        ConnectionEnd::Lost { opened: false }
+                    }
+                }
             };
 
-            match current_ws_state {
-                WsState::Closed => {
-                    if reconnect_tries >= MAX_RECONNECT_TRIES {
-                        debug!(
-                            "WS: Max reconnect tries ({MAX_RECONNECT_TRIES}) reached. Stopping."
-                        );
-                        // Explicitly send a stop message to self to break the loop
-                        let _ = ws_internal_sender.broadcast(WsMessage::Stop).await;
-                        // Update state to stopped to ensure loop termination
-                        *self.ws_state.lock().unwrap() = WsState::Stopped;
-                        continue; // Go back to loop start and hit WsState::Stopped case
+            match end {
+                ConnectionEnd::Stopped => {
+                    self.ws_state.set(WsState::Stopped);
+                    break;
+                }
+                // Reset the back-off once we managed to talk with the server
+                ConnectionEnd::Lost { opened: true } => {
+                    attempt = 1;
+                    if !sse {
+                        ws_failures = 0;
                     }
+                }
+                ConnectionEnd::Lost { opened: false } => {
+                    attempt += 1;
+                    ws_failures += 1;
+                }
+            }
+        }
+        debug!("WS: Main run loop stopped.");
+    }
+
+    async fn connection(&self, mut ws: WebSocket) -> ConnectionEnd {
+        let mut outgoing = self.ws_receiver.activate_cloned();
+
+        // The sink waits for the socket to be open, so a sent ping means we are connected
+        if ws
+            .send(encode(Codec::Json, ClientMessage::Ping))
+            .await
+            .is_err()
+        {
+            return ConnectionEnd::Lost { opened: false };
+        }
+        // The protocol the server picked is only known once the socket is open
+        let codec = Codec::from_protocol(Some(&ws.protocol()));
+        debug!("WS: Using {codec:?}");
+        let (mut sink, stream) = ws.split();
+        let reconnect = self.cursors.lock().unwrap().reconnect_messages();
+        for message in reconnect {
+            if sink.send(encode(codec, message)).await.is_err() {
+                return ConnectionEnd::Lost { opened: false };
+            }
+        }
+        self.ws_state.set(WsState::Open);
+        debug!("WS: Connection opened.");
 
-                    reconnect_tries += 1;
-
-                    debug!(
-                        "WS: Attempting to connect (try {reconnect_tries}/{MAX_RECONNECT_TRIES}) after {reconnect_delay_ms}ms delay..."
-                    );
-
-                    TimeoutFuture::new(reconnect_delay_ms).await; // Wait before retrying
-
-                    match WebSocket::open("ws://localhost:3000/ws") {
-                        Ok(ws) => {
-                            // Reset reconnect attempts and delay on successful connection
-                            reconnect_tries = 0;
-                            reconnect_delay_ms = INITIAL_RECONNECT_DELAY_MS;
-
-                            // Update state to Open
-                            *self.ws_state.lock().unwrap() = WsState::Open;
-                            debug!("WS: Connection opened.");
-
-                            let (mut ws_sink, mut ws_stream) = ws.split();
-
-                            // Clone necessary parts for the spawned tasks
-                            let mut ws_local_sender_rb = self.ws_receiver.clone();
-                            let mut app_router_tx_clone = self.channel_sender.clone();
-                            let ws_state_clone_for_tasks = self.ws_state.clone(); // Arc clone
-
-                            // Task for sending messages FROM app TO WebSocket
-                            spawn_local(async move {
-                                debug!("WS Send Task: Started");
-                                while let Ok(msg) = ws_local_sender_rb.recv().await {
-                                    match msg {
-                                        WsMessage::Close => {
-                                            debug!("WS Send Task: Received Close signal.");
-                                            // Attempt to close the WebSocket cleanly
-                                            let _ = ws_sink.close().await;
-                                            // Signal main loop to reconnect
-                                            *ws_state_clone_for_tasks.lock().unwrap() =
-                                                WsState::Closed;
-                                            break; // Exit send task
-                                        }
-                                        WsMessage::Stop => {
-                                            debug!(
-                                                "WS Send Task: Received Stop signal. Closing WS."
-                                            );
-                                            let _ = ws_sink.close().await;
-                                            *ws_state_clone_for_tasks.lock().unwrap() =
-                                                WsState::Stopped;
-                                            break; // Exit send task
-                                        }
-                                        WsMessage::Message(json_value) => {
-                                            let json_string =
-                                                serde_json::to_string(&json_value).unwrap_or_else(|e| {
-                                                    debug!(
-                                                        "WS Send Task: Failed to serialize outgoing JSON: {e}"
-                                                    );
-                                                    "{}".to_string() // Send empty JSON or handle error
-                                                });
-                                            if (ws_sink.send(Message::Text(json_string)).await)
-                                                .is_err()
-                                            {
-                                                debug!(
-                                                    "WS Send Task: Failed to send message, connection likely closed."
-                                                );
-                                                // Signal main loop to reconnect
-                                                *ws_state_clone_for_tasks.lock().unwrap() =
-                                                    WsState::Closed;
-                                                break; // Exit send task
-                                            }
-                                        }
-                                    }
-                                }
-                                debug!("WS Send Task: Stopped.");
-                            });
-
-                            let ws_state_clone_for_tasks = self.ws_state.clone(); // Arc clone
-                                                                                  // Task for receiving messages FROM WebSocket
-                            spawn_local(async move {
-                                debug!("WS Receive Task: Started");
-                                while let Some(message) = ws_stream.next().await {
-                                    match message {
-                                        Ok(Message::Text(msg)) => {
-                                            let parsed_value: Mutation =
-                                                match serde_json::from_str(&msg) {
-                                                    Ok(v) => v,
-                                                    Err(e) => {
-                                                        debug!("WS Receive Task: Failed to parse incoming WS message as JSON: {e}. Message: {msg}");
-                                                        continue; // Skip if not valid JSON
-                                                    }
-                                                };
-                                            // All incoming JSON messages go directly to the AppMessageRouter
-                                            if let Err(e) = app_router_tx_clone.send(parsed_value).await
-                                            {
-                                                debug!("WS Receive Task: Failed to send message to AppRouter: {e}");
-                                                // If we can't send to the app router, something is wrong,
-                                                // potentially close the connection and try to reconnect.
-                                                *ws_state_clone_for_tasks.lock().unwrap() =
-                                                    WsState::Closed;
-                                                break; // Exit receive task
-                                            } else {
-                                                debug!("WS Receive Task: Routed generic message to AppRouter");
-                                            }
-                                        }
-                                        Ok(_) => debug!(
-                                            "WS Receive Task: Received non-text message, not implemented yet"
-                                        ),
-                                        Err(err) => {
-                                            debug!("WS Receive Task: Error in receiver: {err:?}");
-                                            // Set state to closed on receive error
-                                            *ws_state_clone_for_tasks.lock().unwrap() =
-                                                WsState::Closed;
-                                            break; // Exit receive task on error
-                                        }
-                                    }
-                                }
-                                debug!("WS Receive Task: Stopped.");
-                            });
+        // Anything the server sends counts as a heartbeat
+        let last_seen = Cell::new(Utc::now());
+
+        let send = async {
+            while let Ok(message) = outgoing.recv().await {
+                match message {
+                    WsMessage::Message(message) => {
+                        if sink.send(encode(codec, message)).await.is_err() {
+                            debug!("WS Send: Failed to send message, connection likely closed.");
+                            break;
                         }
-                        Err(err) => {
-                            debug!("WS: Connection error: {err:?}");
-                            reconnect_tries += 1;
-                            // Exponential back-off with a cap
-                            reconnect_delay_ms =
-                                (reconnect_delay_ms * 2).min(MAX_RECONNECT_DELAY_MS);
-                            // Ensure state is closed on error
-                            *self.ws_state.lock().unwrap() = WsState::Closed;
+                    }
+                    WsMessage::Close => {
+                        let _ = sink.close().await;
+                        break;
+                    }
+                    WsMessage::Stop => {
+                        let _ = sink.close().await;
+                        return ConnectionEnd::Stopped;
+                    }
+                }
+            }
+            ConnectionEnd::Lost { opened: true }
+        };
+
+        let receive = self.receive(stream, &last_seen);
+
+        let watchdog = async {
+            let timeout = TimeDelta::milliseconds(HEARTBEAT_TIMEOUT_MS.into());
+            loop {
+                TimeoutFuture::new(HEARTBEAT_INTERVAL_MS).await;
+                if Utc::now() - last_seen.get() > timeout {
+                    debug!("WS Watchdog: Missed the server heartbeat, reconnecting.");
+                    break;
+                }
+            }
+        };
+
+        let (send, receive, watchdog) = (send.fuse(), receive.fuse(), watchdog.fuse());
+        futures::pin_mut!(send, receive, watchdog);
+        futures::select! {
+            end = send => end,
+            _ = receive => ConnectionEnd::Lost { opened: true },
+            _ = watchdog => ConnectionEnd::Lost { opened: true },
+        }
+    }
+
+    async fn receive(&self, mut stream: SplitStream<WebSocket>, last_seen: &Cell<DateTime<Utc>>) {
+        let mut channel_sender = self.channel_sender.clone();
+        while let Some(message) = stream.next().await {
+            last_seen.set(Utc::now());
+            let msg = match message {
+                Ok(Message::Text(msg)) => Frame::Text(msg),
+                Ok(Message::Bytes(bytes)) => Frame::Binary(bytes),
+                Err(err) => {
+                    debug!("WS Receive: Error in receiver: {err:?}");
+                    return;
+                }
+            };
+            let frame: ServerFrame = match decode(msg) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    debug!("WS Receive: Failed to decode incoming WS message: {e}");
+                    continue;
+                }
+            };
+            if !self.dispatch(frame, &mut channel_sender).await {
+                return;
+            }
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        frame: ServerFrame,
+        channel_sender: &mut mpsc::Sender<ServerFrame>,
+    ) -> bool {
+        let actions = self.cursors.lock().unwrap().handle(frame);
+        for action in actions {
+            match action {
+                FrameAction::Route(frame) => {
+                    if let Err(e) = channel_sender.send(frame).await {
+                        debug!("WS Receive: Failed to send message to AppRouter: {e}");
+                        return false;
+                    }
+                }
+                FrameAction::Send(message) => {
+                    let _ = self.ws_sender.broadcast(WsMessage::Message(message)).await;
+                }
+            }
+        }
+        true
+    }
+
+    async fn event_source(&self) -> ConnectionEnd {
+        let outgoing = self.ws_receiver.activate_cloned();
+        let mut channel_sender = self.channel_sender.clone();
+        let Ok(mut es) = EventSource::new(SSE_ENDPOINT) else {
+            return ConnectionEnd::Lost { opened: false };
+        };
+        let (Ok(connections), Ok(messages)) = (es.subscribe("connection"), es.subscribe("message"))
+        else {
+            return ConnectionEnd::Lost { opened: false };
+        };
+        debug!("SSE: Connecting to the event stream.");
+
+        let events = stream::select(connections, messages).map(SseInput::Event);
+        let outgoing = outgoing.map(SseInput::Outgoing);
+        let ticks = IntervalStream::new(HEARTBEAT_INTERVAL_MS).map(|_| SseInput::Tick);
+        let mut inputs = stream::select(events, stream::select(outgoing, ticks));
+
+        let timeout = TimeDelta::milliseconds(HEARTBEAT_TIMEOUT_MS.into());
+        let mut last_seen = Utc::now();
+        let mut connection: Option<Uuid> = None;
+
+        while let Some(input) = inputs.next().await {
+            let opened = connection.is_some();
+            match input {
+                SseInput::Event(Ok((kind, event))) => {
+                    last_seen = Utc::now();
+                    let data = event.data().as_string().unwrap_or_default();
+                    if kind == "connection" {
+                        // The event source reconnects on its own, every connection gets a new id
+                        let Ok(id) = Uuid::from_str(&data) else {
+                            continue;
+                        };
+                        connection = Some(id);
+                        self.ws_state.set(WsState::Open);
+                        debug!("SSE: Connection '{id}' opened.");
+                        let reconnect = self.cursors.lock().unwrap().reconnect_messages();
+                        for message in reconnect {
+                            send_frame(id, message).await;
                         }
+                        continue;
                     }
+                    let frame: ServerFrame = match decode(Frame::Text(data)) {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            debug!("SSE: Failed to decode incoming event: {e}");
+                            continue;
+                        }
+                    };
+                    if !self.dispatch(frame, &mut channel_sender).await {
+                        return ConnectionEnd::Lost { opened };
+                    }
+                }
+                SseInput::Event(Err(err)) => {
+                    debug!("SSE: Event stream error: {err:?}");
+                    return ConnectionEnd::Lost { opened };
                 }
-                WsState::Open => {
-                    // If we're open, just briefly wait before re-checking the state.
-                    // The actual connection management is handled by the spawned tasks.
-                    TimeoutFuture::new(1_000).await; // Shorter timeout for quicker state checks
+                // The keep alive is the server heartbeat, there is nothing to answer over here
+                SseInput::Outgoing(WsMessage::Message(ClientMessage::Pong)) => {}
+                SseInput::Outgoing(WsMessage::Message(message)) => {
+                    // Before the connection event the tracked subscriptions are replayed anyway
+                    if let Some(id) = connection {
+                        send_frame(id, message).await;
+                    }
                 }
-                WsState::Stopped => {
-                    debug!("WS: Run loop received Stop signal. Exiting.");
-                    break; // Exit the main run loop
+                SseInput::Outgoing(WsMessage::Close) => return ConnectionEnd::Lost { opened },
+                SseInput::Outgoing(WsMessage::Stop) => {
+                    es.close();
+                    return ConnectionEnd::Stopped;
+                }
+                SseInput::Tick => {
+                    if Utc::now() - last_seen > timeout {
+                        debug!("SSE Watchdog: Missed the server heartbeat, reconnecting.");
+                        return ConnectionEnd::Lost { opened };
+                    }
                 }
             }
         }
-        debug!("WS: Main run loop stopped.");
+        ConnectionEnd::Lost {
+            opened: connection.is_some(),
+        }
+    }
+}
+
+async fn send_frame(connection: Uuid, message: ClientMessage) {
+    if let Err(err) = send_sync_frame(connection, ClientFrame::new(message)).await {
+        debug!("SSE: Failed to send the frame: {err}");
     }
 }
+
+enum SseInput {
+    Event(Result<(String, MessageEvent), EventSourceError>),
+    Outgoing(WsMessage),
+    Tick,
+}
+
+enum ConnectionEnd {
+    Lost { opened: bool },
+    Stopped,
+}
@@ -6,7 +6,7 @@ use uuid::Uuid;
 cfg_if! {
     if #[cfg(feature = "ssr")] {
         use super::Error;
-        use sqlx::{FromRow, MySqlPool};
+        use sqlx::{FromRow, MySqlExecutor};
     }
 }
 
@@ -20,13 +20,17 @@ pub struct Category {
 
 #[cfg(feature = "ssr")]
 impl Category {
-    pub async fn create(name: &str, server: Uuid, pool: &MySqlPool) -> Result<Uuid, Error> {
+    pub async fn create(
+        name: &str,
+        server: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<Uuid, Error> {
         let id = Uuid::new_v4();
         sqlx::query("INSERT INTO categories (id, name, server_id) VALUES (?,?,?)")
             .bind(id)
             .bind(name)
             .bind(server)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(id)
     }
@@ -35,22 +39,26 @@ impl Category {
         new_name: &str,
         channel_id: Uuid,
         server: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<(), Error> {
         sqlx::query("UPDATE categories SET categories.name = ? WHERE categories.server_id = ? AND categories.id = ?")
             .bind(new_name)
             .bind(
                 server
             ).bind(channel_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
-    pub async fn delete(id: Uuid, server_id: Uuid, pool: &MySqlPool) -> Result<(), Error> {
+    pub async fn delete(
+        id: Uuid,
+        server_id: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<(), Error> {
         sqlx::query("DELETE FROM categories WHERE server_id = ? AND id = ?")
             .bind(server_id)
             .bind(id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
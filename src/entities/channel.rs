@@ -7,7 +7,7 @@ use uuid::Uuid;
 cfg_if! {
     if #[cfg(feature = "ssr")] {
         use super::Error;
-        use sqlx::{FromRow, MySqlPool, Decode, Encode};
+        use sqlx::{FromRow, MySqlExecutor, MySqlPool, Decode, Encode};
     }
 }
 
@@ -58,12 +58,12 @@ impl Channel {
     pub async fn update_topic(
         channel_id: Uuid,
         topic: &str,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<(), Error> {
         sqlx::query("UPDATE channels SET channels.topic = ? WHERE channels.id = ?")
             .bind(topic)
             .bind(channel_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -71,7 +71,7 @@ impl Channel {
         name: &str,
         channel_type: ChannelType,
         server: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<Uuid, Error> {
         let id = Uuid::new_v4();
         sqlx::query("INSERT INTO channels (id, name, channel_type, server_id) VALUES (?, ?, ?, ?)")
@@ -79,7 +79,7 @@ impl Channel {
             .bind(name)
             .bind(channel_type)
             .bind(server)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(id)
     }
@@ -89,7 +89,7 @@ impl Channel {
         channel_type: ChannelType,
         server: Uuid,
         category: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<Uuid, Error> {
         let id = Uuid::new_v4();
         sqlx::query("INSERT INTO channels (id, name, channel_type, server_id, category_id) VALUES (?, ?, ?, ?, ?)")
@@ -98,25 +98,33 @@ impl Channel {
             .bind(channel_type)
             .bind(server)
             .bind(category)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(id)
     }
 
-    pub async fn rename(new_name: &str, channel_id: Uuid, pool: &MySqlPool) -> Result<(), Error> {
+    pub async fn rename(
+        new_name: &str,
+        channel_id: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<(), Error> {
         sqlx::query("UPDATE channels SET channels.name = ? WHERE channels.id = ?")
             .bind(new_name)
             .bind(channel_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
 
-    pub async fn delete(channel_id: Uuid, server_id: Uuid, pool: &MySqlPool) -> Result<(), Error> {
+    pub async fn delete(
+        channel_id: Uuid,
+        server_id: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<(), Error> {
         sqlx::query("DELETE FROM channels WHERE server_id = ? AND id = ?")
             .bind(server_id)
             .bind(channel_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -124,17 +132,29 @@ impl Channel {
     pub async fn remove_all_from_category(
         server_id: Uuid,
         category_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<(), Error> {
         sqlx::query("UPDATE channels SET channels.category_id = NULL WHERE channels.category_id = ? AND channels.server_id = ?")
             .bind(
                 category_id
             ).bind(server_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
 
+    pub async fn user_has_access(
+        channel_id: Uuid,
+        user_id: Uuid,
+        pool: &MySqlPool,
+    ) -> Result<bool, Error> {
+        Ok(sqlx::query_as::<_, (bool,)>("SELECT EXISTS(SELECT 1 FROM channels JOIN members ON members.server_id = channels.server_id WHERE channels.id = ? AND members.user_id = ?)")
+                    .bind(channel_id)
+                    .bind(user_id)
+                    .fetch_one(pool)
+                    .await?.0)
+    }
+
     pub async fn get_channel(
         channel_id: Uuid,
         server_id: Uuid,
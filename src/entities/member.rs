@@ -11,7 +11,7 @@ cfg_if! {
         use super::Error;
         use super::server::Server;
         use super::role::Role;
-        use sqlx::{FromRow, MySqlPool};
+        use sqlx::{FromRow, MySqlExecutor, MySqlPool};
     }
 }
 
@@ -79,12 +79,12 @@ impl Member {
     pub async fn update_members_status(
         user_id: Uuid,
         status: Status,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<(), Error> {
         sqlx::query("UPDATE members SET members.status = ? WHERE members.user_id = ?")
             .bind(status)
             .bind(user_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -250,6 +250,34 @@ impl Member {
         .await;
         Ok(res?)
     }
+    pub async fn get_user_member_on_channel(
+        user_id: Uuid,
+        channel_id: Uuid,
+        pool: &MySqlPool,
+    ) -> Result<Member, Error> {
+        Ok(sqlx::query_as::<_, Member>(
+            "SELECT mv.* FROM members_with_profile_fallback mv JOIN channels ON channels.server_id = mv.server_id WHERE mv.user_id = ? AND channels.id = ?",
+        )
+        .bind(user_id)
+        .bind(channel_id)
+        .fetch_one(pool)
+        .await?)
+    }
+
+    pub async fn get_user_member_on_thread(
+        user_id: Uuid,
+        thread_id: Uuid,
+        pool: &MySqlPool,
+    ) -> Result<Member, Error> {
+        Ok(sqlx::query_as::<_, Member>(
+            "SELECT mv.* FROM members_with_profile_fallback mv JOIN channels ON channels.server_id = mv.server_id JOIN threads ON threads.channel_id = channels.id WHERE mv.user_id = ? AND threads.id = ?",
+        )
+        .bind(user_id)
+        .bind(thread_id)
+        .fetch_one(pool)
+        .await?)
+    }
+
     pub async fn create(
         user: Uuid,
         server: Uuid,
@@ -304,12 +332,12 @@ impl Member {
     pub async fn delete_from_server(
         user_id: Uuid,
         server_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<(), Error> {
         sqlx::query("DELETE FROM members WHERE user_id=? AND server_id=?")
             .bind(user_id)
             .bind(server_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -1,6 +1,5 @@
 use cfg_if::cfg_if;
 use chrono::{DateTime, Utc};
-use log::debug;
 use reactive_stores::Store;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
@@ -13,7 +12,9 @@ use super::role::Role;
 
 cfg_if! {
     if #[cfg(feature = "ssr")] {
-        use sqlx::{FromRow, MySqlPool};
+        use sqlx::mysql::MySql;
+        use sqlx::{Acquire, FromRow, MySqlExecutor, MySqlPool, QueryBuilder};
+        use std::collections::{HashMap, HashSet};
         use super::Error;
     }
 }
@@ -31,6 +32,9 @@ pub struct ChannelMessage {
     pub edited_timestamp: Option<DateTime<Utc>>,
     pub pinned: bool,
     pub mention_everyone: bool,
+    //NOTE: the message replied to a message that was deleted, message_reference is None
+    #[serde(default)]
+    pub reference_deleted: bool,
     pub mentions: Vec<Member>,
     pub mentions_roles: Vec<Role>,
     pub attachments: Vec<Attachment>,
@@ -38,6 +42,36 @@ pub struct ChannelMessage {
     pub reactions: Vec<Reaction>,
 }
 
+pub const MESSAGE_PAGE_SIZE: u32 = 50;
+pub const MAX_MESSAGE_PAGE_SIZE: u32 = 100;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub struct MessagePage {
+    pub before: Option<Uuid>,
+    pub after: Option<Uuid>,
+    pub limit: Option<u32>,
+}
+
+impl MessagePage {
+    pub fn latest() -> Self {
+        MessagePage::default()
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+            .unwrap_or(MESSAGE_PAGE_SIZE)
+            .clamp(1, MAX_MESSAGE_PAGE_SIZE)
+    }
+}
+
+//NOTE: the messages around a message, in order, and if there are more on each side
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct MessageWindow {
+    pub messages: Vec<ChannelMessage>,
+    pub has_older: bool,
+    pub has_newer: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Store)]
 #[cfg_attr(feature = "ssr", derive(FromRow))]
 pub struct Attachment {
@@ -85,14 +119,106 @@ pub struct SqlChannelMessage {
     pub edited_timestamp: Option<DateTime<Utc>>,
     pub pinned: bool,
     pub mention_everyone: bool,
+    pub reference_deleted: bool,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(FromRow)]
+struct SqlMemberReaction {
+    id: Uuid,
+    message_id: Uuid,
+    name: Vec<u8>,
+    counter: u32,
+    me: bool,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(FromRow)]
+struct MessageRelation<T> {
+    message_id: Uuid,
+    #[sqlx(flatten)]
+    row: T,
+}
+
+#[cfg(feature = "ssr")]
+impl<T> MessageRelation<T> {
+    fn group(rows: Vec<MessageRelation<T>>) -> HashMap<Uuid, Vec<T>> {
+        let mut grouped: HashMap<Uuid, Vec<T>> = HashMap::new();
+        for relation in rows {
+            grouped
+                .entry(relation.message_id)
+                .or_default()
+                .push(relation.row);
+        }
+        grouped
+    }
+}
+
+#[cfg(feature = "ssr")]
+struct MessageRelations {
+    senders: HashMap<Uuid, Member>,
+    mentions: HashMap<Uuid, Vec<Member>>,
+    mentions_roles: HashMap<Uuid, Vec<Role>>,
+    attachments: HashMap<Uuid, Vec<Attachment>>,
+    embeds: HashMap<Uuid, Vec<Embed>>,
+    reactions: HashMap<Uuid, Vec<Reaction>>,
+}
+
+#[cfg(feature = "ssr")]
+impl MessageRelations {
+    fn assemble(
+        &self,
+        message: SqlChannelMessage,
+        message_reference: Option<Box<ChannelMessage>>,
+        with_reactions: bool,
+    ) -> Result<ChannelMessage, Error> {
+        let sender = self
+            .senders
+            .get(&message.sender_id)
+            .cloned()
+            .ok_or(Error::NotFound)?;
+        Ok(ChannelMessage {
+            id: message.id,
+            channel_id: message.channel_id,
+            thread_id: message.thread_id,
+            sender,
+            message_reference,
+            content: message.content,
+            timestamp: message.timestamp,
+            edited_timestamp: message.edited_timestamp,
+            pinned: message.pinned,
+            mention_everyone: message.mention_everyone,
+            reference_deleted: message.reference_deleted,
+            mentions: self.mentions.get(&message.id).cloned().unwrap_or_default(),
+            mentions_roles: self
+                .mentions_roles
+                .get(&message.id)
+                .cloned()
+                .unwrap_or_default(),
+            attachments: self
+                .attachments
+                .get(&message.id)
+                .cloned()
+                .unwrap_or_default(),
+            embeds: self.embeds.get(&message.id).cloned().unwrap_or_default(),
+            reactions: if with_reactions {
+                self.reactions.get(&message.id).cloned().unwrap_or_default()
+            } else {
+                vec![]
+            },
+        })
+    }
 }
 
 #[cfg(feature = "ssr")]
 impl ChannelMessage {
-    pub async fn mention_everyone(message_id: Uuid, pool: &MySqlPool) -> Result<(), Error> {
+    pub async fn mention_everyone(
+        message_id: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<(), Error> {
         sqlx::query("UPDATE channel_messages ch SET ch.mention_everyone = TRUE WHERE ch.id = ?")
             .bind(message_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -101,43 +227,181 @@ impl ChannelMessage {
         message_id: Uuid,
         op: OpenGraph,
         url: String,
-        pool: &MySqlPool,
+        conn: impl Acquire<'_, Database = MySql>,
     ) -> Result<Embed, Error> {
+        let mut conn = conn.acquire().await?;
         let id = Uuid::new_v4();
         let data = serde_json::to_value(op).unwrap();
         sqlx::query("INSERT INTO embeds (id, url, data) VALUES (?, ?, ?)")
             .bind(id)
             .bind(&url)
             .bind(&data)
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
 
         sqlx::query("INSERT INTO channel_messages_embeds (message_id, embeds_id) VALUES (?, ?)")
             .bind(message_id)
             .bind(id)
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
 
         Ok(Embed { id, url, data })
     }
-    pub async fn pin(message_id: Uuid, pinned: bool, pool: &MySqlPool) -> Result<(), Error> {
+    pub async fn pin(
+        message_id: Uuid,
+        pinned: bool,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<(), Error> {
         sqlx::query("UPDATE channel_messages ch SET ch.pinned = ? WHERE ch.id = ?")
             .bind(pinned)
             .bind(message_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
 
+    pub async fn get_sender_and_channel(
+        message_id: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<(Uuid, Uuid), Error> {
+        Ok(
+            sqlx::query_as("SELECT sender_id, channel_id FROM channel_messages WHERE id = ?")
+                .bind(message_id)
+                .fetch_one(conn)
+                .await?,
+        )
+    }
+
+    pub async fn get_message_thread(
+        message_id: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<Option<Uuid>, Error> {
+        Ok(
+            sqlx::query_scalar("SELECT thread_id FROM channel_messages WHERE id = ?")
+                .bind(message_id)
+                .fetch_one(conn)
+                .await?,
+        )
+    }
+
+    pub async fn edit(
+        message_id: Uuid,
+        content: &str,
+        edited_timestamp: DateTime<Utc>,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "UPDATE channel_messages ch SET ch.content = ?, ch.edited_timestamp = ?, ch.mention_everyone = FALSE WHERE ch.id = ?",
+        )
+        .bind(content)
+        .bind(edited_timestamp)
+        .bind(message_id)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn clear_mentions(
+        message_id: Uuid,
+        conn: impl Acquire<'_, Database = MySql>,
+    ) -> Result<(), Error> {
+        let mut conn = conn.acquire().await?;
+        sqlx::query("DELETE FROM messages_mentions WHERE message_id = ?")
+            .bind(message_id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("DELETE FROM messages_role_mentions WHERE message_id = ?")
+            .bind(message_id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_embed(
+        message_id: Uuid,
+        embed_id: Uuid,
+        conn: impl Acquire<'_, Database = MySql>,
+    ) -> Result<(), Error> {
+        let mut conn = conn.acquire().await?;
+        sqlx::query("DELETE FROM channel_messages_embeds WHERE message_id = ? AND embeds_id = ?")
+            .bind(message_id)
+            .bind(embed_id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("DELETE FROM embeds WHERE id = ?")
+            .bind(embed_id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    //NOTE: returns the uploadthing keys of the attachments, the attachments uploaded before the
+    //file_key column only have their url, the key is its last segment
+    pub async fn delete(
+        message_id: Uuid,
+        conn: impl Acquire<'_, Database = MySql>,
+    ) -> Result<Vec<String>, Error> {
+        let mut conn = conn.acquire().await?;
+        let attachments: Vec<(Uuid, String)> = sqlx::query_as(
+            r#"
+            SELECT
+                a.id,
+                COALESCE(a.file_key, SUBSTRING_INDEX(a.url, '/', -1))
+            FROM
+                attachments a
+                INNER JOIN channel_messages_attachments cma ON a.id = cma.attachment_id
+            WHERE
+                cma.message_id = ?
+            "#,
+        )
+        .bind(message_id)
+        .fetch_all(&mut *conn)
+        .await?;
+        let embeds: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT embeds_id FROM channel_messages_embeds WHERE message_id = ?",
+        )
+        .bind(message_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        //NOTE: edited_timestamp is ON UPDATE CURRENT_TIMESTAMP, the replies aren't edited
+        sqlx::query(
+            "UPDATE channel_messages SET reference_deleted = TRUE, edited_timestamp = edited_timestamp WHERE message_reference = ?",
+        )
+        .bind(message_id)
+        .execute(&mut *conn)
+        .await?;
+        //NOTE: mentions, reactions and the attachment and embed links cascade with the message
+        sqlx::query("DELETE FROM channel_messages WHERE id = ?")
+            .bind(message_id)
+            .execute(&mut *conn)
+            .await?;
+
+        for (id, _) in &attachments {
+            sqlx::query("DELETE FROM attachments WHERE id = ?")
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        for id in embeds {
+            sqlx::query("DELETE FROM embeds WHERE id = ?")
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(attachments.into_iter().map(|(_, key)| key).collect())
+    }
+
     pub async fn add_mention(
         message_id: Uuid,
         member_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<(), Error> {
         sqlx::query("INSERT INTO messages_mentions (message_id, member_id) VALUES (?, ?)")
             .bind(message_id)
             .bind(member_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -145,19 +409,19 @@ impl ChannelMessage {
     pub async fn add_role_mention(
         message_id: Uuid,
         role_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<(), Error> {
         sqlx::query("INSERT INTO messages_role_mentions (message_id, role_id) VALUES (?, ?)")
             .bind(message_id)
             .bind(role_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
 
     pub async fn get_message_attachments(
         message_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<Vec<Attachment>, Error> {
         Ok(sqlx::query_as(
             r#"
@@ -173,7 +437,7 @@ impl ChannelMessage {
              "#,
         )
         .bind(message_id)
-        .fetch_all(pool)
+        .fetch_all(conn)
         .await?)
     }
 
@@ -181,27 +445,30 @@ impl ChannelMessage {
         message_id: Uuid,
         filename: &str,
         url: &str,
-        pool: &MySqlPool,
+        file_key: &str,
+        conn: impl Acquire<'_, Database = MySql>,
     ) -> Result<Attachment, Error> {
+        let mut conn = conn.acquire().await?;
         let id = Uuid::new_v4();
         sqlx::query(
             "
             INSERT INTO attachments
-            (id, filename, url)
-            VALUES (?, ?, ?)
+            (id, filename, url, file_key)
+            VALUES (?, ?, ?, ?)
         ",
         )
         .bind(id)
         .bind(filename)
         .bind(url)
-        .execute(pool)
+        .bind(file_key)
+        .execute(&mut *conn)
         .await?;
         sqlx::query(
             "INSERT INTO channel_messages_attachments (message_id, attachment_id) VALUES (?, ?)",
         )
         .bind(message_id)
         .bind(id)
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
         Ok(Attachment {
             id,
@@ -212,7 +479,7 @@ impl ChannelMessage {
 
     pub async fn get_message_embeds(
         message_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<Vec<Embed>, Error> {
         Ok(sqlx::query_as(
             r#"
@@ -228,13 +495,13 @@ impl ChannelMessage {
                     "#,
         )
         .bind(message_id)
-        .fetch_all(pool)
+        .fetch_all(conn)
         .await?)
     }
 
     pub async fn get_message_mentions(
         message_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<Vec<Member>, Error> {
         Ok(sqlx::query_as(
             r#"
@@ -248,13 +515,13 @@ impl ChannelMessage {
                     "#,
         )
         .bind(message_id)
-        .fetch_all(pool)
+        .fetch_all(conn)
         .await?)
     }
 
     pub async fn get_message_role_mentions(
         message_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<Vec<Role>, Error> {
         Ok(sqlx::query_as(
             r#"
@@ -268,14 +535,15 @@ impl ChannelMessage {
                     "#,
         )
         .bind(message_id)
-        .fetch_all(pool)
+        .fetch_all(conn)
         .await?)
     }
 
     pub async fn get_message_reference(
         message_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl Acquire<'_, Database = MySql>,
     ) -> Result<ChannelMessage, Error> {
+        let mut conn = conn.acquire().await?;
         let sql_message: SqlChannelMessage = sqlx::query_as(
             r#"
             SELECT
@@ -288,7 +556,8 @@ impl ChannelMessage {
                 timestamp,
                 edited_timestamp,
                 pinned,
-                mention_everyone
+                mention_everyone,
+                reference_deleted
             FROM
                 channel_messages
             WHERE
@@ -296,20 +565,21 @@ impl ChannelMessage {
             "#,
         )
         .bind(message_id)
-        .fetch_one(pool)
+        .fetch_one(&mut *conn)
         .await?;
 
         let sender: Member =
             sqlx::query_as("SELECT * FROM members_with_profile_fallback WHERE id = ?")
                 .bind(sql_message.sender_id)
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
 
-        let mentions = ChannelMessage::get_message_mentions(sql_message.id, pool).await?;
+        let mentions = ChannelMessage::get_message_mentions(sql_message.id, &mut *conn).await?;
         let mentions_roles =
-            ChannelMessage::get_message_role_mentions(sql_message.id, pool).await?;
-        let attachments = ChannelMessage::get_message_attachments(sql_message.id, pool).await?;
-        let embeds = ChannelMessage::get_message_embeds(sql_message.id, pool).await?;
+            ChannelMessage::get_message_role_mentions(sql_message.id, &mut *conn).await?;
+        let attachments =
+            ChannelMessage::get_message_attachments(sql_message.id, &mut *conn).await?;
+        let embeds = ChannelMessage::get_message_embeds(sql_message.id, &mut *conn).await?;
 
         Ok(ChannelMessage {
             id: sql_message.id,
@@ -322,6 +592,7 @@ impl ChannelMessage {
             edited_timestamp: sql_message.edited_timestamp,
             pinned: sql_message.pinned,
             mention_everyone: sql_message.mention_everyone,
+            reference_deleted: sql_message.reference_deleted,
             mentions,
             mentions_roles,
             attachments,
@@ -333,181 +604,431 @@ impl ChannelMessage {
     pub async fn get_thread_messages(
         thread_id: Uuid,
         member_id: Uuid,
+        page: MessagePage,
         pool: &MySqlPool,
     ) -> Result<Vec<ChannelMessage>, Error> {
-        let messages: Vec<SqlChannelMessage> = sqlx::query_as(
+        let messages = ChannelMessage::get_page("thread_id", thread_id, page, pool).await?;
+        ChannelMessage::load_messages(messages, Some(member_id), pool).await
+    }
+
+    pub async fn get_channel_messages(
+        channel_id: Uuid,
+        member_id: Uuid,
+        page: MessagePage,
+        pool: &MySqlPool,
+    ) -> Result<Vec<ChannelMessage>, Error> {
+        let messages = ChannelMessage::get_page("channel_id", channel_id, page, pool).await?;
+        ChannelMessage::load_messages(messages, Some(member_id), pool).await
+    }
+
+    pub async fn get_channel_window(
+        channel_id: Uuid,
+        member_id: Uuid,
+        message_id: Uuid,
+        limit: Option<u32>,
+        pool: &MySqlPool,
+    ) -> Result<MessageWindow, Error> {
+        ChannelMessage::get_window("channel_id", channel_id, member_id, message_id, limit, pool)
+            .await
+    }
+
+    pub async fn get_thread_window(
+        thread_id: Uuid,
+        member_id: Uuid,
+        message_id: Uuid,
+        limit: Option<u32>,
+        pool: &MySqlPool,
+    ) -> Result<MessageWindow, Error> {
+        ChannelMessage::get_window("thread_id", thread_id, member_id, message_id, limit, pool).await
+    }
+
+    //NOTE: half of the limit on each side of the message, the message has to be in the chat
+    async fn get_window(
+        column: &'static str,
+        id: Uuid,
+        member_id: Uuid,
+        message_id: Uuid,
+        limit: Option<u32>,
+        pool: &MySqlPool,
+    ) -> Result<MessageWindow, Error> {
+        let target = ChannelMessage::get_messages_by_ids(&[message_id], pool)
+            .await?
+            .pop()
+            .filter(|target| match column {
+                "thread_id" => target.thread_id == Some(id),
+                _ => target.channel_id == id,
+            })
+            .ok_or(Error::NotFound)?;
+        let page = MessagePage {
+            limit,
+            ..Default::default()
+        };
+        let half = (page.limit() / 2).max(1);
+        let older = ChannelMessage::get_page(
+            column,
+            id,
+            MessagePage {
+                before: Some(message_id),
+                after: None,
+                limit: Some(half),
+            },
+            pool,
+        )
+        .await?;
+        let newer = ChannelMessage::get_page(
+            column,
+            id,
+            MessagePage {
+                before: None,
+                after: Some(message_id),
+                limit: Some(half),
+            },
+            pool,
+        )
+        .await?;
+        let has_older = older.len() as u32 == half;
+        let has_newer = newer.len() as u32 == half;
+        let messages = older
+            .into_iter()
+            .chain(std::iter::once(target))
+            .chain(newer)
+            .collect();
+        Ok(MessageWindow {
+            messages: ChannelMessage::load_messages(messages, Some(member_id), pool).await?,
+            has_older,
+            has_newer,
+        })
+    }
+
+    async fn get_cursor(
+        message_id: Uuid,
+        pool: &MySqlPool,
+    ) -> Result<(DateTime<Utc>, Uuid), Error> {
+        Ok(
+            sqlx::query_as("SELECT timestamp, id FROM channel_messages WHERE id = ?")
+                .bind(message_id)
+                .fetch_one(pool)
+                .await?,
+        )
+    }
+
+    //NOTE: the messages are ordered by (timestamp, id), the id breaks the ties of the messages
+    //sent in the same second, without a cursor or with only before it's the newest page
+    async fn get_page(
+        column: &'static str,
+        id: Uuid,
+        page: MessagePage,
+        pool: &MySqlPool,
+    ) -> Result<Vec<SqlChannelMessage>, Error> {
+        let mut query_builder = QueryBuilder::new(
             r#"
             SELECT
-                id as id,
+                id,
                 channel_id,
                 thread_id,
                 sender_id,
-                message_reference as message_reference,
+                message_reference,
                 content,
                 timestamp,
                 edited_timestamp,
                 pinned,
-                mention_everyone
+                mention_everyone,
+                reference_deleted
             FROM
                 channel_messages
-            WHERE
-                thread_id = ?
-            ORDER BY
-                timestamp ASC
-            "#,
-        )
-        .bind(thread_id)
-        .fetch_all(pool)
-        .await?;
+            WHERE "#,
+        );
+        query_builder.push(column).push(" = ").push_bind(id);
+        if let Some(before) = page.before {
+            let (timestamp, id) = ChannelMessage::get_cursor(before, pool).await?;
+            query_builder
+                .push(" AND (timestamp < ")
+                .push_bind(timestamp)
+                .push(" OR (timestamp = ")
+                .push_bind(timestamp)
+                .push(" AND id < ")
+                .push_bind(id)
+                .push("))");
+        }
+        if let Some(after) = page.after {
+            let (timestamp, id) = ChannelMessage::get_cursor(after, pool).await?;
+            query_builder
+                .push(" AND (timestamp > ")
+                .push_bind(timestamp)
+                .push(" OR (timestamp = ")
+                .push_bind(timestamp)
+                .push(" AND id > ")
+                .push_bind(id)
+                .push("))");
+        }
+        let newest_first = page.after.is_none();
+        query_builder
+            .push(if newest_first {
+                " ORDER BY timestamp DESC, id DESC LIMIT "
+            } else {
+                " ORDER BY timestamp ASC, id ASC LIMIT "
+            })
+            .push_bind(page.limit());
+        let mut messages: Vec<SqlChannelMessage> =
+            query_builder.build_query_as().fetch_all(pool).await?;
+        if newest_first {
+            messages.reverse();
+        }
+        Ok(messages)
+    }
 
-        let mut full_messages = vec![];
+    //NOTE: hydrates a whole page with one query per relation, the referenced messages are
+    //hydrated together with the page
+    pub async fn load_messages(
+        messages: Vec<SqlChannelMessage>,
+        member_id: Option<Uuid>,
+        conn: impl Acquire<'_, Database = MySql>,
+    ) -> Result<Vec<ChannelMessage>, Error> {
+        if messages.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut conn = conn.acquire().await?;
 
-        for message in messages {
-            let msg_sender: Member =
-                sqlx::query_as("SELECT * FROM members_with_profile_fallback WHERE id = ?")
-                    .bind(message.sender_id)
-                    .fetch_one(pool)
-                    .await?;
+        let reference_ids = messages
+            .iter()
+            .filter_map(|message| message.message_reference)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let references = ChannelMessage::get_messages_by_ids(&reference_ids, &mut *conn).await?;
 
-            let msg_reference = if let Some(reference) = message.message_reference {
-                Some(Box::new(
-                    ChannelMessage::get_message_reference(reference, pool).await?,
-                ))
-            } else {
-                None
-            };
-
-            let msg_mentions = ChannelMessage::get_message_mentions(message.id, pool).await?;
-            let msg_roles_mentions =
-                ChannelMessage::get_message_role_mentions(message.id, pool).await?;
-            let msg_attachments = ChannelMessage::get_message_attachments(message.id, pool).await?;
-            let msg_embeds = ChannelMessage::get_message_embeds(message.id, pool).await?;
-            let msg_reactions =
-                ChannelMessage::get_message_reactions(message.id, member_id, pool).await?;
-            full_messages.push(ChannelMessage {
-                id: message.id,
-                channel_id: message.channel_id,
-                thread_id: message.thread_id,
-                sender: msg_sender,
-                message_reference: msg_reference,
-                content: message.content,
-                timestamp: message.timestamp,
-                edited_timestamp: message.edited_timestamp,
-                pinned: message.pinned,
-                mention_everyone: message.mention_everyone,
-                mentions: msg_mentions,
-                mentions_roles: msg_roles_mentions,
-                attachments: msg_attachments,
-                embeds: msg_embeds,
-                reactions: msg_reactions,
-            });
-        }
+        let page_ids = messages
+            .iter()
+            .map(|message| message.id)
+            .collect::<Vec<_>>();
+        let ids = page_ids
+            .iter()
+            .chain(references.iter().map(|message| &message.id))
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let sender_ids = messages
+            .iter()
+            .chain(references.iter())
+            .map(|message| message.sender_id)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let relations = MessageRelations {
+            senders: ChannelMessage::get_senders(&sender_ids, &mut *conn).await?,
+            mentions: ChannelMessage::get_messages_mentions(&ids, &mut *conn).await?,
+            mentions_roles: ChannelMessage::get_messages_role_mentions(&ids, &mut *conn).await?,
+            attachments: ChannelMessage::get_messages_attachments(&ids, &mut *conn).await?,
+            embeds: ChannelMessage::get_messages_embeds(&ids, &mut *conn).await?,
+            reactions: match member_id {
+                Some(member_id) => {
+                    ChannelMessage::get_messages_reactions(&page_ids, member_id, &mut *conn).await?
+                }
+                None => HashMap::new(),
+            },
+        };
+
+        let references = references
+            .into_iter()
+            .map(|reference| {
+                let reference = relations.assemble(reference, None, false)?;
+                Ok((reference.id, reference))
+            })
+            .collect::<Result<HashMap<_, _>, Error>>()?;
 
-        Ok(full_messages)
+        messages
+            .into_iter()
+            .map(|message| {
+                let reference = message
+                    .message_reference
+                    .and_then(|id| references.get(&id).cloned())
+                    .map(Box::new);
+                relations.assemble(message, reference, true)
+            })
+            .collect()
     }
 
-    pub async fn get_channel_messages(
-        channel_id: Uuid,
-        member_id: Uuid,
-        pool: &MySqlPool,
-    ) -> Result<Vec<ChannelMessage>, Error> {
-        let messages: Vec<SqlChannelMessage> = sqlx::query_as(
+    fn push_ids(query_builder: &mut QueryBuilder<'_, MySql>, ids: &[Uuid]) {
+        query_builder.push(" IN (");
+        let mut separated = query_builder.separated(", ");
+        for id in ids {
+            separated.push_bind(*id);
+        }
+        separated.push_unseparated(")");
+    }
+
+    async fn get_messages_by_ids(
+        ids: &[Uuid],
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<Vec<SqlChannelMessage>, Error> {
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut query_builder = QueryBuilder::new(
             r#"
             SELECT
-                id as id,
+                id,
                 channel_id,
                 thread_id,
                 sender_id,
-                message_reference as message_reference,
+                message_reference,
                 content,
                 timestamp,
                 edited_timestamp,
                 pinned,
-                mention_everyone
+                mention_everyone,
+                reference_deleted
             FROM
                 channel_messages
             WHERE
-                channel_id = ?
-            ORDER BY
-                timestamp ASC
-            "#,
-        )
-        .bind(channel_id)
-        .fetch_all(pool)
-        .await?;
+                id"#,
+        );
+        ChannelMessage::push_ids(&mut query_builder, ids);
+        Ok(query_builder.build_query_as().fetch_all(conn).await?)
+    }
 
-        let mut full_messages = vec![];
+    async fn get_senders(
+        ids: &[Uuid],
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<HashMap<Uuid, Member>, Error> {
+        let mut query_builder =
+            QueryBuilder::new("SELECT * FROM members_with_profile_fallback WHERE id");
+        ChannelMessage::push_ids(&mut query_builder, ids);
+        let members: Vec<Member> = query_builder.build_query_as().fetch_all(conn).await?;
+        Ok(members
+            .into_iter()
+            .map(|member| (member.id, member))
+            .collect())
+    }
 
-        for message in messages {
-            let msg_sender: Member =
-                sqlx::query_as("SELECT * FROM members_with_profile_fallback WHERE id = ?")
-                    .bind(message.sender_id)
-                    .fetch_one(pool)
-                    .await?;
+    async fn get_messages_mentions(
+        ids: &[Uuid],
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<HashMap<Uuid, Vec<Member>>, Error> {
+        let mut query_builder = QueryBuilder::new(
+            r#"
+            SELECT
+                mm.message_id,
+                m.*
+            FROM
+                members_with_profile_fallback m
+                INNER JOIN messages_mentions mm ON m.id = mm.member_id
+            WHERE
+                mm.message_id"#,
+        );
+        ChannelMessage::push_ids(&mut query_builder, ids);
+        let rows: Vec<MessageRelation<Member>> =
+            query_builder.build_query_as().fetch_all(conn).await?;
+        Ok(MessageRelation::group(rows))
+    }
 
-            let msg_reference = if let Some(reference) = message.message_reference {
-                let reference = ChannelMessage::get_message_reference(reference, pool).await;
-                debug!("{reference:?}");
-                Some(Box::new(reference?))
-            } else {
-                None
-            };
-
-            let msg_mentions = ChannelMessage::get_message_mentions(message.id, pool).await?;
-            let msg_roles_mentions =
-                ChannelMessage::get_message_role_mentions(message.id, pool).await?;
-            let msg_attachments = ChannelMessage::get_message_attachments(message.id, pool).await?;
-            let msg_embeds = ChannelMessage::get_message_embeds(message.id, pool).await?;
-            let msg_reactions =
-                ChannelMessage::get_message_reactions(message.id, member_id, pool).await?;
-            full_messages.push(ChannelMessage {
-                id: message.id,
-                channel_id: message.channel_id,
-                thread_id: message.thread_id,
-                sender: msg_sender,
-                message_reference: msg_reference,
-                content: message.content,
-                timestamp: message.timestamp,
-                edited_timestamp: message.edited_timestamp,
-                pinned: message.pinned,
-                mention_everyone: message.mention_everyone,
-                mentions: msg_mentions,
-                mentions_roles: msg_roles_mentions,
-                attachments: msg_attachments,
-                embeds: msg_embeds,
-                reactions: msg_reactions,
-            });
-        }
+    async fn get_messages_role_mentions(
+        ids: &[Uuid],
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<HashMap<Uuid, Vec<Role>>, Error> {
+        let mut query_builder = QueryBuilder::new(
+            r#"
+            SELECT
+                mrm.message_id,
+                r.id, r.name, r.server_id, r.can_edit, r.priority
+            FROM
+                roles r
+                INNER JOIN messages_role_mentions mrm ON r.id = mrm.role_id
+            WHERE
+                mrm.message_id"#,
+        );
+        ChannelMessage::push_ids(&mut query_builder, ids);
+        let rows: Vec<MessageRelation<Role>> =
+            query_builder.build_query_as().fetch_all(conn).await?;
+        Ok(MessageRelation::group(rows))
+    }
 
-        Ok(full_messages)
+    async fn get_messages_attachments(
+        ids: &[Uuid],
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<HashMap<Uuid, Vec<Attachment>>, Error> {
+        let mut query_builder = QueryBuilder::new(
+            r#"
+            SELECT
+                cma.message_id,
+                a.id,
+                a.filename,
+                a.url
+            FROM
+                attachments a
+                INNER JOIN channel_messages_attachments cma ON a.id = cma.attachment_id
+            WHERE
+                cma.message_id"#,
+        );
+        ChannelMessage::push_ids(&mut query_builder, ids);
+        let rows: Vec<MessageRelation<Attachment>> =
+            query_builder.build_query_as().fetch_all(conn).await?;
+        Ok(MessageRelation::group(rows))
     }
 
-    async fn get_message_reactions(
-        message_id: Uuid,
-        member_id: Uuid,
-        pool: &MySqlPool,
-    ) -> Result<Vec<Reaction>, Error> {
-        let reactions = sqlx::query_as::<_, SqlReaction>(
-            "
-            SELECT re.* from reactions re where re.message_id = ?
-        ",
-        )
-        .bind(message_id)
-        .fetch_all(pool)
-        .await;
+    async fn get_messages_embeds(
+        ids: &[Uuid],
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<HashMap<Uuid, Vec<Embed>>, Error> {
+        let mut query_builder = QueryBuilder::new(
+            r#"
+            SELECT
+                cme.message_id,
+                e.id,
+                e.url,
+                e.data
+            FROM
+                embeds e
+                INNER JOIN channel_messages_embeds cme ON e.id = cme.embeds_id
+            WHERE
+                cme.message_id"#,
+        );
+        ChannelMessage::push_ids(&mut query_builder, ids);
+        let rows: Vec<MessageRelation<Embed>> =
+            query_builder.build_query_as().fetch_all(conn).await?;
+        Ok(MessageRelation::group(rows))
+    }
 
-        let mut full_reaction = vec![];
-        for reaction in reactions? {
-            let me = ChannelMessage::check_member_in_reaction(member_id, reaction.id, pool).await?;
-            full_reaction.push(Reaction {
-                id: reaction.id,
-                message_id: reaction.message_id,
-                name: String::from_utf8(reaction.name)?,
-                counter: reaction.counter,
-                me,
+    async fn get_messages_reactions(
+        ids: &[Uuid],
+        member_id: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<HashMap<Uuid, Vec<Reaction>>, Error> {
+        let mut query_builder = QueryBuilder::new(
+            r#"
+            SELECT
+                re.id,
+                re.message_id,
+                re.name,
+                re.counter,
+                EXISTS (
+                  SELECT 1
+                  FROM reaction_members rm
+                  WHERE rm.reaction_id = re.id AND rm.member_id = "#,
+        );
+        query_builder.push_bind(member_id).push(
+            r#"
+                ) AS me
+            FROM
+                reactions re
+            WHERE
+                re.message_id"#,
+        );
+        ChannelMessage::push_ids(&mut query_builder, ids);
+        let rows: Vec<SqlMemberReaction> = query_builder.build_query_as().fetch_all(conn).await?;
+        let mut reactions: HashMap<Uuid, Vec<Reaction>> = HashMap::new();
+        for row in rows {
+            reactions.entry(row.message_id).or_default().push(Reaction {
+                id: row.id,
+                message_id: row.message_id,
+                name: String::from_utf8(row.name)?,
+                counter: row.counter,
+                me: row.me,
             });
         }
-        Ok(full_reaction)
+        Ok(reactions)
     }
 
     pub async fn add_channel_message(
@@ -515,8 +1036,9 @@ impl ChannelMessage {
         member_id: Uuid,
         message: &str,
         msg_reference: Option<Uuid>,
-        pool: &MySqlPool,
+        conn: impl Acquire<'_, Database = MySql>,
     ) -> Result<ChannelMessage, Error> {
+        let mut conn = conn.acquire().await?;
         let id = Uuid::new_v4();
         if let Some(reference) = msg_reference {
             sqlx::query(
@@ -531,7 +1053,7 @@ impl ChannelMessage {
             .bind(member_id)
             .bind(message)
             .bind(reference)
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
         } else {
             sqlx::query(
@@ -545,7 +1067,7 @@ impl ChannelMessage {
             .bind(channel_id)
             .bind(member_id)
             .bind(message)
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
         }
         let sql_message: SqlChannelMessage = sqlx::query_as(
@@ -560,7 +1082,8 @@ impl ChannelMessage {
                 timestamp,
                 edited_timestamp,
                 pinned,
-                mention_everyone
+                mention_everyone,
+                reference_deleted
             FROM
                 channel_messages
             WHERE
@@ -568,16 +1091,16 @@ impl ChannelMessage {
             "#,
         )
         .bind(id)
-        .fetch_one(pool)
+        .fetch_one(&mut *conn)
         .await?;
         let sender: Member =
             sqlx::query_as("SELECT mv.* FROM members_with_profile_fallback mv WHERE mv.id = ?")
                 .bind(sql_message.sender_id)
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?;
         let message_reference = if let Some(id) = msg_reference {
             Some(Box::new(
-                ChannelMessage::get_message_reference(id, pool).await?,
+                ChannelMessage::get_message_reference(id, &mut *conn).await?,
             ))
         } else {
             None
@@ -593,6 +1116,7 @@ impl ChannelMessage {
             edited_timestamp: sql_message.edited_timestamp,
             pinned: sql_message.pinned,
             mention_everyone: sql_message.mention_everyone,
+            reference_deleted: sql_message.reference_deleted,
             mentions: vec![],
             mentions_roles: vec![],
             attachments: vec![],
@@ -617,7 +1141,8 @@ impl ChannelMessage {
                 timestamp,
                 edited_timestamp,
                 pinned,
-                mention_everyone
+                mention_everyone,
+                reference_deleted
             FROM
                 channel_messages
             WHERE
@@ -632,76 +1157,52 @@ impl ChannelMessage {
         .fetch_all(pool)
         .await?;
 
-        let mut full_messages = vec![];
-
-        for message in messages {
-            let msg_sender: Member =
-                sqlx::query_as("SELECT * FROM members_with_profile_fallback WHERE id = ?")
-                    .bind(message.sender_id)
-                    .fetch_one(pool)
-                    .await?;
-
-            let msg_reference = if let Some(reference) = message.message_reference {
-                Some(Box::new(
-                    ChannelMessage::get_message_reference(reference, pool).await?,
-                ))
-            } else {
-                None
-            };
-
-            let msg_mentions = ChannelMessage::get_message_mentions(message.id, pool).await?;
-            let msg_roles_mentions =
-                ChannelMessage::get_message_role_mentions(message.id, pool).await?;
-            let msg_attachments = ChannelMessage::get_message_attachments(message.id, pool).await?;
-            let msg_embeds = ChannelMessage::get_message_embeds(message.id, pool).await?;
-            full_messages.push(ChannelMessage {
-                id: message.id,
-                channel_id: message.channel_id,
-                thread_id: message.thread_id,
-                sender: msg_sender,
-                message_reference: msg_reference,
-                content: message.content,
-                timestamp: message.timestamp,
-                edited_timestamp: message.edited_timestamp,
-                pinned: message.pinned,
-                mention_everyone: message.mention_everyone,
-                mentions: msg_mentions,
-                mentions_roles: msg_roles_mentions,
-                attachments: msg_attachments,
-                embeds: msg_embeds,
-                reactions: vec![],
-            });
-        }
-
-        Ok(full_messages)
+        ChannelMessage::load_messages(messages, None, pool).await
     }
 
-    pub async fn inc_reaction_counter(reaction_id: Uuid, pool: &MySqlPool) -> Result<(), Error> {
+    pub async fn inc_reaction_counter(
+        reaction_id: Uuid,
+        conn: impl Acquire<'_, Database = MySql>,
+    ) -> Result<u32, Error> {
+        let mut conn = conn.acquire().await?;
         sqlx::query("UPDATE reactions SET counter = counter + 1 WHERE id = ?")
             .bind(reaction_id)
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
-        Ok(())
+        Ok(
+            sqlx::query_as::<_, (u32,)>("SELECT counter FROM reactions WHERE id = ?")
+                .bind(reaction_id)
+                .fetch_one(&mut *conn)
+                .await?
+                .0,
+        )
     }
 
-    pub async fn dec_reaction_counter(reaction_id: Uuid, pool: &MySqlPool) -> Result<u32, Error> {
+    pub async fn dec_reaction_counter(
+        reaction_id: Uuid,
+        conn: impl Acquire<'_, Database = MySql>,
+    ) -> Result<u32, Error> {
+        let mut conn = conn.acquire().await?;
         sqlx::query("UPDATE reactions SET counter = counter - 1 WHERE id = ?")
             .bind(reaction_id)
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
         Ok(
             sqlx::query_as::<_, (u32,)>("SELECT counter FROM reactions WHERE id = ?")
                 .bind(reaction_id)
-                .fetch_one(pool)
+                .fetch_one(&mut *conn)
                 .await?
                 .0,
         )
     }
 
-    pub async fn delete_reaction(reaction_id: Uuid, pool: &MySqlPool) -> Result<(), Error> {
+    pub async fn delete_reaction(
+        reaction_id: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<(), Error> {
         sqlx::query("DELETE FROM reactions WHERE id = ?")
             .bind(reaction_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -709,12 +1210,12 @@ impl ChannelMessage {
     pub async fn add_member_to_reaction(
         reaction_id: Uuid,
         member_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<(), Error> {
         sqlx::query("INSERT INTO reaction_members (reaction_id, member_id) VALUES (?, ?)")
             .bind(reaction_id)
             .bind(member_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -722,12 +1223,12 @@ impl ChannelMessage {
     pub async fn remove_member_to_reaction(
         reaction_id: Uuid,
         member_id: Uuid,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<(), Error> {
         sqlx::query("DELETE FROM reaction_members WHERE reaction_id = ? AND member_id = ?")
             .bind(reaction_id)
             .bind(member_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -756,7 +1257,7 @@ impl ChannelMessage {
     pub async fn create_reaction(
         message_id: Uuid,
         name: &str,
-        pool: &MySqlPool,
+        conn: impl MySqlExecutor<'_>,
     ) -> Result<Reaction, Error> {
         let reaction_id = Uuid::new_v4();
         let res = sqlx::query(
@@ -766,7 +1267,7 @@ impl ChannelMessage {
         .bind(message_id)
         .bind(name)
         .bind(0)
-        .execute(pool)
+        .execute(conn)
         .await;
         res?;
         Ok(Reaction {
@@ -3,6 +3,7 @@ pub mod channel;
 pub mod member;
 pub mod message;
 pub mod role;
+pub mod search;
 pub mod server;
 pub mod thread;
 pub mod user;
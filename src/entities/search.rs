@@ -0,0 +1,579 @@
+use cfg_if::cfg_if;
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::message::ChannelMessage;
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use chrono::{NaiveTime, Utc, DateTime};
+        use sqlx::mysql::MySql;
+        use sqlx::{FromRow, MySqlPool, QueryBuilder};
+        use uuid::Uuid;
+        use super::message::SqlChannelMessage;
+        use super::Error;
+    }
+}
+
+pub const SEARCH_PAGE_SIZE: u32 = 25;
+const SNIPPET_LENGTH: usize = 160;
+const SNIPPET_CONTEXT: usize = 40;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HasFilter {
+    Attachment,
+    Embed,
+    Link,
+}
+
+//NOTE: a parsed search query, `from:`, `in:` and `mentions:` take names, quote them when they
+//have spaces, like `from:"john doe"`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct MessageSearch {
+    pub terms: Vec<String>,
+    pub from: Vec<String>,
+    pub channels: Vec<String>,
+    pub mentions: Vec<String>,
+    pub has: Vec<HasFilter>,
+    pub pinned: Option<bool>,
+    pub before: Option<NaiveDate>,
+    pub after: Option<NaiveDate>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlight: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub message: ChannelMessage,
+    pub channel_name: String,
+    pub thread_name: Option<String>,
+    pub snippet: Vec<SnippetPart>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    pub total: u64,
+    pub offset: u32,
+    pub has_more: bool,
+}
+
+impl MessageSearch {
+    pub fn parse(query: &str) -> Self {
+        let mut search = MessageSearch::default();
+        for token in MessageSearch::tokenize(query) {
+            //NOTE: a quoted key is text, `"from:john"` searches for the words
+            let parsed = match token.split_once(':') {
+                Some((key, value)) if !key.contains('"') && !value.is_empty() => {
+                    search.add_filter(&key.to_lowercase(), value.trim_matches('"'))
+                }
+                _ => false,
+            };
+            if !parsed {
+                let term = token.trim_matches('"').to_string();
+                if !term.is_empty() {
+                    search.terms.push(term);
+                }
+            }
+        }
+        search
+    }
+
+    fn add_filter(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "from" => self.from.push(value.trim_start_matches('@').to_string()),
+            "in" => self
+                .channels
+                .push(value.trim_start_matches('#').to_string()),
+            "mentions" => self
+                .mentions
+                .push(value.trim_start_matches('@').to_string()),
+            "has" => match value.to_lowercase().as_str() {
+                "attachment" | "file" => self.has.push(HasFilter::Attachment),
+                "embed" => self.has.push(HasFilter::Embed),
+                "link" => self.has.push(HasFilter::Link),
+                _ => return false,
+            },
+            "pinned" => match value.to_lowercase().as_str() {
+                "true" | "yes" => self.pinned = Some(true),
+                "false" | "no" => self.pinned = Some(false),
+                _ => return false,
+            },
+            "before" => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                Ok(date) => self.before = Some(date),
+                Err(_) => return false,
+            },
+            "after" => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                Ok(date) => self.after = Some(date),
+                Err(_) => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    //NOTE: splits on whitespace outside of quotes, the quotes are kept in the tokens
+    fn tokenize(query: &str) -> Vec<String> {
+        let mut tokens = vec![];
+        let mut token = String::new();
+        let mut in_quotes = false;
+        for char in query.chars() {
+            if char == '"' {
+                in_quotes = !in_quotes;
+            }
+            if char.is_whitespace() && !in_quotes {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            } else {
+                token.push(char);
+            }
+        }
+        if !token.is_empty() {
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &MessageSearch::default()
+    }
+
+    //NOTE: every word is required and matches as a prefix, quoted terms are phrases,
+    //words shorter than innodb_ft_min_token_size or stopwords are ignored by mysql
+    pub fn fulltext(&self) -> Option<String> {
+        let terms = self
+            .terms
+            .iter()
+            .filter_map(|term| {
+                let term = term
+                    .chars()
+                    .filter(|char| !"+-<>()~*\"@".contains(*char))
+                    .collect::<String>();
+                let term = term.trim();
+                if term.is_empty() {
+                    None
+                } else if term.contains(char::is_whitespace) {
+                    Some(format!("+\"{term}\""))
+                } else {
+                    Some(format!("+{term}*"))
+                }
+            })
+            .collect::<Vec<_>>();
+        (!terms.is_empty()).then(|| terms.join(" "))
+    }
+
+    pub fn snippet(&self, content: &str) -> Vec<SnippetPart> {
+        let words = self
+            .terms
+            .iter()
+            .flat_map(|term| term.split_whitespace())
+            .map(regex::escape)
+            .collect::<Vec<_>>();
+        let matches = if words.is_empty() {
+            vec![]
+        } else {
+            Regex::new(&format!(r"(?i)\b({})\w*", words.join("|")))
+                .map(|regex| {
+                    regex
+                        .find_iter(content)
+                        .map(|found| found.range())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+
+        let chars = content
+            .char_indices()
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        let first = matches
+            .first()
+            .map(|range| chars.partition_point(|idx| *idx < range.start))
+            .unwrap_or_default();
+        let start_char = first.saturating_sub(SNIPPET_CONTEXT);
+        let end_char = (start_char + SNIPPET_LENGTH).min(chars.len());
+        let start = chars.get(start_char).copied().unwrap_or(content.len());
+        let end = chars.get(end_char).copied().unwrap_or(content.len());
+
+        let mut parts = vec![];
+        let mut cursor = start;
+        if start > 0 {
+            parts.push(SnippetPart {
+                text: "…".into(),
+                highlight: false,
+            });
+        }
+        for range in matches {
+            if range.end <= cursor || range.start >= end {
+                continue;
+            }
+            let range_start = range.start.max(cursor);
+            let range_end = range.end.min(end);
+            if range_start > cursor {
+                parts.push(SnippetPart {
+                    text: content[cursor..range_start].to_string(),
+                    highlight: false,
+                });
+            }
+            parts.push(SnippetPart {
+                text: content[range_start..range_end].to_string(),
+                highlight: true,
+            });
+            cursor = range_end;
+        }
+        if cursor < end {
+            parts.push(SnippetPart {
+                text: content[cursor..end].to_string(),
+                highlight: false,
+            });
+        }
+        if end < content.len() {
+            parts.push(SnippetPart {
+                text: "…".into(),
+                highlight: false,
+            });
+        }
+        parts
+    }
+}
+
+#[cfg(feature = "ssr")]
+#[derive(FromRow)]
+struct SqlSearchHit {
+    #[sqlx(flatten)]
+    message: SqlChannelMessage,
+    channel_name: String,
+    thread_name: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+impl MessageSearch {
+    //NOTE: only the channels and threads of the server are searched, the caller has to be a member
+    pub async fn search(
+        &self,
+        server_id: Uuid,
+        member_id: Uuid,
+        offset: u32,
+        pool: &MySqlPool,
+    ) -> Result<SearchPage, Error> {
+        let mut query_builder = QueryBuilder::new("SELECT COUNT(*)");
+        self.push_conditions(&mut query_builder, server_id);
+        let (total,): (i64,) = query_builder.build_query_as().fetch_one(pool).await?;
+
+        let mut query_builder = QueryBuilder::new(
+            r#"
+            SELECT
+                cm.id,
+                cm.channel_id,
+                cm.thread_id,
+                cm.sender_id,
+                cm.message_reference,
+                cm.content,
+                cm.timestamp,
+                cm.edited_timestamp,
+                cm.pinned,
+                cm.mention_everyone,
+                cm.reference_deleted,
+                c.name AS channel_name,
+                t.name AS thread_name"#,
+        );
+        self.push_conditions(&mut query_builder, server_id);
+        query_builder
+            .push(" ORDER BY cm.timestamp DESC, cm.id DESC LIMIT ")
+            .push_bind(SEARCH_PAGE_SIZE)
+            .push(" OFFSET ")
+            .push_bind(offset);
+        let hits: Vec<SqlSearchHit> = query_builder.build_query_as().fetch_all(pool).await?;
+
+        let (names, messages): (Vec<_>, Vec<_>) = hits
+            .into_iter()
+            .map(|hit| ((hit.channel_name, hit.thread_name), hit.message))
+            .unzip();
+        let messages = ChannelMessage::load_messages(messages, Some(member_id), pool).await?;
+        let results = messages
+            .into_iter()
+            .zip(names)
+            .map(|(message, (channel_name, thread_name))| SearchResult {
+                snippet: self.snippet(&message.content),
+                message,
+                channel_name,
+                thread_name,
+            })
+            .collect::<Vec<_>>();
+
+        let total = total as u64;
+        Ok(SearchPage {
+            has_more: offset as u64 + (results.len() as u64) < total,
+            results,
+            total,
+            offset,
+        })
+    }
+
+    fn push_conditions(&self, query_builder: &mut QueryBuilder<'_, MySql>, server_id: Uuid) {
+        query_builder
+            .push(
+                r#"
+            FROM
+                channel_messages cm
+                INNER JOIN channels c ON c.id = cm.channel_id
+                LEFT JOIN threads t ON t.id = cm.thread_id
+            WHERE
+                c.server_id = "#,
+            )
+            .push_bind(server_id);
+
+        if let Some(fulltext) = self.fulltext() {
+            query_builder
+                .push(" AND MATCH (cm.content) AGAINST (")
+                .push_bind(fulltext)
+                .push(" IN BOOLEAN MODE)");
+        }
+        if !self.from.is_empty() {
+            query_builder
+                .push(" AND cm.sender_id IN (SELECT m.id FROM members_with_profile_fallback m WHERE m.server_id = ")
+                .push_bind(server_id)
+                .push(" AND m.name");
+            MessageSearch::push_names(query_builder, &self.from);
+            query_builder.push(")");
+        }
+        if !self.channels.is_empty() {
+            query_builder.push(" AND (c.name");
+            MessageSearch::push_names(query_builder, &self.channels);
+            query_builder.push(" OR t.name");
+            MessageSearch::push_names(query_builder, &self.channels);
+            query_builder.push(")");
+        }
+        if !self.mentions.is_empty() {
+            query_builder.push(
+                " AND EXISTS (SELECT 1 FROM messages_mentions mm INNER JOIN members_with_profile_fallback m ON m.id = mm.member_id WHERE mm.message_id = cm.id AND m.name",
+            );
+            MessageSearch::push_names(query_builder, &self.mentions);
+            query_builder.push(")");
+        }
+        for has in &self.has {
+            query_builder.push(match has {
+                HasFilter::Attachment => " AND EXISTS (SELECT 1 FROM channel_messages_attachments cma WHERE cma.message_id = cm.id)",
+                HasFilter::Embed => " AND EXISTS (SELECT 1 FROM channel_messages_embeds cme WHERE cme.message_id = cm.id)",
+                HasFilter::Link => " AND (cm.content LIKE '%http://%' OR cm.content LIKE '%https://%')",
+            });
+        }
+        if let Some(pinned) = self.pinned {
+            query_builder.push(" AND cm.pinned = ").push_bind(pinned);
+        }
+        if let Some(before) = self.before {
+            query_builder
+                .push(" AND cm.timestamp < ")
+                .push_bind(MessageSearch::start_of(before));
+        }
+        if let Some(after) = self.after.and_then(|after| after.succ_opt()) {
+            query_builder
+                .push(" AND cm.timestamp >= ")
+                .push_bind(MessageSearch::start_of(after));
+        }
+    }
+
+    fn push_names(query_builder: &mut QueryBuilder<'_, MySql>, names: &[String]) {
+        query_builder.push(" IN (");
+        let mut separated = query_builder.separated(", ");
+        for name in names {
+            separated.push_bind(name.clone());
+        }
+        separated.push_unseparated(")");
+    }
+
+    fn start_of(date: NaiveDate) -> DateTime<Utc> {
+        date.and_time(NaiveTime::MIN).and_utc()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(parts: &[SnippetPart]) -> String {
+        parts.iter().map(|part| part.text.as_str()).collect()
+    }
+
+    fn highlighted(parts: &[SnippetPart]) -> Vec<&str> {
+        parts
+            .iter()
+            .filter(|part| part.highlight)
+            .map(|part| part.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_keeps_quoted_spaces() {
+        assert_eq!(
+            MessageSearch::tokenize(r#"  hello "big world"   from:"john doe" "#),
+            vec!["hello", r#""big world""#, r#"from:"john doe""#]
+        );
+    }
+
+    #[test]
+    fn parse_filters() {
+        let search = MessageSearch::parse(
+            r#"deploy from:@john from:"jane doe" in:#general mentions:@ana has:file has:link pinned:yes before:2025-02-01 after:2025-01-01"#,
+        );
+        assert_eq!(search.terms, vec!["deploy"]);
+        assert_eq!(search.from, vec!["john", "jane doe"]);
+        assert_eq!(search.channels, vec!["general"]);
+        assert_eq!(search.mentions, vec!["ana"]);
+        assert_eq!(search.has, vec![HasFilter::Attachment, HasFilter::Link]);
+        assert_eq!(search.pinned, Some(true));
+        assert_eq!(search.before, NaiveDate::from_ymd_opt(2025, 2, 1));
+        assert_eq!(search.after, NaiveDate::from_ymd_opt(2025, 1, 1));
+    }
+
+    #[test]
+    fn parse_keys_are_case_insensitive() {
+        let search = MessageSearch::parse("FROM:john Pinned:NO has:Embed");
+        assert_eq!(search.from, vec!["john"]);
+        assert_eq!(search.pinned, Some(false));
+        assert_eq!(search.has, vec![HasFilter::Embed]);
+        assert!(search.terms.is_empty());
+    }
+
+    #[test]
+    fn parse_keeps_invalid_filters_as_text() {
+        let search = MessageSearch::parse(
+            r#"has:video pinned:maybe before:2025-13-01 after:yesterday color:red from: "from:john""#,
+        );
+        assert_eq!(
+            search.terms,
+            vec![
+                "has:video",
+                "pinned:maybe",
+                "before:2025-13-01",
+                "after:yesterday",
+                "color:red",
+                "from:",
+                "from:john",
+            ]
+        );
+        assert!(search.has.is_empty());
+        assert_eq!(search.pinned, None);
+        assert_eq!(search.before, None);
+        assert_eq!(search.after, None);
+        assert!(search.from.is_empty());
+    }
+
+    #[test]
+    fn parse_empty_queries() {
+        assert!(MessageSearch::parse("").is_empty());
+        assert!(MessageSearch::parse("   \t ").is_empty());
+        assert!(MessageSearch::parse(r#""""#).is_empty());
+        assert_eq!(MessageSearch::parse("").fulltext(), None);
+    }
+
+    #[test]
+    fn parse_filter_only_queries() {
+        let search = MessageSearch::parse("from:john has:attachment");
+        assert!(!search.is_empty());
+        assert!(search.terms.is_empty());
+        assert_eq!(search.fulltext(), None);
+        assert_eq!(
+            search.snippet("just a message"),
+            vec![SnippetPart {
+                text: "just a message".into(),
+                highlight: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn fulltext_requires_every_word_as_a_prefix() {
+        let search = MessageSearch::parse(r#"deploy "release notes""#);
+        assert_eq!(
+            search.fulltext().as_deref(),
+            Some(r#"+deploy* +"release notes""#)
+        );
+    }
+
+    #[test]
+    fn fulltext_strips_boolean_operators() {
+        let search = MessageSearch::parse(r#"+must -not <less >more (group) ~tilde star* @at"#);
+        assert_eq!(
+            search.fulltext().as_deref(),
+            Some("+must* +not* +less* +more* +group* +tilde* +star* +at*")
+        );
+
+        let search = MessageSearch::parse(r#""(a) ~b" c"d"#);
+        assert_eq!(search.fulltext().as_deref(), Some(r#"+"a b" +cd*"#));
+    }
+
+    #[test]
+    fn fulltext_skips_operator_only_terms() {
+        let search = MessageSearch::parse(r#"+ - * () ~ <> "" deploy"#);
+        assert_eq!(search.fulltext().as_deref(), Some("+deploy*"));
+        assert_eq!(MessageSearch::parse("+-*()~<>@").fulltext(), None);
+    }
+
+    #[test]
+    fn snippet_highlights_prefix_matches() {
+        let search = MessageSearch::parse("run hello");
+        let parts = search.snippet("Say Hello, we're running late");
+        assert_eq!(text(&parts), "Say Hello, we're running late");
+        assert_eq!(highlighted(&parts), vec!["Hello", "running"]);
+    }
+
+    #[test]
+    fn snippet_ignores_regex_characters() {
+        let search = MessageSearch::parse("a.b");
+        let parts = search.snippet("axb a.b");
+        assert_eq!(highlighted(&parts), vec!["a.b"]);
+    }
+
+    #[test]
+    fn snippet_cuts_around_the_first_match() {
+        let content = format!("{} needle {}", "a ".repeat(100), "b ".repeat(100));
+        let parts = MessageSearch::parse("needle").snippet(&content);
+        assert_eq!(parts.first().map(|part| part.text.as_str()), Some("…"));
+        assert_eq!(parts.last().map(|part| part.text.as_str()), Some("…"));
+        assert_eq!(highlighted(&parts), vec!["needle"]);
+        let window = text(&parts[1..parts.len() - 1]);
+        assert_eq!(window.chars().count(), SNIPPET_LENGTH);
+        assert!(content.contains(&window));
+        assert_eq!(
+            window
+                .find("needle")
+                .map(|idx| window[..idx].chars().count()),
+            Some(SNIPPET_CONTEXT)
+        );
+    }
+
+    #[test]
+    fn snippet_cuts_multibyte_text_on_char_boundaries() {
+        let content = format!("{} café {}", "é".repeat(100), "ü".repeat(200));
+        let parts = MessageSearch::parse("CAFÉ").snippet(&content);
+        assert_eq!(highlighted(&parts), vec!["café"]);
+        assert_eq!(parts.first().map(|part| part.text.as_str()), Some("…"));
+        assert_eq!(parts.last().map(|part| part.text.as_str()), Some("…"));
+        let window = text(&parts[1..parts.len() - 1]);
+        assert_eq!(window.chars().count(), SNIPPET_LENGTH);
+        assert!(content.contains(&window));
+
+        let emoji = "🦀".repeat(SNIPPET_LENGTH + 10);
+        let parts = MessageSearch::parse("rust").snippet(&emoji);
+        assert_eq!(text(&parts), format!("{}…", "🦀".repeat(SNIPPET_LENGTH)));
+    }
+
+    #[test]
+    fn snippet_of_a_short_message_is_the_whole_message() {
+        let parts = MessageSearch::parse("ünï").snippet("ünïcode");
+        assert_eq!(
+            parts,
+            vec![SnippetPart {
+                text: "ünïcode".into(),
+                highlight: true,
+            },]
+        );
+    }
+}
@@ -42,7 +42,7 @@ impl Server {
         user_id: Uuid,
         pool: &MySqlPool,
     ) -> Result<bool, Error> {
-        Ok(sqlx::query_as::<_, (bool,)>("SELECT EXIST(SELECT * FROM servers LEFT JOIN members ON servers.id = members.server_id WHERE members.user_id = ? AND servers.id = ?)")
+        Ok(sqlx::query_as::<_, (bool,)>("SELECT EXISTS(SELECT * FROM servers LEFT JOIN members ON servers.id = members.server_id WHERE members.user_id = ? AND servers.id = ?)")
                     .bind(user_id)
                     .bind(server_id)
                     .fetch_one(pool)
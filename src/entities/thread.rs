@@ -7,7 +7,7 @@ cfg_if! {
     if #[cfg(feature = "ssr")] {
         use super::member::Member;
         use super::Error;
-        use sqlx::{FromRow, MySqlPool};
+        use sqlx::{FromRow, MySqlExecutor, MySqlPool};
     }
 }
 
@@ -75,10 +75,13 @@ WHERE t.id = ?
         .fetch_all(pool)
         .await?)
     }
-    pub async fn delete_members(thread_id: Uuid, pool: &MySqlPool) -> Result<(), Error> {
+    pub async fn delete_members(
+        thread_id: Uuid,
+        conn: impl MySqlExecutor<'_>,
+    ) -> Result<(), Error> {
         sqlx::query("Delete FROM threads_members WHERE threads_members.thread_id = ?")
             .bind(thread_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -137,6 +140,18 @@ WHERE t.id = ?
         Ok(())
     }
 
+    pub async fn user_has_access(
+        thread_id: Uuid,
+        user_id: Uuid,
+        pool: &MySqlPool,
+    ) -> Result<bool, Error> {
+        Ok(sqlx::query_as::<_, (bool,)>("SELECT EXISTS(SELECT 1 FROM threads JOIN channels ON channels.id = threads.channel_id JOIN members ON members.server_id = channels.server_id WHERE threads.id = ? AND members.user_id = ?)")
+                    .bind(thread_id)
+                    .bind(user_id)
+                    .fetch_one(pool)
+                    .await?.0)
+    }
+
     pub async fn get_created_by(thread_id: Uuid, pool: &MySqlPool) -> Result<Uuid, Error> {
         Ok(sqlx::query_as::<_, (Uuid,)>(
             "SELECT threads.created_by FROM threads WHERE threads.id = ?",
@@ -199,10 +214,10 @@ WHERE t.id = ?
         Ok(())
     }
 
-    pub async fn delete(thread_id: Uuid, pool: &MySqlPool) -> Result<(), Error> {
+    pub async fn delete(thread_id: Uuid, conn: impl MySqlExecutor<'_>) -> Result<(), Error> {
         sqlx::query("DELETE FROM threads WHERE id = ?")
             .bind(thread_id)
-            .execute(pool)
+            .execute(conn)
             .await?;
         Ok(())
     }
@@ -13,13 +13,33 @@ async fn main() {
     use start_axum::entities::user::AuthSession;
     use start_axum::entities::user::User;
     use start_axum::state::AppState;
+    use start_axum::sync::batch::BATCH_WINDOW;
+    use start_axum::sync::connections::OutboundQueue;
     use start_axum::sync::connections::UserConnections;
     use start_axum::sync::connections::UserConnectionsManager;
+    use start_axum::sync::connections::OUTBOUND_QUEUE_CAPACITY;
+    use start_axum::sync::history::SyncHistory;
+    use start_axum::sync::metrics::SyncMetrics;
+    use start_axum::sync::outbox::SyncOutbox;
+    use start_axum::sync::presence::SyncPresence;
     use start_axum::sync::router::SyncRouter;
+    use start_axum::sync::snapshot::SyncSnapshots;
     use start_axum::sync::subs::SubscriptionManager;
-    use start_axum::uploadthing::UploadThing;
+    use start_axum::sync::transport::LocalTransport;
+    use start_axum::sync::transport::MySqlTransport;
+    use start_axum::sync::transport::SyncTransport;
+    use start_axum::sync::transport::TransportKind;
+    use start_axum::sync::typing::TypingIndicators;
+    use start_axum::uploadthing::{FileDeletions, UploadThing};
+    use std::sync::Arc;
+    use std::time::Duration;
 
     use start_axum::app::*;
+    use start_axum::ws::admin::clear_subscriptions;
+    use start_axum::ws::admin::disconnect_connection;
+    use start_axum::ws::admin::sync_overview;
+    use start_axum::ws::admin::SyncAdmins;
+    use start_axum::ws::sse::sse_handler;
     use start_axum::ws::ws_handler;
     use tokio::spawn;
     use uuid::Uuid;
@@ -29,7 +49,7 @@ async fn main() {
         extract::Path,
         http::Request,
         response::{IntoResponse, Response},
-        routing::get,
+        routing::{delete, get, post},
         Router,
     };
 
@@ -51,9 +71,12 @@ async fn main() {
         handle_server_fns_with_context(
             move || {
                 provide_context(app_state.sync_sender.clone());
+                provide_context(app_state.outbox.clone());
                 provide_context(app_state.connection_sender.clone());
                 provide_context(app_state.pool.clone());
                 provide_context(app_state.uploadthing.clone());
+                provide_context(app_state.user_connections.clone());
+                provide_context(app_state.typing.clone());
                 provide_context(cookies.clone());
                 provide_context(auth_session.clone())
             },
@@ -74,6 +97,7 @@ async fn main() {
             move || {
                 provide_context(cookies.clone());
                 provide_context(app_state.sync_sender.clone());
+                provide_context(app_state.outbox.clone());
                 provide_context(app_state.connection_sender.clone());
                 provide_context(app_state.pool.clone());
                 provide_context(app_state.uploadthing.clone());
@@ -117,21 +141,76 @@ async fn main() {
     let (connection_sender, connection_receiver) = async_broadcast::broadcast(1000);
 
     let user_connections = UserConnections::default();
+    let outbound = OutboundQueue {
+        capacity: std::env::var("SYNC_QUEUE_CAPACITY")
+            .ok()
+            .and_then(|capacity| capacity.parse().ok())
+            .unwrap_or(OUTBOUND_QUEUE_CAPACITY),
+        policy: std::env::var("SYNC_OVERFLOW_POLICY")
+            .ok()
+            .map(|policy| policy.parse().expect("valid sync overflow policy"))
+            .unwrap_or_default(),
+        batch_window: std::env::var("SYNC_BATCH_WINDOW_MS")
+            .ok()
+            .and_then(|window| window.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(BATCH_WINDOW),
+        metrics: Arc::new(SyncMetrics::default()),
+    };
+
+    let presence = SyncPresence::new(pool.clone());
+    presence
+        .reset_disconnected()
+        .await
+        .expect("reset members status");
 
-    let connections_manager = UserConnectionsManager::new(user_connections.clone());
+    let connections_manager = UserConnectionsManager::new(
+        user_connections.clone(),
+        presence,
+        pool.clone(),
+        sync_sender.clone(),
+    );
     connections_manager
-        .clone()
         .start_receiving(connection_receiver)
         .await;
-    connections_manager.connection_cleanup().await;
 
-    let subscriptions = SubscriptionManager::new();
-    let sync_router = SyncRouter::new(subscriptions, user_connections.clone());
+    let subscriptions = SubscriptionManager::new(pool.clone());
+
+    let typing = TypingIndicators::new(sync_sender.clone(), subscriptions.clone());
+    typing.clone().expire_typing().await;
+    //NOTE: set SYNC_TRANSPORT=mysql when running more than one instance,
+    //every node relays its mutations to the others through the sync_relay table
+    let transport: Arc<dyn SyncTransport> = match std::env::var("SYNC_TRANSPORT")
+        .ok()
+        .map(|transport| transport.parse().expect("valid sync transport"))
+        .unwrap_or_default()
+    {
+        TransportKind::Local => Arc::new(LocalTransport),
+        TransportKind::MySql => Arc::new(MySqlTransport::new(pool.clone())),
+    };
+    transport.start(sync_sender.clone()).await;
+    let sync_router = SyncRouter::new(
+        subscriptions.clone(),
+        user_connections.clone(),
+        SyncHistory::new(),
+        SyncSnapshots::new(pool.clone()),
+        transport,
+        outbound.metrics.clone(),
+    );
 
     spawn(async move {
         sync_router.start(sync_receiver).await;
     });
 
+    //NOTE: the server fns stage their mutations in the sync_outbox table with the writes,
+    //the dispatcher is the one that hands them to the router
+    let outbox = SyncOutbox::new(pool.clone());
+    outbox.start(sync_sender.clone()).await;
+
+    FileDeletions::new(pool.clone(), uploadthing.clone())
+        .start()
+        .await;
+
     let app_state = AppState {
         connection_sender,
         sync_sender,
@@ -139,11 +218,26 @@ async fn main() {
         routes: routes.clone(),
         pool: pool.clone(),
         user_connections,
+        outbound,
+        subscriptions,
+        outbox,
+        sync_admins: SyncAdmins::from_env(),
+        typing,
         uploadthing,
     };
 
     let app = Router::new()
         .route("/ws", any(ws_handler))
+        .route("/sync/events", get(sse_handler))
+        .route("/sync/admin", get(sync_overview))
+        .route(
+            "/sync/admin/connections/{connection_id}/disconnect",
+            post(disconnect_connection),
+        )
+        .route(
+            "/sync/admin/connections/{connection_id}/subscriptions",
+            delete(clear_subscriptions),
+        )
         .route(
             "/api/{*fn_name}",
             get(server_fn_handler).post(server_fn_handler),
@@ -8,6 +8,7 @@ use uuid::Uuid;
 use crate::entities::message::{Attachment, ChannelMessage, Embed, Reaction};
 use crate::entities::role::Role;
 use crate::entities::thread::Thread;
+use crate::sync::keys::{kind, Coalesce, Payload};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ServerMessage {
@@ -26,6 +27,32 @@ pub struct ServerMessage {
 //     }
 // }
 
+impl Payload<kind::Server> for ServerMessage {}
+
+impl Coalesce for ServerMessage {
+    fn entity(&self) -> Option<String> {
+        self.msg.entity()
+    }
+}
+
+//NOTE: the typing indicators are published on the chat itself
+impl Payload<kind::Channel> for Message {}
+impl Payload<kind::Thread> for Message {}
+
+impl Coalesce for Message {
+    fn entity(&self) -> Option<String> {
+        match self {
+            Message::MemberConnected { member_id } | Message::MemberDisconnected { member_id } => {
+                Some(format!("status:{member_id}"))
+            }
+            Message::Typing {
+                user_id, chat_id, ..
+            } => Some(format!("typing:{chat_id}:{user_id}")),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Message {
     RoleCreated {
@@ -1,6 +1,10 @@
-use crate::sync::connections::{ConnectionMessage, UserConnections};
+use crate::sync::connections::{ConnectionMessage, OutboundQueue, UserConnections};
+use crate::sync::outbox::SyncOutbox;
+use crate::sync::subs::SubscriptionManager;
+use crate::sync::typing::TypingIndicators;
 use crate::sync::SyncRequest;
 use crate::uploadthing::server::UploadThing;
+use crate::ws::admin::SyncAdmins;
 use async_broadcast::Sender;
 use leptos::config::LeptosOptions;
 use leptos_axum::AxumRouteListing;
@@ -13,6 +17,11 @@ pub struct AppState {
     pub leptos_options: LeptosOptions,
     pub pool: MySqlPool,
     pub user_connections: UserConnections,
+    pub outbound: OutboundQueue,
+    pub subscriptions: SubscriptionManager,
+    pub outbox: SyncOutbox,
+    pub sync_admins: SyncAdmins,
+    pub typing: TypingIndicators,
     pub uploadthing: UploadThing,
     pub routes: Vec<AxumRouteListing>,
 }
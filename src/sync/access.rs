@@ -0,0 +1,48 @@
+use std::str::FromStr;
+
+use log::{error, warn};
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use crate::entities::channel::Channel;
+use crate::entities::server::Server;
+use crate::entities::thread::Thread;
+
+use super::keys::SyncKey;
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionAccess {
+    pool: MySqlPool,
+}
+
+impl SubscriptionAccess {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn can_subscribe(&self, key: &str, user_id: Uuid) -> bool {
+        let Ok(sync_key) = SyncKey::from_str(key) else {
+            warn!("SubscriptionAccess: Unknown key '{key}', denying access.");
+            return false;
+        };
+        //NOTE: the store keys are scoped by the entity they're namespaced with
+        let access = match sync_key {
+            SyncKey::User(id) => Ok(id == user_id),
+            SyncKey::Server(server_id)
+            | SyncKey::ServerChannels(server_id)
+            | SyncKey::ServerCategories(server_id) => {
+                Server::member_exist(server_id, user_id, &self.pool).await
+            }
+            SyncKey::Channel(channel_id) | SyncKey::ChannelStore(channel_id) => {
+                Channel::user_has_access(channel_id, user_id, &self.pool).await
+            }
+            SyncKey::Thread(thread_id) => {
+                Thread::user_has_access(thread_id, user_id, &self.pool).await
+            }
+        };
+        access.unwrap_or_else(|err| {
+            error!("SubscriptionAccess: Failed to check access of '{user_id}' to '{key}': {err:?}");
+            false
+        })
+    }
+}
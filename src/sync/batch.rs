@@ -0,0 +1,73 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use super::protocol::{SeqRange, ServerFrame};
+use super::Mutation;
+
+pub const BATCH_WINDOW: Duration = Duration::from_millis(20);
+pub const MAX_BATCH_SIZE: usize = 256;
+
+#[derive(Debug, Default)]
+pub struct MutationBatch {
+    mutations: Vec<Mutation>,
+}
+
+#[derive(Debug)]
+pub struct FlushedBatch {
+    pub frame: ServerFrame,
+    pub keys: Vec<String>,
+    pub coalesced: usize,
+}
+
+impl MutationBatch {
+    //NOTE: returns true for the first mutation of the batch, that one schedules the flush
+    pub fn push(&mut self, mutation: Mutation) -> bool {
+        self.mutations.push(mutation);
+        self.mutations.len() == 1
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.mutations.len() >= MAX_BATCH_SIZE
+    }
+
+    pub fn take(&mut self) -> Option<FlushedBatch> {
+        let mutations = std::mem::take(&mut self.mutations);
+        if mutations.len() <= 1 {
+            return mutations.into_iter().next().map(|mutation| FlushedBatch {
+                keys: vec![mutation.key.clone()],
+                frame: ServerFrame::Mutation(mutation),
+                coalesced: 0,
+            });
+        }
+        let mut ranges: Vec<SeqRange> = vec![];
+        for mutation in &mutations {
+            match ranges.iter_mut().find(|range| range.key == mutation.key) {
+                Some(range) => {
+                    range.from = range.from.min(mutation.seq);
+                    range.to = range.to.max(mutation.seq);
+                }
+                None => ranges.push(SeqRange {
+                    key: mutation.key.clone(),
+                    from: mutation.seq,
+                    to: mutation.seq,
+                }),
+            }
+        }
+        let total = mutations.len();
+        let mut entities = HashSet::new();
+        let mut mutations: Vec<Mutation> = mutations
+            .into_iter()
+            .rev()
+            .filter(|mutation| match &mutation.entity {
+                Some(entity) => entities.insert((mutation.key.clone(), entity.clone())),
+                None => true,
+            })
+            .collect();
+        mutations.reverse();
+        Some(FlushedBatch {
+            keys: ranges.iter().map(|range| range.key.clone()).collect(),
+            coalesced: total - mutations.len(),
+            frame: ServerFrame::Batch { mutations, ranges },
+        })
+    }
+}
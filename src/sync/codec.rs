@@ -0,0 +1,78 @@
+use std::fmt::Display;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+pub const JSON_PROTOCOL: &str = "talw.json";
+pub const MSGPACK_PROTOCOL: &str = "talw.msgpack";
+//NOTE: in order of preference, the server picks the first one the client offers
+pub const PROTOCOLS: [&str; 2] = [MSGPACK_PROTOCOL, JSON_PROTOCOL];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    Json(serde_json::Error),
+    MessagePack(String),
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::Json(err) => write!(f, "invalid json frame: {err}"),
+            CodecError::MessagePack(err) => write!(f, "invalid msgpack frame: {err}"),
+        }
+    }
+}
+
+impl From<serde_json::Error> for CodecError {
+    fn from(err: serde_json::Error) -> Self {
+        CodecError::Json(err)
+    }
+}
+
+impl Codec {
+    //NOTE: without a negotiated protocol we keep talking json, that is what older clients send
+    pub fn from_protocol(protocol: Option<&str>) -> Self {
+        match protocol {
+            Some(MSGPACK_PROTOCOL) => Codec::MessagePack,
+            _ => Codec::Json,
+        }
+    }
+
+    pub fn encode_value(&self, value: &Value) -> Result<Frame, CodecError> {
+        match self {
+            Codec::Json => Ok(Frame::Text(serde_json::to_string(value)?)),
+            Codec::MessagePack => rmp_serde::to_vec_named(value)
+                .map(Frame::Binary)
+                .map_err(|err| CodecError::MessagePack(err.to_string())),
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Frame, CodecError> {
+        self.encode_value(&serde_json::to_value(value)?)
+    }
+}
+
+//NOTE: the frame kind tells the codec, and msgpack goes through the json data model
+//so both codecs decode into exactly the same types
+pub fn decode<T: DeserializeOwned>(frame: Frame) -> Result<T, CodecError> {
+    let value = match frame {
+        Frame::Text(text) => return Ok(serde_json::from_str(&text)?),
+        Frame::Binary(bytes) => rmp_serde::from_slice::<Value>(&bytes)
+            .map_err(|err| CodecError::MessagePack(err.to_string()))?,
+    };
+    Ok(serde_json::from_value(value)?)
+}
@@ -1,22 +1,86 @@
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use async_broadcast::{broadcast, InactiveReceiver, Receiver, Sender};
+use async_broadcast::{broadcast, InactiveReceiver, Receiver, Sender, TrySendError};
 use dashmap::DashMap;
-use log::{debug, error, info};
-use serde_json::Value;
+use log::{debug, error, info, warn};
+use serde_json::{json, Value};
+use sqlx::MySqlPool;
 use tokio::spawn;
-use tokio::time::{interval, Instant};
+use tokio::time::{interval, sleep, Instant};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+use crate::entities::member::{Member, Status};
+use crate::messages::{Message, ServerMessage};
+
+use super::batch::{FlushedBatch, MutationBatch, BATCH_WINDOW};
+use super::keys;
+use super::metrics::SyncMetrics;
+use super::presence::{SyncPresence, PRESENCE_HEARTBEAT_INTERVAL, PRESENCE_SWEEP_INTERVAL};
+use super::protocol::ServerFrame;
+use super::{Mutation, SyncRequest};
+
+pub const PRESENCE_GRACE_PERIOD: Duration = Duration::from_secs(10);
+pub const OUTBOUND_QUEUE_CAPACITY: usize = 1000;
+
 pub type UserConnections = Arc<DashMap<Uuid, Connection>>;
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    #[default]
+    DropOldest,
+    Disconnect,
+    Resync,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "drop_oldest" => Ok(OverflowPolicy::DropOldest),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            "resync" => Ok(OverflowPolicy::Resync),
+            policy => Err(format!("unknown overflow policy '{policy}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboundQueue {
+    pub capacity: usize,
+    pub policy: OverflowPolicy,
+    //NOTE: a zero window sends every mutation on its own
+    pub batch_window: Duration,
+    pub metrics: Arc<SyncMetrics>,
+}
+
+impl Default for OutboundQueue {
+    fn default() -> Self {
+        Self {
+            capacity: OUTBOUND_QUEUE_CAPACITY,
+            policy: OverflowPolicy::default(),
+            batch_window: BATCH_WINDOW,
+            metrics: Default::default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Connection {
+    pub user_id: Uuid,
     pub sender: Sender<Value>,
     pub receiver: InactiveReceiver<Value>,
     pub created: Instant,
-    pub confirmed: bool,
+    queue: OutboundQueue,
+    lagging: Arc<AtomicBool>,
+    resync: Arc<Mutex<HashSet<String>>>,
+    batch: Arc<Mutex<MutationBatch>>,
+    closed: CancellationToken,
 }
 
 impl Connection {
@@ -26,69 +90,269 @@ impl Connection {
     pub fn receiver(&self) -> Receiver<Value> {
         self.receiver.clone().activate()
     }
+    pub fn closed(&self) -> CancellationToken {
+        self.closed.clone()
+    }
+    pub fn close(&self) {
+        self.closed.cancel();
+    }
+    pub fn queue_depth(&self) -> usize {
+        self.sender.len()
+    }
+    pub fn is_lagging(&self) -> bool {
+        self.lagging.load(Ordering::Relaxed)
+    }
 }
 
 impl Connection {
-    pub fn new() -> Self {
-        let (sender, receiver) = broadcast(1000);
+    pub fn new(user_id: Uuid, queue: OutboundQueue) -> Self {
+        let (mut sender, receiver) = broadcast(queue.capacity);
+        sender.set_overflow(queue.policy == OverflowPolicy::DropOldest);
         Connection {
+            user_id,
             sender,
             receiver: receiver.deactivate(),
             created: Instant::now(),
-            confirmed: false,
+            queue,
+            lagging: Default::default(),
+            resync: Default::default(),
+            batch: Default::default(),
+            closed: CancellationToken::new(),
         }
     }
-}
 
-impl Default for Connection {
-    fn default() -> Self {
-        Self::new()
+    pub fn push(&self, frame: Value) -> bool {
+        let mut batch = self.batch.lock().unwrap();
+        //NOTE: the pending mutations were pushed first, so they are sent first
+        self.flush(&mut batch);
+        self.enqueue(&[], frame)
+    }
+
+    pub fn push_mutation(&self, mutation: Mutation) -> bool {
+        if self.sender.is_closed() || self.sender.receiver_count() == 0 {
+            return false;
+        }
+        if self.queue.batch_window.is_zero() {
+            let keys = [mutation.key.clone()];
+            return self.enqueue(&keys, json!(ServerFrame::Mutation(mutation)));
+        }
+        let mut batch = self.batch.lock().unwrap();
+        if batch.push(mutation) {
+            self.schedule_flush();
+        }
+        if batch.is_full() {
+            self.flush(&mut batch);
+        }
+        true
+    }
+
+    fn schedule_flush(&self) {
+        let connection = self.clone();
+        spawn(async move {
+            sleep(connection.queue.batch_window).await;
+            let mut batch = connection.batch.lock().unwrap();
+            connection.flush(&mut batch);
+        });
+    }
+
+    fn flush(&self, batch: &mut MutationBatch) {
+        let Some(FlushedBatch {
+            frame,
+            keys,
+            coalesced,
+        }) = batch.take()
+        else {
+            return;
+        };
+        if matches!(frame, ServerFrame::Batch { .. }) {
+            self.queue.metrics.batched_frame();
+        }
+        if coalesced > 0 {
+            debug!(
+                "Connection: Coalesced {coalesced} mutations for a client of {}",
+                self.user_id
+            );
+            self.queue.metrics.coalesced_mutations(coalesced);
+        }
+        self.enqueue(&keys, json!(frame));
+    }
+
+    pub fn take_resync(&self) -> Vec<String> {
+        self.resync.lock().unwrap().drain().collect()
+    }
+
+    //NOTE: this never waits on the client, a slow socket only affects its own queue
+    fn enqueue(&self, keys: &[String], frame: Value) -> bool {
+        self.track_lag();
+        match self.sender.try_broadcast(frame) {
+            Ok(None) => true,
+            Ok(Some(_)) => {
+                debug!(
+                    "Connection: Dropped the oldest frame of a client of {}",
+                    self.user_id
+                );
+                self.queue.metrics.dropped_frame();
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                self.queue.metrics.dropped_frame();
+                match self.queue.policy {
+                    OverflowPolicy::Disconnect => {
+                        warn!(
+                            "Connection: Disconnecting a slow client of {}",
+                            self.user_id
+                        );
+                        self.queue.metrics.disconnected_client();
+                        self.closed.cancel();
+                    }
+                    OverflowPolicy::Resync => {
+                        let mut resync = self.resync.lock().unwrap();
+                        for key in keys {
+                            if resync.insert(key.clone()) {
+                                self.queue.metrics.forced_resync();
+                            }
+                        }
+                    }
+                    OverflowPolicy::DropOldest => {}
+                }
+                true
+            }
+            Err(TrySendError::Closed(_)) | Err(TrySendError::Inactive(_)) => false,
+        }
+    }
+
+    fn track_lag(&self) {
+        let len = self.sender.len();
+        if len >= self.queue.capacity * 3 / 4 {
+            if !self.lagging.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "Connection: A client of {} is lagging ({len} queued)",
+                    self.user_id
+                );
+                self.queue.metrics.lagging_client();
+            }
+        } else if len == 0 {
+            self.lagging.store(false, Ordering::Relaxed);
+        }
     }
 }
 
+pub fn user_connections_ids(connections: &UserConnections, user_id: Uuid) -> Vec<Uuid> {
+    connections
+        .iter()
+        .filter(|entry| entry.value().user_id == user_id)
+        .map(|entry| *entry.key())
+        .collect()
+}
+
 #[derive(Clone)]
 pub struct UserConnectionsManager {
     connections: UserConnections,
+    presence: SyncPresence,
+    pool: MySqlPool,
+    sync: Sender<SyncRequest>,
 }
 
 #[derive(Debug, Clone)]
 pub enum ConnectionMessage {
-    InitConnection { client: Uuid },
-    CompleteConnection { client: Uuid },
-    DeleteConnection { client: Uuid },
+    CompleteConnection { client: Uuid, connection: Uuid },
+    DeleteConnection { client: Uuid, connection: Uuid },
 }
 
 impl UserConnectionsManager {
-    pub fn new(connections: UserConnections) -> Self {
-        Self { connections }
+    pub fn new(
+        connections: UserConnections,
+        presence: SyncPresence,
+        pool: MySqlPool,
+        sync: Sender<SyncRequest>,
+    ) -> Self {
+        Self {
+            connections,
+            presence,
+            pool,
+            sync,
+        }
+    }
+
+    //NOTE: the status only changes with the first and the last connection of the user
+    //across every node, the presence store is the one that tells them apart
+    async fn update_presence(&self, user_id: Uuid, status: Status) {
+        let changed = match status {
+            Status::ONLINE => self.presence.connect(user_id).await,
+            Status::OFFLINE => self.presence.disconnect(user_id).await,
+        };
+        match changed {
+            Ok(true) => self.broadcast_presence(user_id, status).await,
+            Ok(false) => {}
+            Err(err) => {
+                error!("Connection Manager: Failed to update the presence of {user_id}: {err:?}")
+            }
+        }
+    }
+
+    async fn broadcast_presence(&self, user_id: Uuid, status: Status) {
+        let members = match Member::get_user_members(user_id, &self.pool).await {
+            Ok(members) => members,
+            Err(err) => {
+                error!("Connection Manager: Failed to get the members of {user_id}: {err:?}");
+                return;
+            }
+        };
+
+        for member in members {
+            let msg = match status {
+                Status::ONLINE => Message::MemberConnected {
+                    member_id: member.id,
+                },
+                Status::OFFLINE => Message::MemberDisconnected {
+                    member_id: member.id,
+                },
+            };
+            let _ = self
+                .sync
+                .broadcast(SyncRequest::mutation(
+                    keys::server(member.server_id),
+                    &ServerMessage {
+                        server_id: member.server_id,
+                        msg,
+                    },
+                ))
+                .await;
+        }
+        debug!("Connection Manager: {user_id} is now {status:?}");
     }
 
-    pub async fn connection_cleanup(self) {
+    async fn start_presence(&self) {
+        if let Err(err) = self.presence.heartbeat().await {
+            error!("Connection Manager: Failed to register the presence node: {err:?}");
+        }
+        info!(
+            "Connection Manager: Presence node '{}' registered",
+            self.presence.node_id()
+        );
+        let manager = self.clone();
         spawn(async move {
-            let mut interval = interval(Duration::from_secs(10));
-            debug!("Timed Dead connection checker started.");
+            let mut heartbeat = interval(PRESENCE_HEARTBEAT_INTERVAL);
+            let mut sweep = interval(PRESENCE_SWEEP_INTERVAL);
             loop {
-                interval.tick().await;
-                let mut timed_out_connections = Vec::new();
-                let now = Instant::now();
-
-                for entry in self.connections.iter() {
-                    let user_id = *entry.key();
-                    let creation_time = entry.value().created;
-                    let confirmed = entry.value().confirmed;
-
-                    if now.duration_since(creation_time) > Duration::from_secs(5) && !confirmed {
-                        println!(
-                            "User {} exceeded connection timeout ({}sec). Removing.",
-                            user_id, 5
-                        );
-                        timed_out_connections.push(user_id);
+                tokio::select! {
+                    _ = heartbeat.tick() => {
+                        if let Err(err) = manager.presence.heartbeat().await {
+                            error!("Connection Manager: Failed to report the presence node: {err:?}");
+                        }
+                    }
+                    _ = sweep.tick() => {
+                        match manager.presence.sweep().await {
+                            Ok(users) => {
+                                for user_id in users {
+                                    manager.broadcast_presence(user_id, Status::OFFLINE).await;
+                                }
+                            }
+                            Err(err) => {
+                                error!("Connection Manager: Failed to sweep the presence nodes: {err:?}");
+                            }
+                        }
                     }
-                }
-
-                for user_id in timed_out_connections {
-                    self.connections.remove(&user_id);
-                    debug!(" Removed timed-out connection for user: {user_id}");
                 }
             }
         });
@@ -96,28 +360,26 @@ impl UserConnectionsManager {
 
     pub async fn start_receiving(self, mut recevier: Receiver<ConnectionMessage>) {
         info!("Connection Manager: Starting message manager loop...");
+        self.start_presence().await;
         spawn(async move {
             while let Ok(message) = recevier.recv().await {
                 match message {
-                    ConnectionMessage::InitConnection { client } => {
-                        debug!("Connection Manager: Starting connection for {client}");
-                        if self.connections.contains_key(&client) {
-                            error!("Connection already exist, missing confirmation");
-                        } else {
-                            self.connections.insert(client, Connection::new());
-                            debug!("Connection started");
-                        }
-                    }
-                    ConnectionMessage::CompleteConnection { client } => {
-                        if let Some(mut connection) = self.connections.get_mut(&client) {
-                            connection.confirmed = true;
-                            debug!("Connection Manager: Connection for {client} completed");
-                        } else {
-                            error!("Connection Manager: Connection for {client} don't exist");
-                        }
+                    ConnectionMessage::CompleteConnection { client, connection } => {
+                        debug!(
+                            "Connection Manager: Connection {connection} for {client} completed"
+                        );
+                        self.update_presence(client, Status::ONLINE).await;
                     }
-                    ConnectionMessage::DeleteConnection { client } => {
-                        self.connections.remove(&client);
+                    ConnectionMessage::DeleteConnection { client, connection } => {
+                        self.connections.remove(&connection);
+                        debug!("Connection Manager: Connection {connection} for {client} closed");
+                        //NOTE: the count of this node is only dropped after the grace period,
+                        //a reconnection meanwhile, on any node, keeps the user online
+                        let manager = self.clone();
+                        spawn(async move {
+                            sleep(PRESENCE_GRACE_PERIOD).await;
+                            manager.update_presence(client, Status::OFFLINE).await;
+                        });
                     }
                 }
             }
@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::keys::SyncKey;
+use super::Mutation;
+
+pub const HISTORY_LIMIT: usize = 512;
+
+#[derive(Debug, Default)]
+struct KeyHistory {
+    seq: u64,
+    events: VecDeque<Mutation>,
+}
+
+#[derive(Debug)]
+pub enum Replay {
+    Events(Vec<Mutation>),
+    ResyncRequired,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncHistory {
+    epoch: Uuid,
+    keys: Arc<DashMap<String, KeyHistory>>,
+}
+
+impl Default for SyncHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncHistory {
+    pub fn new() -> Self {
+        Self {
+            epoch: Uuid::new_v4(),
+            keys: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub fn epoch(&self) -> Uuid {
+        self.epoch
+    }
+
+    pub fn current_seq(&self, key: &str) -> u64 {
+        self.keys.get(key).map(|history| history.seq).unwrap_or(0)
+    }
+
+    pub fn record(
+        &self,
+        key: &SyncKey,
+        data: Value,
+        mutation_id: Option<Uuid>,
+        entity: Option<String>,
+    ) -> Mutation {
+        let mut history = self.keys.entry(key.to_string()).or_default();
+        history.seq += 1;
+        let mutation = Mutation {
+            module: key.module().to_string(),
+            key: key.to_string(),
+            seq: history.seq,
+            data,
+            mutation_id,
+            entity,
+        };
+        if history.events.len() == HISTORY_LIMIT {
+            history.events.pop_front();
+        }
+        history.events.push_back(mutation.clone());
+        mutation
+    }
+
+    pub fn replay(&self, key: &str, last_seq: u64) -> Replay {
+        let Some(history) = self.keys.get(key) else {
+            return if last_seq == 0 {
+                Replay::Events(vec![])
+            } else {
+                Replay::ResyncRequired
+            };
+        };
+
+        if last_seq > history.seq {
+            return Replay::ResyncRequired;
+        }
+        if last_seq == history.seq {
+            return Replay::Events(vec![]);
+        }
+
+        match history.events.front() {
+            Some(oldest) if oldest.seq <= last_seq + 1 => Replay::Events(
+                history
+                    .events
+                    .iter()
+                    .filter(|mutation| mutation.seq > last_seq)
+                    .cloned()
+                    .collect(),
+            ),
+            _ => Replay::ResyncRequired,
+        }
+    }
+}
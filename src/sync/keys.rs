@@ -0,0 +1,230 @@
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyncModule {
+    User,
+    Server,
+    Channel,
+    Thread,
+    ChannelStore,
+    CategoryStore,
+}
+
+impl SyncModule {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            SyncModule::User => "user",
+            SyncModule::Server => "server",
+            SyncModule::Channel => "channel",
+            SyncModule::Thread => "thread",
+            SyncModule::ChannelStore => "channelStore",
+            SyncModule::CategoryStore => "categoriesStore",
+        }
+    }
+
+    //NOTE: every key of the module starts with it, used to replace the whole module on subscribe
+    pub fn prefix(&self) -> String {
+        format!("{}:", self.as_str())
+    }
+}
+
+impl Display for SyncModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyncKey {
+    User(Uuid),
+    Server(Uuid),
+    Channel(Uuid),
+    Thread(Uuid),
+    ServerChannels(Uuid),
+    ChannelStore(Uuid),
+    ServerCategories(Uuid),
+}
+
+impl SyncKey {
+    pub fn module(&self) -> SyncModule {
+        match self {
+            SyncKey::User(_) => SyncModule::User,
+            SyncKey::Server(_) => SyncModule::Server,
+            SyncKey::Channel(_) => SyncModule::Channel,
+            SyncKey::Thread(_) => SyncModule::Thread,
+            SyncKey::ServerChannels(_) | SyncKey::ChannelStore(_) => SyncModule::ChannelStore,
+            SyncKey::ServerCategories(_) => SyncModule::CategoryStore,
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        match self {
+            SyncKey::User(id)
+            | SyncKey::Server(id)
+            | SyncKey::Channel(id)
+            | SyncKey::Thread(id)
+            | SyncKey::ServerChannels(id)
+            | SyncKey::ChannelStore(id)
+            | SyncKey::ServerCategories(id) => *id,
+        }
+    }
+}
+
+impl Display for SyncKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncKey::ServerChannels(id) | SyncKey::ServerCategories(id) => {
+                write!(f, "{}server:{id}", self.module().prefix())
+            }
+            SyncKey::ChannelStore(id) => write!(f, "{}channel:{id}", self.module().prefix()),
+            key => write!(f, "{}{}", key.module().prefix(), key.id()),
+        }
+    }
+}
+
+impl FromStr for SyncKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments: Vec<&str> = s.split(':').collect();
+        let invalid = || format!("invalid sync key '{s}'");
+        let (constructor, id): (fn(Uuid) -> SyncKey, &str) = match segments.as_slice() {
+            ["user", id] => (SyncKey::User, *id),
+            ["server", id] => (SyncKey::Server, *id),
+            ["channel", id] => (SyncKey::Channel, *id),
+            ["thread", id] => (SyncKey::Thread, *id),
+            ["channelStore", "server", id] => (SyncKey::ServerChannels, *id),
+            ["channelStore", "channel", id] => (SyncKey::ChannelStore, *id),
+            ["categoriesStore", "server", id] => (SyncKey::ServerCategories, *id),
+            _ => return Err(invalid()),
+        };
+        Uuid::from_str(id).map(constructor).map_err(|_| invalid())
+    }
+}
+
+impl Serialize for SyncKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SyncKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        SyncKey::from_str(&key).map_err(serde::de::Error::custom)
+    }
+}
+
+pub trait KeyKind {
+    const MODULE: SyncModule;
+
+    fn key(id: Uuid) -> SyncKey;
+}
+
+//NOTE: only payloads that carry the whole state of their entity can name it, a later mutation
+//of the same entity replaces the earlier one when they are batched together
+pub trait Coalesce {
+    fn entity(&self) -> Option<String> {
+        None
+    }
+}
+
+//NOTE: a payload can only be published on, and listened from, the kinds of key it's bound to,
+//the server and the client check the same impls
+pub trait Payload<K: KeyKind>: Coalesce + Serialize + DeserializeOwned {}
+
+pub mod kind {
+    use uuid::Uuid;
+
+    use super::{KeyKind, SyncKey, SyncModule};
+
+    macro_rules! key_kind {
+        ($($kind:ident => $module:ident),* $(,)?) => {
+            $(
+                #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+                pub struct $kind;
+
+                impl KeyKind for $kind {
+                    const MODULE: SyncModule = SyncModule::$module;
+
+                    fn key(id: Uuid) -> SyncKey {
+                        SyncKey::$kind(id)
+                    }
+                }
+            )*
+        };
+    }
+
+    key_kind! {
+        User => User,
+        Server => Server,
+        Channel => Channel,
+        Thread => Thread,
+        ServerChannels => ChannelStore,
+        ChannelStore => ChannelStore,
+        ServerCategories => CategoryStore,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypedKey<K> {
+    id: Uuid,
+    kind: PhantomData<K>,
+}
+
+impl<K: KeyKind> TypedKey<K> {
+    pub fn new(id: Uuid) -> Self {
+        Self {
+            id,
+            kind: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn key(&self) -> SyncKey {
+        K::key(self.id)
+    }
+}
+
+impl<K: KeyKind> From<TypedKey<K>> for SyncKey {
+    fn from(key: TypedKey<K>) -> Self {
+        key.key()
+    }
+}
+
+pub fn user(id: Uuid) -> TypedKey<kind::User> {
+    TypedKey::new(id)
+}
+
+pub fn server(id: Uuid) -> TypedKey<kind::Server> {
+    TypedKey::new(id)
+}
+
+pub fn channel(id: Uuid) -> TypedKey<kind::Channel> {
+    TypedKey::new(id)
+}
+
+pub fn thread(id: Uuid) -> TypedKey<kind::Thread> {
+    TypedKey::new(id)
+}
+
+pub fn server_channels(id: Uuid) -> TypedKey<kind::ServerChannels> {
+    TypedKey::new(id)
+}
+
+pub fn channel_store(id: Uuid) -> TypedKey<kind::ChannelStore> {
+    TypedKey::new(id)
+}
+
+pub fn server_categories(id: Uuid) -> TypedKey<kind::ServerCategories> {
+    TypedKey::new(id)
+}
@@ -0,0 +1,113 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+#[derive(Debug)]
+pub struct SyncMetrics {
+    started: Instant,
+    routed_mutations: AtomicU64,
+    delivered_mutations: AtomicU64,
+    routing_micros: AtomicU64,
+    max_routing_micros: AtomicU64,
+    dropped_frames: AtomicU64,
+    lagging_clients: AtomicU64,
+    disconnected_clients: AtomicU64,
+    forced_resyncs: AtomicU64,
+    batched_frames: AtomicU64,
+    coalesced_mutations: AtomicU64,
+}
+
+impl Default for SyncMetrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            routed_mutations: Default::default(),
+            delivered_mutations: Default::default(),
+            routing_micros: Default::default(),
+            max_routing_micros: Default::default(),
+            dropped_frames: Default::default(),
+            lagging_clients: Default::default(),
+            disconnected_clients: Default::default(),
+            forced_resyncs: Default::default(),
+            batched_frames: Default::default(),
+            coalesced_mutations: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SyncMetricsSnapshot {
+    pub uptime_secs: u64,
+    pub routed_mutations: u64,
+    pub delivered_mutations: u64,
+    pub mutations_per_sec: f64,
+    //NOTE: from the router taking the mutation to it being queued on every subscriber
+    pub avg_routing_micros: u64,
+    pub max_routing_micros: u64,
+    pub dropped_frames: u64,
+    pub lagging_clients: u64,
+    pub disconnected_clients: u64,
+    pub forced_resyncs: u64,
+    pub batched_frames: u64,
+    pub coalesced_mutations: u64,
+}
+
+impl SyncMetrics {
+    pub fn routed_mutation(&self, deliveries: usize, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        self.routed_mutations.fetch_add(1, Ordering::Relaxed);
+        self.delivered_mutations
+            .fetch_add(deliveries as u64, Ordering::Relaxed);
+        self.routing_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_routing_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    pub fn dropped_frame(&self) {
+        self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn lagging_client(&self) {
+        self.lagging_clients.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn disconnected_client(&self) {
+        self.disconnected_clients.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn forced_resync(&self) {
+        self.forced_resyncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn batched_frame(&self) {
+        self.batched_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn coalesced_mutations(&self, count: usize) {
+        self.coalesced_mutations
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> SyncMetricsSnapshot {
+        let uptime = self.started.elapsed();
+        let routed_mutations = self.routed_mutations.load(Ordering::Relaxed);
+        SyncMetricsSnapshot {
+            uptime_secs: uptime.as_secs(),
+            routed_mutations,
+            delivered_mutations: self.delivered_mutations.load(Ordering::Relaxed),
+            mutations_per_sec: routed_mutations as f64 / uptime.as_secs_f64().max(1.0),
+            avg_routing_micros: self
+                .routing_micros
+                .load(Ordering::Relaxed)
+                .checked_div(routed_mutations)
+                .unwrap_or_default(),
+            max_routing_micros: self.max_routing_micros.load(Ordering::Relaxed),
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            lagging_clients: self.lagging_clients.load(Ordering::Relaxed),
+            disconnected_clients: self.disconnected_clients.load(Ordering::Relaxed),
+            forced_resyncs: self.forced_resyncs.load(Ordering::Relaxed),
+            batched_frames: self.batched_frames.load(Ordering::Relaxed),
+            coalesced_mutations: self.coalesced_mutations.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -1,36 +1,127 @@
 #[cfg(feature = "ssr")]
+pub mod access;
+#[cfg(feature = "ssr")]
+pub mod batch;
+#[cfg(feature = "ssr")]
 pub mod connections;
 #[cfg(feature = "ssr")]
+pub mod history;
+#[cfg(feature = "ssr")]
+pub mod metrics;
+#[cfg(feature = "ssr")]
+pub mod outbox;
+#[cfg(feature = "ssr")]
+pub mod presence;
+#[cfg(feature = "ssr")]
 pub mod router;
 #[cfg(feature = "ssr")]
+pub mod snapshot;
+#[cfg(feature = "ssr")]
 pub mod subs;
+#[cfg(feature = "ssr")]
+pub mod transport;
+#[cfg(feature = "ssr")]
+pub mod typing;
+
+pub mod codec;
+pub mod keys;
+pub mod protocol;
 
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use uuid::Uuid;
 
+use self::keys::{KeyKind, Payload, SyncKey, TypedKey};
+use self::protocol::KeyCursor;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SyncRequest {
     Mutation {
-        key: String,
+        key: SyncKey,
         data: Value,
+        mutation_id: Option<Uuid>,
+        #[serde(default)]
+        entity: Option<String>,
     },
     Subscription {
         keys: Vec<String>,
-        client: Uuid,
+        connection: Uuid,
         action: SubscriptionMode,
+        snapshot: bool,
     },
     Unsubscription {
         keys: Vec<String>,
         prefix: Option<String>,
-        client: Uuid,
+        connection: Uuid,
+    },
+    Resume {
+        connection: Uuid,
+        epoch: Uuid,
+        cursors: Vec<KeyCursor>,
+    },
+    Reauthorize {
+        user: Uuid,
+    },
+    ReauthorizeKeys {
+        keys: Vec<SyncKey>,
+    },
+    Disconnected {
+        connection: Uuid,
     },
+    ClearSubscriptions {
+        connection: Uuid,
+    },
+    Relayed(Box<SyncRequest>),
+}
+
+impl SyncRequest {
+    pub fn mutation<K: KeyKind, P: Payload<K>>(key: TypedKey<K>, payload: &P) -> Self {
+        SyncRequest::Mutation {
+            key: key.key(),
+            data: json!(payload),
+            mutation_id: None,
+            entity: payload.entity(),
+        }
+    }
+
+    //NOTE: used for the writes of a client, the id is echoed back to it on the mutation
+    pub fn echo<K: KeyKind, P: Payload<K>>(
+        key: TypedKey<K>,
+        payload: &P,
+        mutation_id: Option<Uuid>,
+    ) -> Self {
+        SyncRequest::Mutation {
+            key: key.key(),
+            data: json!(payload),
+            mutation_id,
+            entity: payload.entity(),
+        }
+    }
+
+    //NOTE: the rest of the requests are about connections, and those only live in one node
+    pub fn is_relayable(&self) -> bool {
+        matches!(
+            self,
+            SyncRequest::Mutation { .. }
+                | SyncRequest::Reauthorize { .. }
+                | SyncRequest::ReauthorizeKeys { .. }
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Mutation {
     pub module: String,
+    pub key: String,
+    pub seq: u64,
     pub data: Value,
+    //NOTE: the id the client attached to the write, echoed back so it can reconcile
+    //its optimistic update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mutation_id: Option<Uuid>,
+    //NOTE: only used by the server to coalesce batches, see keys::Coalesce
+    #[serde(skip)]
+    pub entity: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,202 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_broadcast::Sender;
+use log::{debug, error, info, warn};
+use sqlx::mysql::{MySql, MySqlConnection};
+use sqlx::types::Json;
+use sqlx::{MySqlPool, QueryBuilder, Transaction};
+use tokio::spawn;
+use tokio::sync::Notify;
+use tokio::time::interval;
+
+use super::SyncRequest;
+use crate::entities::Error;
+
+const OUTBOX_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const OUTBOX_BATCH_SIZE: u32 = 100;
+const OUTBOX_RETENTION: Duration = Duration::from_secs(60 * 60);
+const OUTBOX_CLAIM_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct SyncOutbox {
+    pool: MySqlPool,
+    notify: Arc<Notify>,
+}
+
+//NOTE: the writes and the requests they publish are committed together,
+//nothing reaches the router until the transaction is committed
+pub struct OutboxTransaction {
+    tx: Transaction<'static, MySql>,
+    notify: Arc<Notify>,
+    staged: usize,
+}
+
+#[derive(sqlx::FromRow)]
+struct OutboxRequest {
+    id: u64,
+    request: Json<SyncRequest>,
+}
+
+impl SyncOutbox {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self {
+            pool,
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    pub async fn begin(&self) -> Result<OutboxTransaction, Error> {
+        Ok(OutboxTransaction {
+            tx: self.pool.begin().await?,
+            notify: self.notify.clone(),
+            staged: 0,
+        })
+    }
+
+    //NOTE: SKIP LOCKED lets every node run a dispatcher, a row is delivered by the node
+    //that claims it and reaches the others through the transport. The claim is committed
+    //before the rows are broadcast, so no lock is held while the router is busy
+    async fn claim(&self) -> Result<Vec<OutboxRequest>, Error> {
+        let mut tx = self.pool.begin().await?;
+        let requests: Vec<OutboxRequest> = sqlx::query_as(
+            "SELECT id, request FROM sync_outbox WHERE sent_at IS NULL AND (claimed_at IS NULL OR claimed_at < NOW() - INTERVAL ? SECOND) ORDER BY id LIMIT ? FOR UPDATE SKIP LOCKED",
+        )
+        .bind(OUTBOX_CLAIM_TIMEOUT.as_secs())
+        .bind(OUTBOX_BATCH_SIZE)
+        .fetch_all(&mut *tx)
+        .await?;
+        if !requests.is_empty() {
+            let mut query_builder =
+                QueryBuilder::new("UPDATE sync_outbox SET claimed_at = NOW() WHERE id IN (");
+            push_ids(
+                &mut query_builder,
+                requests.iter().map(|request| request.id),
+            );
+            query_builder.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(requests)
+    }
+
+    async fn mark_sent(&self, ids: Vec<u64>) -> Result<(), Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let mut query_builder =
+            QueryBuilder::new("UPDATE sync_outbox SET sent_at = NOW() WHERE id IN (");
+        push_ids(&mut query_builder, ids);
+        query_builder.build().execute(&self.pool).await?;
+        Ok(())
+    }
+
+    //NOTE: returns None when the router is closed, the claim of the rows that weren't
+    //broadcast expires and they are dispatched again
+    async fn dispatch(&self, router: &Sender<SyncRequest>) -> Result<Option<usize>, Error> {
+        let requests = self.claim().await?;
+        let dispatched = requests.len();
+        let mut sent = Vec::with_capacity(dispatched);
+        for OutboxRequest { id, request } in requests {
+            debug!("SyncOutbox: Dispatching request '{id}'");
+            if router.broadcast(request.0).await.is_err() {
+                self.mark_sent(sent).await?;
+                return Ok(None);
+            }
+            sent.push(id);
+        }
+        self.mark_sent(sent).await?;
+        Ok(Some(dispatched))
+    }
+
+    async fn prune(&self) -> Result<(), Error> {
+        sqlx::query(
+            "DELETE FROM sync_outbox WHERE sent_at IS NOT NULL AND sent_at < NOW() - INTERVAL ? SECOND",
+        )
+        .bind(OUTBOX_RETENTION.as_secs())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    //NOTE: delivery is at least once, a crash between the broadcast and marking the rows
+    //as sent dispatches them again once their claim expires, the clients already ignore
+    //the mutations they have applied
+    pub async fn start(&self, router: Sender<SyncRequest>) {
+        info!("SyncOutbox: Starting the dispatcher");
+        let outbox = self.clone();
+        spawn(async move {
+            let mut poll = interval(OUTBOX_POLL_INTERVAL);
+            let mut polls: u64 = 0;
+            loop {
+                let prune = tokio::select! {
+                    _ = poll.tick() => {
+                        polls += 1;
+                        polls % (OUTBOX_RETENTION.as_millis() / OUTBOX_POLL_INTERVAL.as_millis()) as u64 == 0
+                    }
+                    _ = outbox.notify.notified() => false,
+                };
+                loop {
+                    match outbox.dispatch(&router).await {
+                        Ok(Some(dispatched)) if dispatched as u32 == OUTBOX_BATCH_SIZE => continue,
+                        Ok(Some(_)) => break,
+                        Ok(None) => {
+                            warn!("SyncOutbox: The router is closed, stopping the dispatcher.");
+                            return;
+                        }
+                        Err(err) => {
+                            error!("SyncOutbox: Failed to dispatch the outbox: {err:?}");
+                            break;
+                        }
+                    }
+                }
+                if prune {
+                    if let Err(err) = outbox.prune().await {
+                        error!("SyncOutbox: Failed to prune the outbox: {err:?}");
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl OutboxTransaction {
+    pub async fn stage(&mut self, request: SyncRequest) -> Result<(), Error> {
+        sqlx::query("INSERT INTO sync_outbox (request) VALUES (?)")
+            .bind(Json(request))
+            .execute(&mut *self.tx)
+            .await?;
+        self.staged += 1;
+        Ok(())
+    }
+
+    pub async fn commit(self) -> Result<(), Error> {
+        self.tx.commit().await?;
+        if self.staged > 0 {
+            self.notify.notify_one();
+        }
+        Ok(())
+    }
+}
+
+impl Deref for OutboxTransaction {
+    type Target = MySqlConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tx
+    }
+}
+
+impl DerefMut for OutboxTransaction {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.tx
+    }
+}
+
+fn push_ids(query_builder: &mut QueryBuilder<'_, MySql>, ids: impl IntoIterator<Item = u64>) {
+    let mut separated = query_builder.separated(", ");
+    for id in ids {
+        separated.push_bind(id);
+    }
+    separated.push_unseparated(")");
+}
@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use sqlx::mysql::{MySql, MySqlConnection};
+use sqlx::{MySqlPool, QueryBuilder};
+use uuid::Uuid;
+
+use crate::entities::member::{Member, Status};
+use crate::entities::Error;
+
+pub const PRESENCE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+pub const PRESENCE_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+pub const PRESENCE_NODE_TIMEOUT: Duration = Duration::from_secs(30);
+
+//NOTE: every node counts the sockets it holds for each user in user_presence,
+//a user is online while the counts of the live nodes add up to more than zero
+#[derive(Debug, Clone)]
+pub struct SyncPresence {
+    node_id: Uuid,
+    pool: MySqlPool,
+}
+
+impl SyncPresence {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self {
+            node_id: Uuid::new_v4(),
+            pool,
+        }
+    }
+
+    pub fn node_id(&self) -> Uuid {
+        self.node_id
+    }
+
+    pub async fn heartbeat(&self) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO presence_nodes (node_id) VALUES (?) ON DUPLICATE KEY UPDATE seen_at = CURRENT_TIMESTAMP",
+        )
+        .bind(self.node_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    //NOTE: clears the members left online by a node that went away, the users that hold
+    //a connection on a live node keep their status
+    pub async fn reset_disconnected(&self) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE members SET status = ?
+            WHERE
+                status = ?
+                AND user_id NOT IN (
+                    SELECT
+                        p.user_id
+                    FROM
+                        user_presence p
+                        INNER JOIN presence_nodes n ON n.node_id = p.node_id
+                    WHERE
+                        n.seen_at >= NOW() - INTERVAL ? SECOND
+                )
+            "#,
+        )
+        .bind(Status::OFFLINE)
+        .bind(Status::ONLINE)
+        .bind(PRESENCE_NODE_TIMEOUT.as_secs())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    //NOTE: returns true when it's the first connection of the user on any node
+    pub async fn connect(&self, user_id: Uuid) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+        lock_user(user_id, &mut tx).await?;
+        sqlx::query(
+            "INSERT INTO user_presence (user_id, node_id, connections) VALUES (?, ?, 1) ON DUPLICATE KEY UPDATE connections = connections + 1",
+        )
+        .bind(user_id)
+        .bind(self.node_id)
+        .execute(&mut *tx)
+        .await?;
+        let online = live_connections(user_id, &mut tx).await? == 1;
+        if online {
+            Member::update_members_status(user_id, Status::ONLINE, &mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(online)
+    }
+
+    //NOTE: returns true when it was the last connection of the user on every node
+    pub async fn disconnect(&self, user_id: Uuid) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+        lock_user(user_id, &mut tx).await?;
+        sqlx::query(
+            "UPDATE user_presence SET connections = connections - 1 WHERE user_id = ? AND node_id = ? AND connections > 0",
+        )
+        .bind(user_id)
+        .bind(self.node_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "DELETE FROM user_presence WHERE user_id = ? AND node_id = ? AND connections = 0",
+        )
+        .bind(user_id)
+        .bind(self.node_id)
+        .execute(&mut *tx)
+        .await?;
+        let offline = set_offline_if_disconnected(user_id, &mut tx).await?;
+        tx.commit().await?;
+        Ok(offline)
+    }
+
+    //NOTE: the counts of a node that stopped reporting are dropped,
+    //returns the users of those nodes that aren't connected to any other node
+    pub async fn sweep(&self) -> Result<Vec<Uuid>, Error> {
+        let mut tx = self.pool.begin().await?;
+        let nodes: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT node_id FROM presence_nodes WHERE seen_at < NOW() - INTERVAL ? SECOND FOR UPDATE SKIP LOCKED",
+        )
+        .bind(PRESENCE_NODE_TIMEOUT.as_secs())
+        .fetch_all(&mut *tx)
+        .await?;
+        if nodes.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut query_builder =
+            QueryBuilder::new("SELECT DISTINCT user_id FROM user_presence WHERE node_id IN (");
+        push_nodes(&mut query_builder, &nodes);
+        let users: Vec<Uuid> = query_builder
+            .build_query_scalar()
+            .fetch_all(&mut *tx)
+            .await?;
+        let mut query_builder = QueryBuilder::new("DELETE FROM user_presence WHERE node_id IN (");
+        push_nodes(&mut query_builder, &nodes);
+        query_builder.build().execute(&mut *tx).await?;
+        let mut query_builder = QueryBuilder::new("DELETE FROM presence_nodes WHERE node_id IN (");
+        push_nodes(&mut query_builder, &nodes);
+        query_builder.build().execute(&mut *tx).await?;
+        tx.commit().await?;
+
+        let mut offline = vec![];
+        for user_id in users {
+            let mut tx = self.pool.begin().await?;
+            lock_user(user_id, &mut tx).await?;
+            if set_offline_if_disconnected(user_id, &mut tx).await? {
+                offline.push(user_id);
+            }
+            tx.commit().await?;
+        }
+        Ok(offline)
+    }
+}
+
+//NOTE: the row of the user is the lock every node takes before changing its counts,
+//so the first and the last connection are seen by exactly one of them
+async fn lock_user(user_id: Uuid, conn: &mut MySqlConnection) -> Result<(), Error> {
+    sqlx::query("SELECT id FROM users WHERE id = ? FOR UPDATE")
+        .bind(user_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+async fn live_connections(user_id: Uuid, conn: &mut MySqlConnection) -> Result<u64, Error> {
+    Ok(sqlx::query_scalar(
+        r#"
+        SELECT
+            CAST(COALESCE(SUM(p.connections), 0) AS UNSIGNED)
+        FROM
+            user_presence p
+            INNER JOIN presence_nodes n ON n.node_id = p.node_id
+        WHERE
+            p.user_id = ?
+            AND n.seen_at >= NOW() - INTERVAL ? SECOND
+        "#,
+    )
+    .bind(user_id)
+    .bind(PRESENCE_NODE_TIMEOUT.as_secs())
+    .fetch_one(&mut *conn)
+    .await?)
+}
+
+async fn set_offline_if_disconnected(
+    user_id: Uuid,
+    conn: &mut MySqlConnection,
+) -> Result<bool, Error> {
+    let offline = live_connections(user_id, &mut *conn).await? == 0;
+    if offline {
+        Member::update_members_status(user_id, Status::OFFLINE, &mut *conn).await?;
+    }
+    Ok(offline)
+}
+
+fn push_nodes(query_builder: &mut QueryBuilder<'_, MySql>, nodes: &[Uuid]) {
+    let mut separated = query_builder.separated(", ");
+    for node in nodes {
+        separated.push_bind(*node);
+    }
+    separated.push_unseparated(")");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn age_node(pool: &MySqlPool, node: &SyncPresence) {
+        sqlx::query(
+            "UPDATE presence_nodes SET seen_at = NOW() - INTERVAL ? SECOND WHERE node_id = ?",
+        )
+        .bind(PRESENCE_NODE_TIMEOUT.as_secs() + 1)
+        .bind(node.node_id)
+        .execute(pool)
+        .await
+        .expect("age the node");
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a MySQL server in DATABASE_URL"]
+    async fn stays_online_while_another_node_holds_a_connection(pool: MySqlPool) {
+        let first = SyncPresence::new(pool.clone());
+        let second = SyncPresence::new(pool.clone());
+        first.heartbeat().await.expect("register the first node");
+        second.heartbeat().await.expect("register the second node");
+        let user_id = Uuid::new_v4();
+
+        assert!(first
+            .connect(user_id)
+            .await
+            .expect("connect to the first node"));
+        assert!(!second
+            .connect(user_id)
+            .await
+            .expect("connect to the second node"));
+        assert!(!first
+            .connect(user_id)
+            .await
+            .expect("connect to the first node again"));
+
+        assert!(!first
+            .disconnect(user_id)
+            .await
+            .expect("disconnect from the first node"));
+        assert!(!first
+            .disconnect(user_id)
+            .await
+            .expect("disconnect from the first node"));
+        assert!(second
+            .disconnect(user_id)
+            .await
+            .expect("disconnect from the second node"));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a MySQL server in DATABASE_URL"]
+    async fn sweeps_the_connections_of_a_dead_node(pool: MySqlPool) {
+        let dead = SyncPresence::new(pool.clone());
+        let live = SyncPresence::new(pool.clone());
+        dead.heartbeat().await.expect("register the dead node");
+        live.heartbeat().await.expect("register the live node");
+        let only_dead = Uuid::new_v4();
+        let both = Uuid::new_v4();
+        dead.connect(only_dead)
+            .await
+            .expect("connect to the dead node");
+        dead.connect(both).await.expect("connect to the dead node");
+        live.connect(both).await.expect("connect to the live node");
+
+        age_node(&pool, &dead).await;
+        assert_eq!(
+            live.sweep().await.expect("sweep the nodes"),
+            vec![only_dead]
+        );
+        assert!(live.sweep().await.expect("sweep the nodes").is_empty());
+        assert!(live
+            .disconnect(both)
+            .await
+            .expect("disconnect from the live node"));
+    }
+}
@@ -0,0 +1,134 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::keys::SyncKey;
+use super::Mutation;
+
+pub const PROTOCOL_VERSION: u16 = 1;
+pub const HEARTBEAT_INTERVAL_MS: u32 = 15_000;
+pub const HEARTBEAT_TIMEOUT_MS: u32 = 45_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ClientFrame {
+    pub version: u16,
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
+impl ClientFrame {
+    pub fn new(message: ClientMessage) -> Self {
+        ClientFrame {
+            version: PROTOCOL_VERSION,
+            message,
+        }
+    }
+}
+
+impl From<ClientMessage> for ClientFrame {
+    fn from(message: ClientMessage) -> Self {
+        ClientFrame::new(message)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Subscribe {
+        keys: Vec<String>,
+        #[serde(default)]
+        replace_prefix: Option<String>,
+        #[serde(default)]
+        snapshot: bool,
+    },
+    Unsubscribe {
+        keys: Vec<String>,
+        #[serde(default)]
+        prefix: Option<String>,
+    },
+    Typing {
+        key: String,
+        is_typing: bool,
+    },
+    Ack {
+        seq: u64,
+    },
+    Resume {
+        epoch: Uuid,
+        cursors: Vec<KeyCursor>,
+    },
+    Ping,
+    Pong,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct KeyCursor {
+    pub key: String,
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SeqRange {
+    pub key: String,
+    pub from: u64,
+    pub to: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    Mutation(Mutation),
+    //NOTE: the mutations are in order, coalescing leaves holes in their seqs
+    //so the ranges say which seqs of every key the batch covers
+    Batch {
+        mutations: Vec<Mutation>,
+        ranges: Vec<SeqRange>,
+    },
+    Subscribed {
+        epoch: Uuid,
+        cursors: Vec<KeyCursor>,
+    },
+    //NOTE: sent before Subscribed, seq is the last mutation of the key when it was loaded,
+    //later mutations can already be part of data so they have to be applied idempotently
+    Snapshot {
+        key: String,
+        seq: u64,
+        data: Value,
+    },
+    Unsubscribed {
+        keys: Vec<String>,
+    },
+    ResyncRequired {
+        key: String,
+    },
+    Error {
+        code: ProtocolErrorCode,
+        message: String,
+    },
+    Ping,
+    Pong,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolErrorCode {
+    Malformed,
+    UnsupportedVersion,
+    InvalidKey,
+    Forbidden,
+}
+
+impl ServerFrame {
+    pub fn error(code: ProtocolErrorCode, message: impl Into<String>) -> Self {
+        ServerFrame::Error {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+pub fn is_valid_key(key: &str) -> bool {
+    SyncKey::from_str(key).is_ok()
+}
@@ -1,102 +1,541 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
 use async_broadcast::Receiver;
 use log::{debug, info, warn};
 use serde_json::{json, Value};
+use tokio::select;
 use tokio::spawn;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use uuid::Uuid;
 
-use crate::sync::{Mutation, SubscriptionMode};
+use crate::sync::protocol::{KeyCursor, ProtocolErrorCode, ServerFrame};
+use crate::sync::SubscriptionMode;
 
-use super::connections::UserConnections;
+use super::connections::{user_connections_ids, Connection, UserConnections};
+use super::history::{Replay, SyncHistory};
+use super::keys::SyncKey;
+use super::metrics::SyncMetrics;
+use super::snapshot::SyncSnapshots;
 use super::subs::SubscriptionManager;
-use super::SyncRequest;
+use super::transport::SyncTransport;
+use super::{Mutation, SyncRequest};
 
 #[derive(Debug, Clone)]
 pub struct SyncRouter {
     subcriptions: SubscriptionManager,
     user_connections: UserConnections,
+    history: SyncHistory,
+    snapshots: SyncSnapshots,
+    transport: Arc<dyn SyncTransport>,
+    metrics: Arc<SyncMetrics>,
 }
 
 impl SyncRouter {
-    pub fn new(subcriptions: SubscriptionManager, connections: UserConnections) -> Self {
+    pub fn new(
+        subcriptions: SubscriptionManager,
+        connections: UserConnections,
+        history: SyncHistory,
+        snapshots: SyncSnapshots,
+        transport: Arc<dyn SyncTransport>,
+        metrics: Arc<SyncMetrics>,
+    ) -> Self {
         Self {
             subcriptions,
             user_connections: connections,
+            history,
+            snapshots,
+            transport,
+            metrics,
         }
     }
 
-    pub async fn send_mutation(&self, key: String, data: Value) {
+    pub async fn send_mutation(
+        &self,
+        key: SyncKey,
+        data: Value,
+        mutation_id: Option<Uuid>,
+        entity: Option<String>,
+    ) {
         info!("SyncRouter: Started forwarding for key '{key}'");
+        let started = Instant::now();
+        let mutation = self.history.record(&key, data, mutation_id, entity);
+        let key = mutation.key.clone();
         let client_subscriptions = self.subcriptions.clone();
-        let user_connections = self.user_connections.clone();
-        if let Some(subscribed_clients_entry) = client_subscriptions.get_subscriptors(&key) {
-            for user_id in subscribed_clients_entry.iter() {
-                let data = data.clone();
-                if let Some(module) = key.split(':').next() {
-                    let module = module.to_string();
-                    if let Some(connection) = user_connections
-                        .get(user_id)
-                        .map(|entry| entry.value().clone())
-                    {
-                        if let Err(e) = connection
-                            .sender()
-                            .broadcast(json!(Mutation { module, data }))
-                            .await
-                        {
-                            log::error!(
-                                "SyncRouter: Failed to send message to client '{user_id}' for key '{key}': {e}"
-                            );
-                        } else {
-                            debug!(
-                                "SyncRouter: Sent message to client '{user_id}' for key '{key}'"
-                            );
+        let subscribed_connections: Vec<Uuid> = client_subscriptions
+            .get_subscriptors(&key)
+            .map(|entry| entry.iter().copied().collect())
+            .unwrap_or_default();
+        let mut deliveries = 0;
+        for connection in subscribed_connections {
+            if self.enqueue_mutation(&connection, mutation.clone()) {
+                deliveries += 1;
+            } else {
+                warn!(
+                    "SyncRouter: Connection '{connection}' subscribed to '{key}' \
+                     but no active WebSocket connection found. Removing subscriptions."
+                );
+                client_subscriptions.clear_subscriptions(&connection);
+            }
+        }
+        self.metrics.routed_mutation(deliveries, started.elapsed());
+    }
+
+    fn user_of(&self, connection: &Uuid) -> Option<Uuid> {
+        self.user_connections
+            .get(connection)
+            .map(|entry| entry.value().user_id)
+    }
+
+    fn connection(&self, client: &Uuid) -> Option<Connection> {
+        self.user_connections
+            .get(client)
+            .map(|entry| entry.value().clone())
+    }
+
+    fn enqueue_mutation(&self, client: &Uuid, mutation: Mutation) -> bool {
+        self.connection(client)
+            .is_some_and(|connection| connection.push_mutation(mutation))
+    }
+
+    fn send_to_client(&self, client: &Uuid, frame: Value) -> bool {
+        let Some(connection) = self.connection(client) else {
+            return false;
+        };
+        let queued = connection.push(frame);
+        if queued {
+            debug!("SyncRouter: Queued message for client '{client}'");
+        } else {
+            log::error!("SyncRouter: Failed to queue message for client '{client}'");
+        }
+        queued
+    }
+
+    //NOTE: runs on the worker of the connection, only the database work happens here,
+    //the routing loop is the one that applies the outcome
+    async fn authorize(&self, connection: Uuid, user_id: Uuid, control: Control) -> Authorized {
+        match control {
+            Control::Subscribe {
+                keys,
+                mode,
+                snapshot,
+            } => {
+                let (keys, denied) = self.subcriptions.authorize(keys, connection, user_id).await;
+                let mut snapshots = vec![];
+                if snapshot {
+                    for key in &keys {
+                        let seq = self.history.current_seq(key);
+                        match self.snapshots.load(key, user_id).await {
+                            Some(data) => snapshots.push(KeySnapshot {
+                                key: key.clone(),
+                                seq,
+                                data,
+                            }),
+                            None => {
+                                debug!("SyncRouter: No snapshot of '{key}' for '{connection}'")
+                            }
                         }
-                    } else {
-                        warn!(
-                            "SyncRouter: Client '{user_id}' subscribed to '{key}' \
-                             but no active WebSocket connection found. Removing subscriptions."
-                        );
-                        client_subscriptions.clear_subscriptions(user_id);
                     }
                 }
+                Authorized::Subscribe {
+                    connection,
+                    mode,
+                    keys,
+                    denied,
+                    snapshots,
+                }
             }
+            Control::Unsubscribe { keys, prefix } => Authorized::Unsubscribe {
+                connection,
+                keys,
+                prefix,
+            },
+            Control::Resume { epoch, cursors } => {
+                let keys = cursors.iter().map(|cursor| cursor.key.clone()).collect();
+                let (keys, denied) = self.subcriptions.authorize(keys, connection, user_id).await;
+                let cursors = cursors
+                    .into_iter()
+                    .filter(|cursor| keys.contains(&cursor.key))
+                    .collect();
+                Authorized::Resume {
+                    connection,
+                    epoch,
+                    cursors,
+                    denied,
+                }
+            }
+            Control::Reauthorize { keys } => {
+                let subscribed = self.subcriptions.connection_keys(&connection);
+                let keys = match keys {
+                    Some(keys) => keys
+                        .into_iter()
+                        .filter(|key| subscribed.contains(key))
+                        .collect(),
+                    None => subscribed,
+                };
+                let (_, denied) = self.subcriptions.authorize(keys, connection, user_id).await;
+                Authorized::Evict {
+                    connection,
+                    keys: denied,
+                }
+            }
+            Control::Clear => Authorized::Clear { connection },
+        }
+    }
+
+    fn control(
+        &self,
+        workers: &mut HashMap<Uuid, UnboundedSender<Control>>,
+        results: &UnboundedSender<Authorized>,
+        connection: Uuid,
+        control: Control,
+    ) {
+        let control = match workers.get(&connection) {
+            Some(worker) => match worker.send(control) {
+                Ok(()) => return,
+                Err(SendError(control)) => control,
+            },
+            None => control,
+        };
+        let Some(user_id) = self.user_of(&connection) else {
+            warn!("SyncRouter: Connection '{connection}' doesn't exist, ignoring request.");
+            return;
         };
+        let (worker, mut controls) = unbounded_channel();
+        let _ = worker.send(control);
+        workers.insert(connection, worker);
+        let router = self.clone();
+        let results = results.clone();
+        spawn(async move {
+            while let Some(control) = controls.recv().await {
+                let authorized = router.authorize(connection, user_id, control).await;
+                if results.send(authorized).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    fn subscribe(
+        &self,
+        connection: Uuid,
+        mode: SubscriptionMode,
+        keys: Vec<String>,
+        denied: Vec<String>,
+        snapshots: Vec<KeySnapshot>,
+    ) {
+        if !denied.is_empty() {
+            self.send_to_client(
+                &connection,
+                json!(ServerFrame::error(
+                    ProtocolErrorCode::Forbidden,
+                    format!("not allowed to subscribe to {}", denied.join(", ")),
+                )),
+            );
+        }
+        if let SubscriptionMode::ReplacePrefix(prefix) = mode {
+            let removed = self.subcriptions.unsubscribe_group(&prefix, &connection);
+            self.unsubscribed(removed, connection);
+        }
+        self.subcriptions.subscribe(keys.clone(), connection);
+        //NOTE: the writes commit before the outbox hands their mutations to the router,
+        //so a snapshot can already include mutations that are fanned out after its seq,
+        //the clients apply them idempotently. The ones fanned out while it was loading
+        //are replayed after it
+        for KeySnapshot { key, seq, data } in snapshots {
+            self.send_to_client(
+                &connection,
+                json!(ServerFrame::Snapshot {
+                    key: key.clone(),
+                    seq,
+                    data,
+                }),
+            );
+            let replay = self.history.replay(&key, seq);
+            self.replay(connection, key, replay);
+        }
+        let cursors: Vec<KeyCursor> = keys
+            .into_iter()
+            .map(|key| KeyCursor {
+                seq: self.history.current_seq(&key),
+                key,
+            })
+            .collect();
+        self.send_to_client(
+            &connection,
+            json!(ServerFrame::Subscribed {
+                epoch: self.history.epoch(),
+                cursors,
+            }),
+        );
+    }
+
+    fn unsubscribed(&self, keys: Vec<String>, client: Uuid) {
+        if !keys.is_empty() {
+            self.send_to_client(&client, json!(ServerFrame::Unsubscribed { keys }));
+        }
+    }
+
+    fn resume(&self, client: Uuid, epoch: Uuid, cursors: Vec<KeyCursor>, denied: Vec<String>) {
+        self.unsubscribed(denied, client);
+        self.subcriptions.subscribe(
+            cursors.iter().map(|cursor| cursor.key.clone()).collect(),
+            client,
+        );
+        for KeyCursor { key, seq } in cursors {
+            let replay = if epoch == self.history.epoch() {
+                self.history.replay(&key, seq)
+            } else {
+                Replay::ResyncRequired
+            };
+            self.replay(client, key, replay);
+        }
+    }
+
+    fn replay(&self, client: Uuid, key: String, replay: Replay) {
+        match replay {
+            Replay::Events(mutations) => {
+                debug!(
+                    "SyncRouter: Replaying {} mutations of '{key}' for '{client}'",
+                    mutations.len()
+                );
+                for mutation in mutations {
+                    self.enqueue_mutation(&client, mutation);
+                }
+            }
+            Replay::ResyncRequired => {
+                debug!("SyncRouter: '{client}' needs to resync '{key}'");
+                self.send_to_client(&client, json!(ServerFrame::ResyncRequired { key }));
+            }
+        }
+    }
+
+    fn apply(&self, workers: &HashMap<Uuid, UnboundedSender<Control>>, authorized: Authorized) {
+        //NOTE: the connection may be gone by the time its worker is done
+        if !workers.contains_key(&authorized.connection()) {
+            return;
+        }
+        match authorized {
+            Authorized::Subscribe {
+                connection,
+                mode,
+                keys,
+                denied,
+                snapshots,
+            } => self.subscribe(connection, mode, keys, denied, snapshots),
+            Authorized::Unsubscribe {
+                connection,
+                keys,
+                prefix,
+            } => {
+                let mut removed = self.subcriptions.unsubscribe(keys, &connection);
+                if let Some(prefix) = prefix {
+                    removed.extend(self.subcriptions.unsubscribe_group(&prefix, &connection));
+                }
+                self.unsubscribed(removed, connection);
+            }
+            Authorized::Resume {
+                connection,
+                epoch,
+                cursors,
+                denied,
+            } => self.resume(connection, epoch, cursors, denied),
+            Authorized::Evict { connection, keys } => {
+                let removed = self.subcriptions.unsubscribe(keys, &connection);
+                self.unsubscribed(removed, connection);
+            }
+            Authorized::Clear { connection } => {
+                let removed = self.subcriptions.clear_subscriptions(&connection);
+                info!(
+                    "SyncRouter: Cleared {} subscriptions of '{connection}'",
+                    removed.len()
+                );
+                self.unsubscribed(removed, connection);
+            }
+        }
     }
 
     pub async fn start(&self, mut receiver: Receiver<SyncRequest>) {
         info!("SyncRouter: Starting message routing loop...");
 
-        while let Ok(receiver) = receiver.recv().await {
-            let router = self.clone();
-            spawn(async move {
-                match receiver {
-                    SyncRequest::Mutation { key, data } => {
-                        router.send_mutation(key, data).await;
+        let mut workers: HashMap<Uuid, UnboundedSender<Control>> = HashMap::new();
+        let (results, mut authorized) = unbounded_channel();
+        //NOTE: requests are handled in order, this keeps the seq of every key
+        //monotonic for each client and the replays consistent with the live mutations.
+        //The subscriptions of a connection go through its own worker, so the queries
+        //they need never hold up the fan-out
+        loop {
+            let request = select! {
+                request = receiver.recv() => match request {
+                    Ok(request) => request,
+                    Err(_) => break,
+                },
+                Some(authorized) = authorized.recv() => {
+                    self.apply(&workers, authorized);
+                    continue;
+                }
+            };
+            let request = match request {
+                SyncRequest::Relayed(request) => *request,
+                request => {
+                    if request.is_relayable() {
+                        self.transport.publish(&request).await;
                     }
-                    SyncRequest::Subscription {
+                    request
+                }
+            };
+            match request {
+                SyncRequest::Mutation {
+                    key,
+                    data,
+                    mutation_id,
+                    entity,
+                } => {
+                    self.send_mutation(key, data, mutation_id, entity).await;
+                }
+                SyncRequest::Subscription {
+                    keys,
+                    connection,
+                    action,
+                    snapshot,
+                } => self.control(
+                    &mut workers,
+                    &results,
+                    connection,
+                    Control::Subscribe {
                         keys,
-                        client,
-                        action,
-                    } => match action {
-                        SubscriptionMode::Add => {
-                            router.subcriptions.subscribe(keys, client);
-                        }
-                        SubscriptionMode::ReplacePrefix(prefix) => {
-                            router.subcriptions.unsubscribe_group(&prefix, &client);
-                            router.subcriptions.subscribe(keys, client);
-                        }
+                        mode: action,
+                        snapshot,
                     },
-                    SyncRequest::Unsubscription {
-                        keys,
-                        client,
-                        prefix,
-                    } => {
-                        router.subcriptions.unsubscribe(keys, &client);
-                        if let Some(prefix) = prefix {
-                            router.subcriptions.unsubscribe_group(&prefix, &client);
-                        }
+                ),
+                SyncRequest::Unsubscription {
+                    keys,
+                    connection,
+                    prefix,
+                } => self.control(
+                    &mut workers,
+                    &results,
+                    connection,
+                    Control::Unsubscribe { keys, prefix },
+                ),
+                SyncRequest::Resume {
+                    connection,
+                    epoch,
+                    cursors,
+                } => self.control(
+                    &mut workers,
+                    &results,
+                    connection,
+                    Control::Resume { epoch, cursors },
+                ),
+                SyncRequest::Reauthorize { user } => {
+                    for connection in user_connections_ids(&self.user_connections, user) {
+                        self.control(
+                            &mut workers,
+                            &results,
+                            connection,
+                            Control::Reauthorize { keys: None },
+                        );
+                    }
+                }
+                SyncRequest::ReauthorizeKeys { keys } => {
+                    let keys: Vec<String> = keys.iter().map(SyncKey::to_string).collect();
+                    for connection in self.subcriptions.connections_of(&keys) {
+                        self.control(
+                            &mut workers,
+                            &results,
+                            connection,
+                            Control::Reauthorize {
+                                keys: Some(keys.clone()),
+                            },
+                        );
                     }
                 }
-            });
+                SyncRequest::Disconnected { connection } => {
+                    workers.remove(&connection);
+                    self.subcriptions.clear_subscriptions(&connection);
+                }
+                SyncRequest::ClearSubscriptions { connection } => {
+                    self.control(&mut workers, &results, connection, Control::Clear);
+                }
+                SyncRequest::Relayed(_) => {
+                    warn!("SyncRouter: Ignoring a nested relayed request.");
+                }
+            }
         }
         info!("SyncRouter: Message routing loop stopped.");
     }
 }
+
+//NOTE: what a connection asked for, its worker handles them one after the other
+#[derive(Debug)]
+enum Control {
+    Subscribe {
+        keys: Vec<String>,
+        mode: SubscriptionMode,
+        snapshot: bool,
+    },
+    Unsubscribe {
+        keys: Vec<String>,
+        prefix: Option<String>,
+    },
+    Resume {
+        epoch: Uuid,
+        cursors: Vec<KeyCursor>,
+    },
+    Reauthorize {
+        keys: Option<Vec<String>>,
+    },
+    Clear,
+}
+
+#[derive(Debug)]
+struct KeySnapshot {
+    key: String,
+    seq: u64,
+    data: Value,
+}
+
+//NOTE: the outcome of a control, with the keys already checked against the database
+#[derive(Debug)]
+enum Authorized {
+    Subscribe {
+        connection: Uuid,
+        mode: SubscriptionMode,
+        keys: Vec<String>,
+        denied: Vec<String>,
+        snapshots: Vec<KeySnapshot>,
+    },
+    Unsubscribe {
+        connection: Uuid,
+        keys: Vec<String>,
+        prefix: Option<String>,
+    },
+    Resume {
+        connection: Uuid,
+        epoch: Uuid,
+        cursors: Vec<KeyCursor>,
+        denied: Vec<String>,
+    },
+    Evict {
+        connection: Uuid,
+        keys: Vec<String>,
+    },
+    Clear {
+        connection: Uuid,
+    },
+}
+
+impl Authorized {
+    fn connection(&self) -> Uuid {
+        match self {
+            Authorized::Subscribe { connection, .. }
+            | Authorized::Unsubscribe { connection, .. }
+            | Authorized::Resume { connection, .. }
+            | Authorized::Evict { connection, .. }
+            | Authorized::Clear { connection } => *connection,
+        }
+    }
+}
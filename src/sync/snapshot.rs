@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+use log::error;
+use serde_json::{json, Value};
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use crate::entities::member::Member;
+use crate::entities::message::{ChannelMessage, MessagePage};
+use crate::entities::server::Server;
+use crate::entities::Error;
+
+use super::keys::SyncKey;
+
+#[derive(Debug, Clone)]
+pub struct SyncSnapshots {
+    pool: MySqlPool,
+}
+
+impl SyncSnapshots {
+    pub fn new(pool: MySqlPool) -> Self {
+        Self { pool }
+    }
+
+    //NOTE: the keys were authorized before, this only builds the data the view starts from
+    pub async fn load(&self, key: &str, user_id: Uuid) -> Option<Value> {
+        let snapshot = match SyncKey::from_str(key).ok()? {
+            SyncKey::Channel(channel_id) => self.channel_messages(channel_id, user_id).await,
+            SyncKey::Thread(thread_id) => self.thread_messages(thread_id, user_id).await,
+            SyncKey::ServerChannels(server_id) => Server::get_channels(server_id, &self.pool)
+                .await
+                .map(|channels| json!(channels)),
+            SyncKey::ServerCategories(server_id) => {
+                Server::get_server_categories(server_id, &self.pool)
+                    .await
+                    .map(|categories| json!(categories))
+            }
+            _ => return None,
+        };
+        snapshot
+            .inspect_err(|err| {
+                error!("SyncSnapshots: Failed to load '{key}' for '{user_id}': {err:?}")
+            })
+            .ok()
+    }
+
+    //NOTE: only the latest page, the client keeps the older pages it loaded
+    async fn channel_messages(&self, channel_id: Uuid, user_id: Uuid) -> Result<Value, Error> {
+        let member = Member::get_user_member_on_channel(user_id, channel_id, &self.pool).await?;
+        let messages = ChannelMessage::get_channel_messages(
+            channel_id,
+            member.id,
+            MessagePage::latest(),
+            &self.pool,
+        )
+        .await?;
+        Ok(json!(messages))
+    }
+
+    async fn thread_messages(&self, thread_id: Uuid, user_id: Uuid) -> Result<Value, Error> {
+        let member = Member::get_user_member_on_thread(user_id, thread_id, &self.pool).await?;
+        let messages = ChannelMessage::get_thread_messages(
+            thread_id,
+            member.id,
+            MessagePage::latest(),
+            &self.pool,
+        )
+        .await?;
+        Ok(json!(messages))
+    }
+}
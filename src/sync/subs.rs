@@ -2,29 +2,64 @@ use std::collections::HashSet;
 use std::sync::Arc;
 
 use dashmap::DashMap;
-use log::info;
+use log::{info, warn};
+use sqlx::MySqlPool;
 use uuid::Uuid;
 
-type UserSubscriptions = Arc<DashMap<Uuid, HashSet<String>>>;
+use super::access::SubscriptionAccess;
+
+type ConnectionSubscriptions = Arc<DashMap<Uuid, HashSet<String>>>;
 
 #[derive(Debug, Clone)]
 pub struct SubscriptionManager {
     subscriptions: Arc<dashmap::DashMap<String, HashSet<Uuid>>>,
-    user_subscriptions: UserSubscriptions,
-}
-
-impl Default for SubscriptionManager {
-    fn default() -> Self {
-        Self::new()
-    }
+    connection_subscriptions: ConnectionSubscriptions,
+    access: SubscriptionAccess,
 }
 
 impl SubscriptionManager {
-    pub fn new() -> Self {
+    pub fn new(pool: MySqlPool) -> Self {
         Self {
             subscriptions: Arc::new(DashMap::new()),
-            user_subscriptions: Arc::new(DashMap::new()),
+            connection_subscriptions: Arc::new(DashMap::new()),
+            access: SubscriptionAccess::new(pool),
+        }
+    }
+
+    pub async fn authorize(
+        &self,
+        keys: Vec<String>,
+        connection: Uuid,
+        user_id: Uuid,
+    ) -> (Vec<String>, Vec<String>) {
+        let mut allowed = vec![];
+        let mut denied = vec![];
+        for key in keys {
+            if self.access.can_subscribe(&key, user_id).await {
+                allowed.push(key);
+            } else {
+                warn!("Connection '{connection}' of '{user_id}' is not allowed to subscribe to key '{key}'.");
+                denied.push(key);
+            }
         }
+        (allowed, denied)
+    }
+
+    pub fn connection_keys(&self, connection: &Uuid) -> Vec<String> {
+        self.connection_subscriptions
+            .get(connection)
+            .map(|keys| keys.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn connections_of(&self, keys: &[String]) -> HashSet<Uuid> {
+        let mut connections: HashSet<Uuid> = HashSet::new();
+        for key in keys {
+            if let Some(subscriptors) = self.subscriptions.get(key) {
+                connections.extend(subscriptors.iter().copied());
+            }
+        }
+        connections
     }
 
     pub fn get_subscriptors(
@@ -34,6 +69,12 @@ impl SubscriptionManager {
         self.subscriptions.get(key)
     }
 
+    pub fn is_subscribed(&self, key: &str, client: &Uuid) -> bool {
+        self.connection_subscriptions
+            .get(client)
+            .is_some_and(|keys| keys.contains(key))
+    }
+
     pub fn subscribe(&self, keys: Vec<String>, client: Uuid) {
         for key in keys {
             info!("Client '{client}' subscribed to key '{key}'.");
@@ -42,19 +83,21 @@ impl SubscriptionManager {
                 .or_default()
                 .insert(client);
 
-            self.user_subscriptions
+            self.connection_subscriptions
                 .entry(client)
                 .or_default()
                 .insert(key);
         }
     }
 
-    pub fn unsubscribe(&self, keys: Vec<String>, client: &Uuid) {
+    pub fn unsubscribe(&self, keys: Vec<String>, client: &Uuid) -> Vec<String> {
+        let mut unsubscribed = vec![];
         for key in keys {
             if let Some(mut entry) = self.subscriptions.get_mut(&key) {
                 let removed = entry.remove(client);
                 if removed {
                     info!("Client '{client}' unsubscribed from key '{key}'.");
+                    unsubscribed.push(key.clone());
                 }
                 if entry.is_empty() {
                     drop(entry);
@@ -63,15 +106,17 @@ impl SubscriptionManager {
                 }
             }
 
-            if let Some(mut sub_keys) = self.user_subscriptions.get_mut(client) {
+            if let Some(mut sub_keys) = self.connection_subscriptions.get_mut(client) {
                 sub_keys.remove(&key);
             }
         }
+        unsubscribed
     }
 
-    pub fn clear_subscriptions(&self, client: &Uuid) {
-        if let Some(mut keys) = self.user_subscriptions.get_mut(client) {
-            for key in keys.drain() {
+    pub fn clear_subscriptions(&self, client: &Uuid) -> Vec<String> {
+        let mut cleared = vec![];
+        if let Some((_, keys)) = self.connection_subscriptions.remove(client) {
+            for key in keys {
                 if let Some(mut entry) = self.subscriptions.get_mut(&key) {
                     let removed = entry.remove(client);
                     if removed {
@@ -83,12 +128,29 @@ impl SubscriptionManager {
                         info!("Key '{key}' has no more subscribers, removing.");
                     }
                 }
+                cleared.push(key);
             }
         }
+        cleared
+    }
+
+    pub fn subscriber_counts(&self) -> Vec<(String, usize)> {
+        self.subscriptions
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().len()))
+            .collect()
+    }
+
+    pub fn subscription_count(&self, client: &Uuid) -> usize {
+        self.connection_subscriptions
+            .get(client)
+            .map(|keys| keys.len())
+            .unwrap_or_default()
     }
 
-    pub fn unsubscribe_group(&self, prefix: &str, client: &Uuid) {
-        if let Some(mut user_keys) = self.user_subscriptions.get_mut(client) {
+    pub fn unsubscribe_group(&self, prefix: &str, client: &Uuid) -> Vec<String> {
+        let mut unsubscribed = vec![];
+        if let Some(mut user_keys) = self.connection_subscriptions.get_mut(client) {
             let mut to_remove = Vec::new();
             for key in user_keys.iter() {
                 if key.starts_with(prefix) {
@@ -99,6 +161,7 @@ impl SubscriptionManager {
                 if let Some(mut subs) = self.subscriptions.get_mut(&key) {
                     subs.remove(client);
                     if subs.is_empty() {
+                        drop(subs);
                         self.subscriptions.remove(&key);
                         info!("Key '{key}' has no more subscribers, removing.");
                     }
@@ -106,7 +169,9 @@ impl SubscriptionManager {
 
                 user_keys.remove(&key);
                 info!("Client '{client}' unsubscribed from group key '{key}'.");
+                unsubscribed.push(key);
             }
         }
+        unsubscribed
     }
 }
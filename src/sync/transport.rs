@@ -0,0 +1,512 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_broadcast::Sender;
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+use sqlx::types::Json;
+use sqlx::{MySqlPool, QueryBuilder};
+use tokio::spawn;
+use tokio::sync::mpsc;
+use tokio::time::{interval, Instant};
+use uuid::Uuid;
+
+use super::SyncRequest;
+
+const RELAY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const RELAY_BATCH_SIZE: u32 = 500;
+const RELAY_WRITER_CAPACITY: usize = 4096;
+const RELAY_GAP_TIMEOUT: Duration = Duration::from_secs(10);
+const RELAY_MAX_GAPS: u64 = 10_000;
+const RELAY_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const RELAY_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+const RELAY_NODE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+#[async_trait]
+pub trait SyncTransport: Debug + Send + Sync {
+    //NOTE: called by the router for every request that has to reach the other nodes,
+    //the local delivery is always done by the router itself
+    async fn publish(&self, request: &SyncRequest);
+
+    //NOTE: the requests of the other nodes are sent back into the router as SyncRequest::Relayed,
+    //so they go through the same ordered loop as the local ones
+    async fn start(&self, router: Sender<SyncRequest>);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TransportKind {
+    #[default]
+    Local,
+    MySql,
+}
+
+impl FromStr for TransportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(TransportKind::Local),
+            "mysql" => Ok(TransportKind::MySql),
+            other => Err(format!("unknown sync transport '{other}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LocalTransport;
+
+#[async_trait]
+impl SyncTransport for LocalTransport {
+    async fn publish(&self, _request: &SyncRequest) {}
+
+    async fn start(&self, _router: Sender<SyncRequest>) {}
+}
+
+type PublishedRequests = Arc<Mutex<Option<mpsc::Receiver<SyncRequest>>>>;
+
+#[derive(Debug, Clone)]
+pub struct MySqlTransport {
+    node_id: Uuid,
+    pool: MySqlPool,
+    writer: mpsc::Sender<SyncRequest>,
+    published: PublishedRequests,
+}
+
+//NOTE: the rows of this node are read too, so the ids it skips over are real gaps,
+//the request is only loaded for the rows of the other nodes
+#[derive(sqlx::FromRow)]
+struct RelayedRequest {
+    id: u64,
+    request: Option<Json<SyncRequest>>,
+}
+
+//NOTE: the auto increment id is taken on insert but the row is only visible once it commits,
+//with several nodes inserting a lower id can show up after a higher one was already read.
+//The ids skipped over are kept as gaps and queried again until they show up or time out
+#[derive(Debug, Default)]
+struct RelayCursor {
+    last_id: u64,
+    gaps: BTreeMap<u64, Instant>,
+}
+
+impl RelayCursor {
+    fn new(last_id: u64) -> Self {
+        Self {
+            last_id,
+            gaps: BTreeMap::new(),
+        }
+    }
+
+    //NOTE: returns false for the ids that were already read
+    fn read(&mut self, id: u64, now: Instant) -> bool {
+        if id <= self.last_id {
+            return self.gaps.remove(&id).is_some();
+        }
+        let skipped = id - self.last_id - 1;
+        if skipped > RELAY_MAX_GAPS {
+            warn!("MySqlTransport: Skipping {skipped} relay ids before '{id}'");
+        }
+        for gap in id.saturating_sub(RELAY_MAX_GAPS).max(self.last_id + 1)..id {
+            self.gaps.insert(gap, now);
+        }
+        self.last_id = id;
+        true
+    }
+
+    fn gaps(&self) -> Vec<u64> {
+        self.gaps.keys().copied().collect()
+    }
+
+    //NOTE: an id that never shows up belongs to an insert that failed
+    fn expire(&mut self, now: Instant) {
+        self.gaps
+            .retain(|_, since| now.duration_since(*since) < RELAY_GAP_TIMEOUT);
+    }
+
+    //NOTE: every row up to the position was read, the relay table can be pruned up to it
+    fn position(&self) -> u64 {
+        self.gaps
+            .keys()
+            .next()
+            .map(|gap| gap - 1)
+            .unwrap_or(self.last_id)
+    }
+}
+
+impl MySqlTransport {
+    pub fn new(pool: MySqlPool) -> Self {
+        let (writer, published) = mpsc::channel(RELAY_WRITER_CAPACITY);
+        Self {
+            node_id: Uuid::new_v4(),
+            pool,
+            writer,
+            published: Arc::new(Mutex::new(Some(published))),
+        }
+    }
+
+    async fn insert(&self, requests: &[SyncRequest]) -> Result<(), sqlx::Error> {
+        let mut query_builder = QueryBuilder::new("INSERT INTO sync_relay (node_id, request) ");
+        query_builder.push_values(requests, |mut row, request| {
+            row.push_bind(self.node_id).push_bind(Json(request));
+        });
+        query_builder.build().execute(&self.pool).await?;
+        Ok(())
+    }
+
+    //NOTE: the router only hands the requests to the writer, it inserts whatever
+    //was published while the previous insert was running in a single statement
+    async fn write(self, mut published: mpsc::Receiver<SyncRequest>) {
+        let mut requests = Vec::with_capacity(RELAY_BATCH_SIZE as usize);
+        while published
+            .recv_many(&mut requests, RELAY_BATCH_SIZE as usize)
+            .await
+            != 0
+        {
+            debug!("MySqlTransport: Publishing {} requests", requests.len());
+            if let Err(err) = self.insert(&requests).await {
+                error!(
+                    "MySqlTransport: Failed to publish {} requests: {err}",
+                    requests.len()
+                );
+            }
+            requests.clear();
+        }
+        warn!("MySqlTransport: The relay writer stopped.");
+    }
+
+    async fn last_id(&self) -> Result<u64, sqlx::Error> {
+        let last: Option<u64> = sqlx::query_scalar("SELECT MAX(id) FROM sync_relay")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(last.unwrap_or_default())
+    }
+
+    async fn fetch(&self, after: u64) -> Result<Vec<RelayedRequest>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, IF(node_id = ?, NULL, request) AS request FROM sync_relay WHERE id > ? ORDER BY id LIMIT ?",
+        )
+        .bind(self.node_id)
+        .bind(after)
+        .bind(RELAY_BATCH_SIZE)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn fetch_gaps(&self, gaps: &[u64]) -> Result<Vec<RelayedRequest>, sqlx::Error> {
+        if gaps.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut query_builder = QueryBuilder::new("SELECT id, IF(node_id = ");
+        query_builder
+            .push_bind(self.node_id)
+            .push(", NULL, request) AS request FROM sync_relay WHERE id IN (");
+        let mut separated = query_builder.separated(", ");
+        for gap in gaps {
+            separated.push_bind(*gap);
+        }
+        separated.push_unseparated(") ORDER BY id");
+        query_builder.build_query_as().fetch_all(&self.pool).await
+    }
+
+    async fn heartbeat(&self, position: u64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO sync_relay_nodes (node_id, position) VALUES (?, ?) ON DUPLICATE KEY UPDATE position = VALUES(position), seen_at = CURRENT_TIMESTAMP",
+        )
+        .bind(self.node_id)
+        .bind(position)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    //NOTE: a row is only deleted once every live node has read it,
+    //a node that stopped reporting its position for too long is left out
+    async fn prune(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM sync_relay_nodes WHERE seen_at < NOW() - INTERVAL ? SECOND")
+            .bind(RELAY_NODE_TIMEOUT.as_secs())
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            "DELETE FROM sync_relay WHERE id <= (SELECT MIN(position) FROM sync_relay_nodes)",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    //NOTE: returns false when the router is closed
+    async fn relay(
+        &self,
+        cursor: &mut RelayCursor,
+        requests: Vec<RelayedRequest>,
+        router: &Sender<SyncRequest>,
+    ) -> bool {
+        let now = Instant::now();
+        for RelayedRequest { id, request } in requests {
+            if !cursor.read(id, now) {
+                continue;
+            }
+            let Some(request) = request else {
+                continue;
+            };
+            debug!("MySqlTransport: Relaying request '{id}'");
+            if router
+                .broadcast(SyncRequest::Relayed(Box::new(request.0)))
+                .await
+                .is_err()
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[async_trait]
+impl SyncTransport for MySqlTransport {
+    async fn publish(&self, request: &SyncRequest) {
+        if self.writer.send(request.clone()).await.is_err() {
+            error!("MySqlTransport: The relay writer is closed, dropping the request.");
+        }
+    }
+
+    async fn start(&self, router: Sender<SyncRequest>) {
+        let published = self
+            .published
+            .lock()
+            .expect("the relay writer lock shouldn't be poisoned")
+            .take();
+        if let Some(published) = published {
+            spawn(self.clone().write(published));
+        }
+        //NOTE: only what is published after the node starts is relayed,
+        //the clients of a new node start from a fresh epoch anyway
+        let last_id = match self.last_id().await {
+            Ok(last_id) => last_id,
+            Err(err) => {
+                error!("MySqlTransport: Failed to read the relay position: {err}");
+                0
+            }
+        };
+        if let Err(err) = self.heartbeat(last_id).await {
+            error!("MySqlTransport: Failed to register the node: {err}");
+        }
+        info!(
+            "MySqlTransport: Node '{}' relaying from '{last_id}'",
+            self.node_id
+        );
+        let transport = self.clone();
+        spawn(async move {
+            let mut cursor = RelayCursor::new(last_id);
+            let mut poll = interval(RELAY_POLL_INTERVAL);
+            let mut polls: u64 = 0;
+            let heartbeat_polls =
+                (RELAY_HEARTBEAT_INTERVAL.as_millis() / RELAY_POLL_INTERVAL.as_millis()) as u64;
+            let prune_polls =
+                (RELAY_PRUNE_INTERVAL.as_millis() / RELAY_POLL_INTERVAL.as_millis()) as u64;
+            loop {
+                poll.tick().await;
+                polls += 1;
+                let fetched = match transport.fetch(cursor.last_id).await {
+                    Ok(requests) => transport.relay(&mut cursor, requests, &router).await,
+                    Err(err) => {
+                        error!("MySqlTransport: Failed to fetch relayed requests: {err}");
+                        true
+                    }
+                };
+                let refetched = match transport.fetch_gaps(&cursor.gaps()).await {
+                    Ok(requests) => transport.relay(&mut cursor, requests, &router).await,
+                    Err(err) => {
+                        error!("MySqlTransport: Failed to fetch the relay gaps: {err}");
+                        true
+                    }
+                };
+                if !fetched || !refetched {
+                    warn!("MySqlTransport: The router is closed, stopping the relay.");
+                    return;
+                }
+                cursor.expire(Instant::now());
+                if polls % heartbeat_polls == 0 {
+                    if let Err(err) = transport.heartbeat(cursor.position()).await {
+                        error!("MySqlTransport: Failed to report the relay position: {err}");
+                    }
+                }
+                if polls % prune_polls == 0 {
+                    if let Err(err) = transport.prune().await {
+                        error!("MySqlTransport: Failed to prune the relay table: {err}");
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_broadcast::{broadcast, Receiver};
+    use tokio::time::timeout;
+
+    use super::*;
+
+    const RELAY_WAIT: Duration = Duration::from_secs(5);
+
+    fn reauthorize(user: Uuid) -> SyncRequest {
+        SyncRequest::Reauthorize { user }
+    }
+
+    async fn relayed_user(requests: &mut Receiver<SyncRequest>) -> Uuid {
+        let request = timeout(RELAY_WAIT, requests.recv())
+            .await
+            .expect("relay the request in time")
+            .expect("keep the router open");
+        match request {
+            SyncRequest::Relayed(request) => match *request {
+                SyncRequest::Reauthorize { user } => user,
+                request => panic!("unexpected relayed request {request:?}"),
+            },
+            request => panic!("unexpected request {request:?}"),
+        }
+    }
+
+    async fn insert_with_id(pool: &MySqlPool, id: u64, request: &SyncRequest) {
+        sqlx::query("INSERT INTO sync_relay (id, node_id, request) VALUES (?, ?, ?)")
+            .bind(id)
+            .bind(Uuid::new_v4())
+            .bind(Json(request))
+            .execute(pool)
+            .await
+            .expect("insert the relay row");
+    }
+
+    async fn relay_rows(pool: &MySqlPool) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM sync_relay")
+            .fetch_one(pool)
+            .await
+            .expect("count the relay rows")
+    }
+
+    #[test]
+    fn cursor_reads_ids_in_order() {
+        let now = Instant::now();
+        let mut cursor = RelayCursor::new(0);
+        assert!(cursor.read(1, now));
+        assert!(cursor.read(2, now));
+        assert!(!cursor.read(2, now));
+        assert!(cursor.gaps().is_empty());
+        assert_eq!(cursor.position(), 2);
+    }
+
+    #[test]
+    fn cursor_keeps_skipped_ids_until_they_show_up() {
+        let now = Instant::now();
+        let mut cursor = RelayCursor::new(3);
+        assert!(cursor.read(6, now));
+        assert_eq!(cursor.gaps(), vec![4, 5]);
+        assert_eq!(cursor.position(), 3);
+
+        assert!(cursor.read(5, now));
+        assert!(!cursor.read(5, now));
+        assert_eq!(cursor.gaps(), vec![4]);
+        assert_eq!(cursor.position(), 3);
+
+        assert!(cursor.read(4, now));
+        assert!(cursor.gaps().is_empty());
+        assert_eq!(cursor.position(), 6);
+    }
+
+    #[test]
+    fn cursor_expires_gaps_that_never_show_up() {
+        let now = Instant::now();
+        let mut cursor = RelayCursor::new(0);
+        cursor.read(3, now);
+        cursor.expire(now);
+        assert_eq!(cursor.gaps(), vec![1, 2]);
+
+        cursor.expire(now + RELAY_GAP_TIMEOUT);
+        assert!(cursor.gaps().is_empty());
+        assert_eq!(cursor.position(), 3);
+        assert!(!cursor.read(1, now));
+    }
+
+    #[test]
+    fn cursor_bounds_the_gaps_of_a_jump() {
+        let now = Instant::now();
+        let mut cursor = RelayCursor::new(0);
+        cursor.read(RELAY_MAX_GAPS * 2, now);
+        assert_eq!(cursor.gaps().len() as u64, RELAY_MAX_GAPS);
+        assert_eq!(cursor.position(), RELAY_MAX_GAPS - 1);
+    }
+
+    //NOTE: the tests below run two nodes against the database in DATABASE_URL,
+    //run them with `cargo test --features ssr -- --ignored`
+    #[sqlx::test]
+    #[ignore = "needs a MySQL server in DATABASE_URL"]
+    async fn relays_requests_between_nodes(pool: MySqlPool) {
+        let first = MySqlTransport::new(pool.clone());
+        let second = MySqlTransport::new(pool.clone());
+        let (first_router, mut first_requests) = broadcast(16);
+        let (second_router, mut second_requests) = broadcast(16);
+        first.start(first_router).await;
+        second.start(second_router).await;
+
+        let from_first = Uuid::new_v4();
+        let from_second = Uuid::new_v4();
+        first.publish(&reauthorize(from_first)).await;
+        second.publish(&reauthorize(from_second)).await;
+
+        assert_eq!(relayed_user(&mut second_requests).await, from_first);
+        assert_eq!(relayed_user(&mut first_requests).await, from_second);
+        assert!(timeout(Duration::from_secs(1), first_requests.recv())
+            .await
+            .is_err());
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a MySQL server in DATABASE_URL"]
+    async fn relays_rows_that_commit_after_a_higher_id(pool: MySqlPool) {
+        let node = MySqlTransport::new(pool.clone());
+        let (router, mut requests) = broadcast(16);
+        node.start(router).await;
+
+        let late = Uuid::new_v4();
+        let early = Uuid::new_v4();
+        insert_with_id(&pool, 2, &reauthorize(early)).await;
+        assert_eq!(relayed_user(&mut requests).await, early);
+
+        insert_with_id(&pool, 1, &reauthorize(late)).await;
+        assert_eq!(relayed_user(&mut requests).await, late);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a MySQL server in DATABASE_URL"]
+    async fn prunes_up_to_the_slowest_node(pool: MySqlPool) {
+        let lagging = MySqlTransport::new(pool.clone());
+        let current = MySqlTransport::new(pool.clone());
+        lagging
+            .heartbeat(0)
+            .await
+            .expect("register the lagging node");
+        current
+            .insert(&[reauthorize(Uuid::new_v4()), reauthorize(Uuid::new_v4())])
+            .await
+            .expect("publish the requests");
+        let last_id = current.last_id().await.expect("read the last id");
+        current
+            .heartbeat(last_id)
+            .await
+            .expect("register the current node");
+
+        current.prune().await.expect("prune the relay");
+        assert_eq!(relay_rows(&pool).await, 2);
+
+        lagging
+            .heartbeat(last_id - 1)
+            .await
+            .expect("move the lagging node");
+        current.prune().await.expect("prune the relay");
+        assert_eq!(relay_rows(&pool).await, 1);
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_broadcast::Sender;
+use dashmap::DashMap;
+use log::debug;
+use tokio::spawn;
+use tokio::time::{interval, Instant};
+use uuid::Uuid;
+
+use crate::messages::Message;
+
+use super::keys::{self, SyncKey};
+use super::subs::SubscriptionManager;
+use super::SyncRequest;
+
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
+
+#[derive(Debug, Clone)]
+pub struct TypingIndicators {
+    typing: Arc<DashMap<(SyncKey, Uuid), Instant>>,
+    sync: Sender<SyncRequest>,
+    subscriptions: SubscriptionManager,
+}
+
+impl TypingIndicators {
+    pub fn new(sync: Sender<SyncRequest>, subscriptions: SubscriptionManager) -> Self {
+        Self {
+            typing: Arc::new(DashMap::new()),
+            sync,
+            subscriptions,
+        }
+    }
+
+    pub fn can_type(key: &SyncKey) -> bool {
+        matches!(key, SyncKey::Channel(_) | SyncKey::Thread(_))
+    }
+
+    //NOTE: the subscription was already authorized against the membership,
+    //so only the connections that are listening on the chat can type on it
+    pub fn can_type_on(&self, key: &SyncKey, connection: &Uuid) -> bool {
+        Self::can_type(key)
+            && self
+                .subscriptions
+                .is_subscribed(&key.to_string(), connection)
+    }
+
+    pub async fn update(&self, key: SyncKey, user_id: Uuid, is_typing: bool) {
+        if !Self::can_type(&key) {
+            return;
+        }
+        let changed = if is_typing {
+            self.typing.insert((key, user_id), Instant::now()).is_none()
+        } else {
+            self.typing.remove(&(key, user_id)).is_some()
+        };
+        if changed {
+            self.broadcast(key, user_id, is_typing).await;
+        }
+    }
+
+    pub async fn clear_user(&self, user_id: Uuid) {
+        let keys: Vec<SyncKey> = self
+            .typing
+            .iter()
+            .filter(|entry| entry.key().1 == user_id)
+            .map(|entry| entry.key().0)
+            .collect();
+        for key in keys {
+            self.update(key, user_id, false).await;
+        }
+    }
+
+    async fn broadcast(&self, key: SyncKey, user_id: Uuid, is_typing: bool) {
+        let typing = Message::Typing {
+            user_id,
+            chat_id: key.id(),
+            is_typing,
+        };
+        let request = match key {
+            SyncKey::Channel(channel_id) => {
+                SyncRequest::mutation(keys::channel(channel_id), &typing)
+            }
+            SyncKey::Thread(thread_id) => SyncRequest::mutation(keys::thread(thread_id), &typing),
+            _ => return,
+        };
+        let _ = self.sync.broadcast(request).await;
+    }
+
+    pub async fn expire_typing(self) {
+        spawn(async move {
+            let mut interval = interval(Duration::from_secs(1));
+            debug!("Typing indicators expiration started.");
+            loop {
+                interval.tick().await;
+                let now = Instant::now();
+                let expired: Vec<(SyncKey, Uuid)> = self
+                    .typing
+                    .iter()
+                    .filter(|entry| now.duration_since(*entry.value()) > TYPING_TIMEOUT)
+                    .map(|entry| *entry.key())
+                    .collect();
+
+                for (key, user_id) in expired {
+                    debug!("Typing of '{user_id}' on '{key}' expired");
+                    self.update(key, user_id, false).await;
+                }
+            }
+        });
+    }
+}
@@ -1,6 +1,8 @@
 #[cfg(feature = "ssr")]
 pub mod server;
 
+#[cfg(feature = "ssr")]
+pub use self::server::deletions::FileDeletions;
 #[cfg(feature = "ssr")]
 pub use self::server::UploadThing;
 
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use log::{debug, error, info};
+use sqlx::{MySqlExecutor, MySqlPool, QueryBuilder};
+use tokio::spawn;
+use tokio::time::interval;
+
+use super::UploadThing;
+use crate::entities::Error;
+
+const DELETIONS_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const DELETIONS_BATCH_SIZE: u32 = 100;
+const DELETIONS_LEASE: Duration = Duration::from_secs(5 * 60);
+const DELETIONS_MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+//NOTE: the keys are staged in the transaction that removes their rows,
+//the worker deletes the files and keeps retrying with back-off until UploadThing accepts it
+#[derive(Debug, Clone)]
+pub struct FileDeletions {
+    pool: MySqlPool,
+    uploadthing: UploadThing,
+}
+
+#[derive(sqlx::FromRow)]
+struct FileDeletion {
+    id: u64,
+    file_key: String,
+    attempts: u32,
+}
+
+impl FileDeletions {
+    pub fn new(pool: MySqlPool, uploadthing: UploadThing) -> Self {
+        Self { pool, uploadthing }
+    }
+
+    pub async fn stage(file_keys: &[String], conn: impl MySqlExecutor<'_>) -> Result<(), Error> {
+        if file_keys.is_empty() {
+            return Ok(());
+        }
+        let mut query_builder = QueryBuilder::new("INSERT INTO file_deletions (file_key) ");
+        query_builder.push_values(file_keys, |mut row, file_key| {
+            row.push_bind(file_key);
+        });
+        query_builder.build().execute(conn).await?;
+        Ok(())
+    }
+
+    //NOTE: the due rows are leased before the request goes out, so the lock isn't held
+    //while waiting on UploadThing and another node doesn't pick them up meanwhile
+    async fn claim(&self) -> Result<Vec<FileDeletion>, Error> {
+        let mut tx = self.pool.begin().await?;
+        let deletions: Vec<FileDeletion> = sqlx::query_as(
+            "SELECT id, file_key, attempts FROM file_deletions WHERE next_attempt_at <= NOW() ORDER BY id LIMIT ? FOR UPDATE SKIP LOCKED",
+        )
+        .bind(DELETIONS_BATCH_SIZE)
+        .fetch_all(&mut *tx)
+        .await?;
+        if !deletions.is_empty() {
+            let mut query_builder =
+                QueryBuilder::new("UPDATE file_deletions SET next_attempt_at = NOW() + INTERVAL ");
+            query_builder
+                .push_bind(DELETIONS_LEASE.as_secs())
+                .push(" SECOND WHERE id IN (");
+            let mut separated = query_builder.separated(", ");
+            for deletion in &deletions {
+                separated.push_bind(deletion.id);
+            }
+            separated.push_unseparated(")");
+            query_builder.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(deletions)
+    }
+
+    async fn done(&self, deletions: &[FileDeletion]) -> Result<(), Error> {
+        let mut query_builder = QueryBuilder::new("DELETE FROM file_deletions WHERE id IN (");
+        let mut separated = query_builder.separated(", ");
+        for deletion in deletions {
+            separated.push_bind(deletion.id);
+        }
+        separated.push_unseparated(")");
+        query_builder.build().execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn retry(&self, deletion: &FileDeletion) -> Result<(), Error> {
+        let backoff = DELETIONS_POLL_INTERVAL
+            .saturating_mul(2_u32.saturating_pow(deletion.attempts))
+            .min(DELETIONS_MAX_BACKOFF);
+        sqlx::query(
+            "UPDATE file_deletions SET attempts = attempts + 1, next_attempt_at = NOW() + INTERVAL ? SECOND WHERE id = ?",
+        )
+        .bind(backoff.as_secs())
+        .bind(deletion.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete(&self) -> Result<usize, Error> {
+        let deletions = self.claim().await?;
+        if deletions.is_empty() {
+            return Ok(0);
+        }
+        let file_keys = deletions
+            .iter()
+            .map(|deletion| deletion.file_key.clone())
+            .collect();
+        match self.uploadthing.delete_files(file_keys).await {
+            Ok(()) => {
+                debug!("FileDeletions: Deleted {} files", deletions.len());
+                self.done(&deletions).await?;
+            }
+            Err(err) => {
+                error!(
+                    "FileDeletions: Failed to delete {} files, retrying later: {err}",
+                    deletions.len()
+                );
+                for deletion in &deletions {
+                    self.retry(deletion).await?;
+                }
+            }
+        }
+        Ok(deletions.len())
+    }
+
+    pub async fn start(&self) {
+        info!("FileDeletions: Starting the worker");
+        let deletions = self.clone();
+        spawn(async move {
+            let mut poll = interval(DELETIONS_POLL_INTERVAL);
+            loop {
+                poll.tick().await;
+                loop {
+                    match deletions.delete().await {
+                        Ok(deleted) if deleted as u32 == DELETIONS_BATCH_SIZE => continue,
+                        Ok(_) => break,
+                        Err(err) => {
+                            error!("FileDeletions: Failed to process the deletions: {err:?}");
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
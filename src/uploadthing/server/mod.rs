@@ -1,3 +1,4 @@
+pub mod deletions;
 pub mod list_files;
 pub mod upload_file;
 
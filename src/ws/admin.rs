@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::response::IntoResponse;
+use axum::Json;
+use http::StatusCode;
+use log::{info, warn};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::entities::user::AuthSession;
+use crate::state::AppState;
+use crate::sync::metrics::SyncMetricsSnapshot;
+use crate::sync::SyncRequest;
+
+#[derive(Debug, Clone, Default)]
+pub struct SyncAdmins(Arc<HashSet<Uuid>>);
+
+impl SyncAdmins {
+    //NOTE: SYNC_ADMINS is a comma separated list of user ids
+    pub fn from_env() -> Self {
+        let admins = std::env::var("SYNC_ADMINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .filter_map(|id| {
+                Uuid::from_str(id)
+                    .inspect_err(|_| warn!("SyncAdmins: Ignoring the invalid user id '{id}'"))
+                    .ok()
+            })
+            .collect();
+        SyncAdmins(Arc::new(admins))
+    }
+
+    pub fn contains(&self, user_id: &Uuid) -> bool {
+        self.0.contains(user_id)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncOverview {
+    pub metrics: SyncMetricsSnapshot,
+    pub router_backlog: usize,
+    pub connections: Vec<ConnectionOverview>,
+    pub keys: Vec<KeyOverview>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConnectionOverview {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub connected_secs: u64,
+    pub queue_depth: usize,
+    pub lagging: bool,
+    pub subscriptions: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyOverview {
+    pub key: String,
+    pub subscribers: usize,
+}
+
+fn authorize(auth_session: &AuthSession, state: &AppState) -> Result<Uuid, StatusCode> {
+    let Some(user) = &auth_session.current_user else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    if !state.sync_admins.contains(&user.id) {
+        warn!("SyncAdmin: '{}' is not a sync admin", user.id);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(user.id)
+}
+
+pub async fn sync_overview(
+    auth_session: AuthSession,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    if let Err(status) = authorize(&auth_session, &state) {
+        return status.into_response();
+    }
+    let mut connections: Vec<ConnectionOverview> = state
+        .user_connections
+        .iter()
+        .map(|entry| {
+            let connection = entry.value();
+            ConnectionOverview {
+                id: *entry.key(),
+                user_id: connection.user_id,
+                connected_secs: connection.created.elapsed().as_secs(),
+                queue_depth: connection.queue_depth(),
+                lagging: connection.is_lagging(),
+                subscriptions: state.subscriptions.subscription_count(entry.key()),
+            }
+        })
+        .collect();
+    connections.sort_by(|a, b| b.queue_depth.cmp(&a.queue_depth));
+    let mut keys: Vec<KeyOverview> = state
+        .subscriptions
+        .subscriber_counts()
+        .into_iter()
+        .map(|(key, subscribers)| KeyOverview { key, subscribers })
+        .collect();
+    keys.sort_by(|a, b| b.subscribers.cmp(&a.subscribers));
+    Json(SyncOverview {
+        metrics: state.outbound.metrics.snapshot(),
+        router_backlog: state.sync_sender.len(),
+        connections,
+        keys,
+    })
+    .into_response()
+}
+
+pub async fn disconnect_connection(
+    auth_session: AuthSession,
+    State(state): State<AppState>,
+    Path(connection_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let admin = match authorize(&auth_session, &state) {
+        Ok(admin) => admin,
+        Err(status) => return status,
+    };
+    let Some(connection) = state
+        .user_connections
+        .get(&connection_id)
+        .map(|entry| entry.value().clone())
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+    info!("SyncAdmin: '{admin}' disconnected '{connection_id}'");
+    //NOTE: the socket tasks close the connection, the client reconnects on its own
+    connection.close();
+    StatusCode::NO_CONTENT
+}
+
+pub async fn clear_subscriptions(
+    auth_session: AuthSession,
+    State(state): State<AppState>,
+    Path(connection_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let admin = match authorize(&auth_session, &state) {
+        Ok(admin) => admin,
+        Err(status) => return status,
+    };
+    if !state.user_connections.contains_key(&connection_id) {
+        return StatusCode::NOT_FOUND;
+    }
+    info!("SyncAdmin: '{admin}' cleared the subscriptions of '{connection_id}'");
+    let _ = state
+        .sync_sender
+        .broadcast(SyncRequest::ClearSubscriptions {
+            connection: connection_id,
+        })
+        .await;
+    StatusCode::ACCEPTED
+}
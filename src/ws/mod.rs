@@ -1,3 +1,7 @@
+pub mod admin;
+pub mod sse;
+
+use async_broadcast::{RecvError, Sender};
 use axum::{
     extract::{
         ws::{Message as WsMessage, WebSocket},
@@ -7,82 +11,339 @@ use axum::{
 };
 use futures::{SinkExt, StreamExt};
 use http::StatusCode;
-use log::{debug, error};
+use log::{debug, error, warn};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{interval, Instant};
+use uuid::Uuid;
 
 use crate::{
     entities::user::{AuthSession, User},
     state::AppState,
-    sync::connections::{Connection, ConnectionMessage},
+    sync::{
+        codec::{decode, Codec, Frame, PROTOCOLS},
+        connections::{Connection, ConnectionMessage},
+        keys::SyncKey,
+        protocol::{
+            is_valid_key, ClientFrame, ClientMessage, ProtocolErrorCode, ServerFrame,
+            HEARTBEAT_INTERVAL_MS, HEARTBEAT_TIMEOUT_MS, PROTOCOL_VERSION,
+        },
+        typing::TypingIndicators,
+        SubscriptionMode, SyncRequest,
+    },
 };
 
+const MAX_KEYS_PER_FRAME: usize = 256;
+
 pub async fn ws_handler(
     auth_session: AuthSession,
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
     if let Some(user) = auth_session.current_user {
-        return ws.on_upgrade(move |socket| handle_socket(socket, state, user));
+        return ws
+            .protocols(PROTOCOLS)
+            .on_upgrade(move |socket| handle_socket(socket, state, user));
     }
     (StatusCode::FORBIDDEN, "Unauthorized WebSocket connection").into_response()
 }
 
-async fn handle_socket(socket: WebSocket, state: AppState, user: User) {
-    let (mut sender, mut receiver) = socket.split();
-
-    let channels = state.user_connections.clone();
-
-    let connection = state.connection_sender;
-
-    let rx = {
-        match channels.get(&user.id) {
-            Some(channel) => channel.value().receiver(),
-            None => {
-                error!("The connection should exist by now, creating channel but its propably not up to date");
-                let bad_connection = Connection::new();
-                let receiver = bad_connection.receiver();
-                channels.insert(user.id, bad_connection);
-                receiver
-            }
+fn ws_message(codec: Codec, frame: &Value) -> Option<WsMessage> {
+    match codec.encode_value(frame) {
+        Ok(Frame::Text(text)) => Some(WsMessage::Text(text.into())),
+        Ok(Frame::Binary(bytes)) => Some(WsMessage::Binary(bytes.into())),
+        Err(err) => {
+            error!("WS: failed to encode a frame: {err}");
+            None
         }
-    };
+    }
+}
 
-    let _ = connection
-        .broadcast(ConnectionMessage::CompleteConnection { client: user.id })
-        .await;
+async fn handle_socket(socket: WebSocket, state: AppState, user: User) {
+    let codec = Codec::from_protocol(
+        socket
+            .protocol()
+            .and_then(|protocol| protocol.to_str().ok()),
+    );
+    debug!("WS: '{}' connected using {codec:?}", user.id);
+    let (mut sender, mut receiver) = socket.split();
 
-    let sync = state.sync_sender;
+    let (connection_id, user_connection) = open_connection(&state, user.id).await;
+    let mut rx = user_connection.receiver();
+    let closed = user_connection.closed();
 
-    let mut rx_clone = rx.clone();
+    let sync = state.sync_sender.clone();
+    let typing = state.typing.clone();
+    let user_id = user.id;
 
+    let outbound = user_connection.clone();
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx_clone.recv().await {
-            if sender
-                .send(WsMessage::Text(serde_json::to_string(&msg).unwrap().into()))
-                .await
-                .is_err()
-            {
+        loop {
+            let msg = match rx.recv().await {
+                Ok(msg) => msg,
+                Err(RecvError::Overflowed(skipped)) => {
+                    debug!("WS: client of '{user_id}' skipped {skipped} frames");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let Some(msg) = ws_message(codec, &msg) else {
+                continue;
+            };
+            if sender.send(msg).await.is_err() {
                 debug!("we got an error when sending the message");
                 break;
             }
+            //NOTE: once the queue is drained the client can refetch what we couldn't deliver
+            if rx.is_empty() {
+                for key in outbound.take_resync() {
+                    let resync = json!(ServerFrame::ResyncRequired { key });
+                    let Some(resync) = ws_message(codec, &resync) else {
+                        continue;
+                    };
+                    if sender.send(resync).await.is_err() {
+                        break;
+                    }
+                }
+            }
         }
     });
 
+    let typing_clone = typing.clone();
+    let sync_clone = sync.clone();
+    let last_seen = Arc::new(Mutex::new(Instant::now()));
+    let heartbeat = heartbeat(user_connection.clone(), last_seen.clone());
     let mut recv_task: tokio::task::JoinHandle<Result<(), anyhow::Error>> =
         tokio::spawn(async move {
-            let sync = sync.clone();
-            while let Some(Ok(WsMessage::Text(msg))) = receiver.next().await {
-                // if let Ok(msg) = serde_json::from_str(&msg) {
-                //     debug!("got this msg from the client: {msg:?}");
-                //     // let _ = sync.broadcast(msg).await;
-                // } else {
-                //     debug!("we got a msg but cant deserialize");
-                // }
+            while let Some(Ok(msg)) = receiver.next().await {
+                *last_seen.lock().unwrap() = Instant::now();
+                let frame = match msg {
+                    WsMessage::Text(text) => Frame::Text(text.to_string()),
+                    WsMessage::Binary(bytes) => Frame::Binary(bytes.to_vec()),
+                    WsMessage::Close(_) => break,
+                    _ => continue,
+                };
+                handle_client_frame(
+                    frame,
+                    &user,
+                    connection_id,
+                    &sync_clone,
+                    &typing_clone,
+                    &user_connection,
+                )
+                .await;
             }
             Ok(())
         });
 
     tokio::select! {
         _ = (&mut send_task) => recv_task.abort(),
-        _ = (&mut recv_task) => send_task.abort()
+        _ = (&mut recv_task) => send_task.abort(),
+        _ = closed.cancelled() => {
+            debug!("WS: closing the connection of '{user_id}'");
+            send_task.abort();
+            recv_task.abort();
+        }
+        _ = heartbeat => {
+            debug!("WS: the connection of '{user_id}' missed its heartbeats");
+            send_task.abort();
+            recv_task.abort();
+        }
     };
+
+    close_connection(&state, user_id, connection_id).await;
+}
+
+pub(crate) async fn open_connection(state: &AppState, user_id: Uuid) -> (Uuid, Connection) {
+    let connection_id = Uuid::new_v4();
+    let connection = Connection::new(user_id, state.outbound.clone());
+    state
+        .user_connections
+        .insert(connection_id, connection.clone());
+    let _ = state
+        .connection_sender
+        .broadcast(ConnectionMessage::CompleteConnection {
+            client: user_id,
+            connection: connection_id,
+        })
+        .await;
+    (connection_id, connection)
+}
+
+pub(crate) async fn close_connection(state: &AppState, user_id: Uuid, connection_id: Uuid) {
+    state.typing.clear_user(user_id).await;
+    let _ = state
+        .sync_sender
+        .broadcast(SyncRequest::Disconnected {
+            connection: connection_id,
+        })
+        .await;
+    let _ = state
+        .connection_sender
+        .broadcast(ConnectionMessage::DeleteConnection {
+            client: user_id,
+            connection: connection_id,
+        })
+        .await;
+}
+
+async fn heartbeat(connection: Connection, last_seen: Arc<Mutex<Instant>>) {
+    let timeout = Duration::from_millis(HEARTBEAT_TIMEOUT_MS.into());
+    let mut interval = interval(Duration::from_millis(HEARTBEAT_INTERVAL_MS.into()));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if last_seen.lock().unwrap().elapsed() > timeout {
+            return;
+        }
+        connection.push(json!(ServerFrame::Ping));
+    }
+}
+
+async fn handle_client_frame(
+    frame: Frame,
+    user: &User,
+    connection: Uuid,
+    sync: &Sender<SyncRequest>,
+    typing: &TypingIndicators,
+    reply: &Connection,
+) {
+    let frame = match decode::<ClientFrame>(frame) {
+        Ok(frame) => frame,
+        Err(err) => {
+            warn!("WS: malformed frame from '{}': {err}", user.id);
+            send_reply(
+                reply,
+                ServerFrame::error(ProtocolErrorCode::Malformed, err.to_string()),
+            );
+            return;
+        }
+    };
+    handle_client_message(frame, user.id, connection, sync, typing, reply).await;
+}
+
+pub(crate) async fn handle_client_message(
+    frame: ClientFrame,
+    user_id: Uuid,
+    connection: Uuid,
+    sync: &Sender<SyncRequest>,
+    typing: &TypingIndicators,
+    reply: &Connection,
+) {
+    if frame.version != PROTOCOL_VERSION {
+        send_reply(
+            reply,
+            ServerFrame::error(
+                ProtocolErrorCode::UnsupportedVersion,
+                format!(
+                    "protocol version {} is not supported, expected {PROTOCOL_VERSION}",
+                    frame.version
+                ),
+            ),
+        );
+        return;
+    }
+
+    match frame.message {
+        ClientMessage::Subscribe {
+            keys,
+            replace_prefix,
+            snapshot,
+        } => {
+            if let Err(error) = validate_keys(&keys, replace_prefix.as_deref()) {
+                send_reply(reply, error);
+                return;
+            }
+            let action = match replace_prefix {
+                Some(prefix) => SubscriptionMode::ReplacePrefix(prefix),
+                None => SubscriptionMode::Add,
+            };
+            let _ = sync
+                .broadcast(SyncRequest::Subscription {
+                    keys,
+                    connection,
+                    action,
+                    snapshot,
+                })
+                .await;
+        }
+        ClientMessage::Unsubscribe { keys, prefix } => {
+            if let Err(error) = validate_keys(&keys, prefix.as_deref()) {
+                send_reply(reply, error);
+                return;
+            }
+            let _ = sync
+                .broadcast(SyncRequest::Unsubscription {
+                    keys,
+                    prefix,
+                    connection,
+                })
+                .await;
+        }
+        ClientMessage::Typing { key, is_typing } => {
+            let Some(sync_key) = SyncKey::from_str(&key)
+                .ok()
+                .filter(|sync_key| typing.can_type_on(sync_key, &connection))
+            else {
+                send_reply(
+                    reply,
+                    ServerFrame::error(
+                        ProtocolErrorCode::InvalidKey,
+                        format!("can't type on '{key}'"),
+                    ),
+                );
+                return;
+            };
+            typing.update(sync_key, user_id, is_typing).await;
+        }
+        ClientMessage::Ack { seq } => {
+            debug!("WS: '{}' acked {seq}", user_id);
+        }
+        ClientMessage::Resume { epoch, cursors } => {
+            let keys: Vec<String> = cursors.iter().map(|cursor| cursor.key.clone()).collect();
+            if let Err(error) = validate_keys(&keys, None) {
+                send_reply(reply, error);
+                return;
+            }
+            let _ = sync
+                .broadcast(SyncRequest::Resume {
+                    connection,
+                    epoch,
+                    cursors,
+                })
+                .await;
+        }
+        ClientMessage::Ping => send_reply(reply, ServerFrame::Pong),
+        ClientMessage::Pong => {}
+    }
+}
+
+fn validate_keys(keys: &[String], prefix: Option<&str>) -> Result<(), ServerFrame> {
+    if keys.len() > MAX_KEYS_PER_FRAME {
+        return Err(ServerFrame::error(
+            ProtocolErrorCode::InvalidKey,
+            format!("a frame can't carry more than {MAX_KEYS_PER_FRAME} keys"),
+        ));
+    }
+    if let Some(key) = keys.iter().find(|key| !is_valid_key(key)) {
+        return Err(ServerFrame::error(
+            ProtocolErrorCode::InvalidKey,
+            format!("'{key}' is not a valid key"),
+        ));
+    }
+    if prefix.is_some_and(|prefix| prefix.is_empty()) {
+        return Err(ServerFrame::error(
+            ProtocolErrorCode::InvalidKey,
+            "the prefix can't be empty",
+        ));
+    }
+    Ok(())
+}
+
+fn send_reply(reply: &Connection, frame: ServerFrame) {
+    if !reply.push(json!(frame)) {
+        error!("WS: failed to reply to the client of '{}'", reply.user_id);
+    }
 }
@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::time::Duration;
+
+use async_broadcast::{Receiver, RecvError};
+use axum::extract::State;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use futures::stream::{self, StreamExt};
+use http::StatusCode;
+use log::debug;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::entities::user::AuthSession;
+use crate::state::AppState;
+use crate::sync::connections::Connection;
+use crate::sync::protocol::{ServerFrame, HEARTBEAT_INTERVAL_MS};
+
+use super::{close_connection, open_connection};
+
+//NOTE: the stream owns it, so the connection is cleaned up once the client goes away
+struct SseConnection {
+    state: AppState,
+    user_id: Uuid,
+    connection_id: Uuid,
+}
+
+impl Drop for SseConnection {
+    fn drop(&mut self) {
+        let state = self.state.clone();
+        let (user_id, connection_id) = (self.user_id, self.connection_id);
+        debug!("SSE: closing the connection of '{user_id}'");
+        tokio::spawn(async move { close_connection(&state, user_id, connection_id).await });
+    }
+}
+
+struct SseStream {
+    rx: Receiver<Value>,
+    connection: Connection,
+    pending: VecDeque<Value>,
+    guard: SseConnection,
+}
+
+impl SseStream {
+    async fn next(mut self) -> Option<(Value, Self)> {
+        if let Some(frame) = self.pending.pop_front() {
+            return Some((frame, self));
+        }
+        loop {
+            match self.rx.recv().await {
+                Ok(frame) => {
+                    //NOTE: once the queue is drained the client can refetch what we couldn't deliver
+                    if self.rx.is_empty() {
+                        self.pending.extend(
+                            self.connection
+                                .take_resync()
+                                .into_iter()
+                                .map(|key| json!(ServerFrame::ResyncRequired { key })),
+                        );
+                    }
+                    return Some((frame, self));
+                }
+                Err(RecvError::Overflowed(skipped)) => {
+                    debug!(
+                        "SSE: client of '{}' skipped {skipped} frames",
+                        self.guard.user_id
+                    );
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+pub async fn sse_handler(
+    auth_session: AuthSession,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let Some(user) = auth_session.current_user else {
+        return (StatusCode::FORBIDDEN, "Unauthorized event stream").into_response();
+    };
+    let (connection_id, connection) = open_connection(&state, user.id).await;
+    let closed = connection.closed();
+    let frames = SseStream {
+        rx: connection.receiver(),
+        connection,
+        pending: VecDeque::new(),
+        guard: SseConnection {
+            state,
+            user_id: user.id,
+            connection_id,
+        },
+    };
+
+    //NOTE: the client needs the connection id to manage its subscriptions through the server functions
+    let opened = stream::once(async move {
+        Event::default()
+            .event("connection")
+            .data(connection_id.to_string())
+    });
+    let frames = stream::unfold(frames, SseStream::next)
+        .map(|frame| Event::default().data(frame.to_string()))
+        .take_until(closed.cancelled_owned());
+
+    //NOTE: EventSource can't answer pings, the keep alive is the heartbeat the client watchdog expects
+    let keep_alive = KeepAlive::new()
+        .interval(Duration::from_millis(HEARTBEAT_INTERVAL_MS.into()))
+        .event(Event::default().data(json!(ServerFrame::Ping).to_string()));
+
+    Sse::new(opened.chain(frames).map(Ok::<_, Infallible>))
+        .keep_alive(keep_alive)
+        .into_response()
+}